@@ -0,0 +1,101 @@
+// Copyright 2024 Dalton Durst and the drc-sim-rust contributors
+// SPDX-License-Identifier: MPL-2.0
+
+//! Compares receiving video one datagram per recv_from against
+//! receiving it in batches with recvmmsg. Each iteration queues a
+//! frame's worth of packets on a loopback socket and then times reading
+//! and parsing them.
+
+use std::{
+    net::UdpSocket,
+    time::{Duration, Instant},
+};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use drc_sim_rust_lib::{incoming_packet_parser::process_video_packet, WUP_VID_PACKET_BUFFER_SIZE};
+
+#[path = "../tests/video_packets/mod.rs"]
+mod video_packets;
+
+use video_packets::VideoPacketBuilder;
+
+/// About the size of a large video frame.
+const FRAME_BYTES: usize = 40_000;
+
+struct Loopback {
+    sender: UdpSocket,
+    receiver: UdpSocket,
+    packets: Vec<Vec<u8>>,
+}
+
+impl Loopback {
+    fn new() -> Loopback {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sender.connect(receiver.local_addr().unwrap()).unwrap();
+        let packets = VideoPacketBuilder::new().packets(&[0x42; FRAME_BYTES], 0);
+        return Loopback {
+            sender,
+            receiver,
+            packets,
+        };
+    }
+
+    fn queue_frame(&self) {
+        for packet in &self.packets {
+            self.sender.send(packet).unwrap();
+        }
+    }
+}
+
+fn recv_from_loop(c: &mut Criterion) {
+    let loopback = Loopback::new();
+    let mut buf = [0u8; WUP_VID_PACKET_BUFFER_SIZE];
+    c.bench_function("recv_from", |b| {
+        b.iter_custom(|iterations| {
+            let mut total = Duration::ZERO;
+            for _ in 0..iterations {
+                loopback.queue_frame();
+                let start = Instant::now();
+                for _ in 0..loopback.packets.len() {
+                    let (size, _) = loopback.receiver.recv_from(&mut buf).unwrap();
+                    process_video_packet(&buf[..size]).unwrap();
+                }
+                total += start.elapsed();
+            }
+            return total;
+        });
+    });
+}
+
+#[cfg(target_os = "linux")]
+fn recvmmsg_batches(c: &mut Criterion) {
+    use drc_sim_rust_lib::sockets::{BatchReceiver, DEFAULT_BATCH_SIZE};
+
+    let loopback = Loopback::new();
+    let mut batch = BatchReceiver::new(DEFAULT_BATCH_SIZE);
+    c.bench_function("recvmmsg", |b| {
+        b.iter_custom(|iterations| {
+            let mut total = Duration::ZERO;
+            for _ in 0..iterations {
+                loopback.queue_frame();
+                let start = Instant::now();
+                let mut received = 0;
+                while received < loopback.packets.len() {
+                    received += batch.recv(&loopback.receiver).unwrap();
+                    for datagram in batch.packets() {
+                        process_video_packet(datagram.data).unwrap();
+                    }
+                }
+                total += start.elapsed();
+            }
+            return total;
+        });
+    });
+}
+
+#[cfg(not(target_os = "linux"))]
+fn recvmmsg_batches(_: &mut Criterion) {}
+
+criterion_group!(benches, recv_from_loop, recvmmsg_batches);
+criterion_main!(benches);
@@ -0,0 +1,314 @@
+// Copyright 2024 Dalton Durst and the drc-sim-rust contributors
+// SPDX-License-Identifier: MPL-2.0
+
+//! Async versions of the GamePad's sockets, for applications running on
+//! tokio. Only built with the "tokio" feature.
+//!
+//! These parse and assemble with the same code as the threaded Session,
+//! so they behave the same; only the waiting is different. Receivers
+//! are streams in the tokio style: call recv_packet or recv_frame in a
+//! loop, or in a select! with everything else the application waits on.
+
+use std::{
+    io,
+    net::{self, SocketAddr},
+    time::Duration,
+};
+
+use log::debug;
+use tokio::{
+    net::UdpSocket,
+    time::{self, Instant, MissedTickBehavior},
+};
+
+use crate::{
+    cmd::{CommandHandler, GamePadState},
+    hid::GamePadInputReport,
+    hid_sender::SharedInput,
+    incoming_packet_parser::{
+        process_audio_packet, process_video_packet, WUPAudioPacket, WUPVideoPacket,
+    },
+    packet_organizer::{AssembledFrame, FrameAssembler},
+    sockets::{self, Datagram, Pad},
+    WUP_VID_PACKET_BUFFER_SIZE,
+};
+
+/// Turns a socket from the sockets module into a tokio one.
+fn from_std(socket: net::UdpSocket) -> io::Result<UdpSocket> {
+    socket.set_nonblocking(true)?;
+    return UdpSocket::from_std(socket);
+}
+
+/// Reads one datagram into buf. With control, it is read with recvmsg
+/// so that it carries its receive timestamp and the kernel's drop count,
+/// if they are enabled on the socket.
+async fn recv_datagram<'a>(
+    socket: &UdpSocket,
+    buf: &'a mut [u8],
+    control: bool,
+) -> io::Result<Datagram<'a>> {
+    #[cfg(target_os = "linux")]
+    if control {
+        // Borrowing buf in the closure would tie the datagram to it, so
+        // it's rebuilt from the size afterwards.
+        let (size, source, received_at, kernel_drops) = socket
+            .async_io(tokio::io::Interest::READABLE, || {
+                let datagram = sockets::recv_datagram(socket, buf)?;
+                return Ok((
+                    datagram.data.len(),
+                    datagram.source,
+                    datagram.received_at,
+                    datagram.kernel_drops,
+                ));
+            })
+            .await?;
+        return Ok(Datagram {
+            data: &buf[..size],
+            source,
+            received_at,
+            kernel_drops,
+        });
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = control;
+    let (size, source) = socket.recv_from(buf).await?;
+    return Ok(Datagram {
+        data: &buf[..size],
+        source,
+        received_at: None,
+        kernel_drops: None,
+    });
+}
+
+/// Receives the console's video and puts it back together into frames.
+pub struct VideoReceiver {
+    socket: UdpSocket,
+    assembler: FrameAssembler,
+    buf: Box<[u8; WUP_VID_PACKET_BUFFER_SIZE]>,
+    control: bool,
+    bad_packets: u64,
+    kernel_drops: u64,
+}
+
+impl VideoReceiver {
+    /// Binds pad's video port on ip.
+    pub fn bind(ip: &str, pad: Pad) -> io::Result<VideoReceiver> {
+        return VideoReceiver::from_std(sockets::get_vid_socket(ip, pad)?, pad);
+    }
+
+    pub fn from_std(socket: net::UdpSocket, pad: Pad) -> io::Result<VideoReceiver> {
+        return Ok(VideoReceiver {
+            socket: from_std(socket)?,
+            assembler: FrameAssembler::for_pad(pad),
+            buf: Box::new([0; WUP_VID_PACKET_BUFFER_SIZE]),
+            control: false,
+            bad_packets: 0,
+            kernel_drops: 0,
+        });
+    }
+
+    /// Stamps every packet with when the kernel received it. Only works
+    /// on Linux.
+    #[cfg(target_os = "linux")]
+    pub fn enable_receive_timestamps(&mut self) -> io::Result<()> {
+        sockets::enable_receive_timestamps(&self.socket)?;
+        self.control = true;
+        return Ok(());
+    }
+
+    /// Counts the packets the kernel drops because the socket's receive
+    /// buffer is full, for kernel_drops. Only works on Linux.
+    #[cfg(target_os = "linux")]
+    pub fn enable_drop_counter(&mut self) -> io::Result<()> {
+        sockets::enable_drop_counter(&self.socket)?;
+        self.control = true;
+        return Ok(());
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        return self.socket.local_addr();
+    }
+
+    /// How many packets couldn't be parsed and were skipped.
+    pub fn bad_packets(&self) -> u64 {
+        return self.bad_packets;
+    }
+
+    /// How many packets the kernel has dropped, as of the last packet
+    /// received. Always 0 unless the drop counter is enabled.
+    pub fn kernel_drops(&self) -> u64 {
+        return self.kernel_drops;
+    }
+
+    pub fn assembler(&self) -> &FrameAssembler {
+        return &self.assembler;
+    }
+
+    /// Waits for the next packet that parses. Packets read this way
+    /// don't go to the assembler, so use either this or recv_frame.
+    pub async fn recv_packet(&mut self) -> io::Result<WUPVideoPacket> {
+        loop {
+            let datagram = recv_datagram(&self.socket, &mut self.buf[..], self.control).await?;
+            if let Some(drops) = datagram.kernel_drops {
+                self.kernel_drops = self.kernel_drops.max(drops as u64);
+            }
+            match process_video_packet(datagram.data) {
+                Some(mut packet) => {
+                    packet.received_at = datagram.received_at;
+                    return Ok(packet);
+                }
+                None => self.bad_packets += 1,
+            }
+        }
+    }
+
+    /// Waits until a frame is complete.
+    pub async fn recv_frame(&mut self) -> io::Result<AssembledFrame> {
+        loop {
+            let packet = self.recv_packet().await?;
+            if let Some(frame) = self.assembler.add_packet(packet) {
+                return Ok(frame);
+            }
+        }
+    }
+}
+
+/// Receives the console's audio.
+pub struct AudioReceiver {
+    socket: UdpSocket,
+    buf: Box<[u8; WUP_VID_PACKET_BUFFER_SIZE]>,
+    control: bool,
+    bad_packets: u64,
+    kernel_drops: u64,
+}
+
+impl AudioReceiver {
+    /// Binds pad's audio port on ip.
+    pub fn bind(ip: &str, pad: Pad) -> io::Result<AudioReceiver> {
+        return AudioReceiver::from_std(sockets::get_aud_socket(ip, pad)?);
+    }
+
+    pub fn from_std(socket: net::UdpSocket) -> io::Result<AudioReceiver> {
+        return Ok(AudioReceiver {
+            socket: from_std(socket)?,
+            buf: Box::new([0; WUP_VID_PACKET_BUFFER_SIZE]),
+            control: false,
+            bad_packets: 0,
+            kernel_drops: 0,
+        });
+    }
+
+    /// Stamps every packet with when the kernel received it. Only works
+    /// on Linux.
+    #[cfg(target_os = "linux")]
+    pub fn enable_receive_timestamps(&mut self) -> io::Result<()> {
+        sockets::enable_receive_timestamps(&self.socket)?;
+        self.control = true;
+        return Ok(());
+    }
+
+    /// Counts the packets the kernel drops because the socket's receive
+    /// buffer is full, for kernel_drops. Only works on Linux.
+    #[cfg(target_os = "linux")]
+    pub fn enable_drop_counter(&mut self) -> io::Result<()> {
+        sockets::enable_drop_counter(&self.socket)?;
+        self.control = true;
+        return Ok(());
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        return self.socket.local_addr();
+    }
+
+    /// How many packets couldn't be parsed and were skipped.
+    pub fn bad_packets(&self) -> u64 {
+        return self.bad_packets;
+    }
+
+    /// How many packets the kernel has dropped, as of the last packet
+    /// received. Always 0 unless the drop counter is enabled.
+    pub fn kernel_drops(&self) -> u64 {
+        return self.kernel_drops;
+    }
+
+    /// Waits for the next packet that parses.
+    pub async fn recv_packet(&mut self) -> io::Result<WUPAudioPacket> {
+        loop {
+            let datagram = recv_datagram(&self.socket, &mut self.buf[..], self.control).await?;
+            if let Some(drops) = datagram.kernel_drops {
+                self.kernel_drops = self.kernel_drops.max(drops as u64);
+            }
+            match process_audio_packet(datagram.data) {
+                Some(mut packet) => {
+                    packet.received_at = datagram.received_at;
+                    return Ok(packet);
+                }
+                None => self.bad_packets += 1,
+            }
+        }
+    }
+}
+
+/// Sends input reports to the console, numbering and timestamping them
+/// as HidSender does.
+pub struct AsyncHidSender {
+    socket: UdpSocket,
+    destination: SocketAddr,
+    start: Instant,
+    sequence: u16,
+}
+
+impl AsyncHidSender {
+    pub fn new(socket: net::UdpSocket, destination: SocketAddr) -> io::Result<AsyncHidSender> {
+        return Ok(AsyncHidSender {
+            socket: from_std(socket)?,
+            destination,
+            start: Instant::now(),
+            sequence: 0,
+        });
+    }
+
+    /// Sends one report. Its sequence number and timestamp are ignored.
+    pub async fn send(&mut self, mut report: GamePadInputReport) -> io::Result<()> {
+        report.sequence = self.sequence;
+        report.timestamp = self.start.elapsed().as_micros() as u32;
+        self.socket
+            .send_to(&report.encode(), self.destination)
+            .await?;
+        self.sequence = self.sequence.wrapping_add(1);
+        return Ok(());
+    }
+
+    /// Sends the contents of input once per interval until sending
+    /// fails. Ticks missed while the runtime was busy are skipped rather
+    /// than sent in a burst.
+    pub async fn run(&mut self, input: SharedInput, interval: Duration) -> io::Result<()> {
+        let mut ticks = time::interval(interval);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            let due = ticks.tick().await;
+            let lateness = due.elapsed();
+            if lateness > interval / 2 {
+                debug!("HID report {} was {:?} late", self.sequence, lateness);
+            }
+            self.send(input.get()).await?;
+        }
+    }
+}
+
+/// Answers the console's commands with handler, as CommandHandler::run
+/// does, until the socket fails.
+pub async fn run_commands<S: GamePadState>(
+    handler: &mut CommandHandler<S>,
+    socket: net::UdpSocket,
+    console: SocketAddr,
+) -> io::Result<()> {
+    let socket = from_std(socket)?;
+    let mut buf = [0u8; WUP_VID_PACKET_BUFFER_SIZE];
+    loop {
+        let size = socket.recv(&mut buf).await?;
+        for reply in handler.handle(&buf[..size]) {
+            socket.send_to(&reply, console).await?;
+        }
+    }
+}
@@ -1,32 +1,275 @@
 // Copyright 2024 Dalton Durst and the drc-sim-rust contributors
 // SPDX-License-Identifier: MPL-2.0
 
-use drc_sim_rust_lib::incoming_packet_parser;
-use drc_sim_rust_lib::sockets;
+// Options:
+//   --second-pad   also run a session for a second GamePad
+//   --http ADDR    decode the first GamePad's video with ffmpeg and
+//                  serve it as MJPEG on ADDR, e.g. 127.0.0.1:8080
+//   --rtp ADDR     send the first GamePad's video as RTP/H.264 to ADDR,
+//                  e.g. 127.0.0.1:5004
+//   --sdp PATH     where to write the SDP file describing the RTP
+//                  stream (default drc-sim-rust.sdp)
+//   --bind ADDR    the address or interface name to listen on, usually
+//                  the interface connected to the console (default
+//                  every interface)
+//   --console IP   the console's address (default 192.168.1.10)
+//   --profile PATH a DeviceProfile to answer the console's commands with
+//   --receive-buffer BYTES
+//                  ask the kernel for this much buffer on the video and
+//                  audio sockets, if packets are being dropped in bursts
+//   --video-overflow POLICY
+//                  what to do with video packets when frames can't be
+//                  assembled fast enough: drop-oldest (default),
+//                  drop-newest or block
+//   --config PATH  read any of bind, console, profile, receive_buffer
+//                  and video_overflow from a file; options on the
+//                  command line win
 
-use drc_sim_rust_lib::WUP_VID_PACKET_BUFFER_SIZE;
-use log::{error, trace};
+use std::{net::IpAddr, thread};
+
+use drc_sim_rust_lib::config::Config;
+use drc_sim_rust_lib::decode::{ProcessDecoder, DEFAULT_JPEG_QUALITY};
+use drc_sim_rust_lib::device_profile::DeviceProfile;
+use drc_sim_rust_lib::feedback::FeedbackEvent;
+use drc_sim_rust_lib::h264::H264Encapsulator;
+use drc_sim_rust_lib::mjpeg_server::MjpegServer;
+use drc_sim_rust_lib::packet_organizer::AssembledFrame;
+use drc_sim_rust_lib::packet_queue::OverflowPolicy;
+use drc_sim_rust_lib::rtp::RtpSink;
+use drc_sim_rust_lib::session::{Session, SessionConfig, SessionEventKind};
+use drc_sim_rust_lib::sockets::{BindAddress, Pad};
+
+use log::{debug, error, info, trace};
+
+/// Prints a message about a bad option or unusable setting and exits,
+/// rather than panicking with a backtrace.
+macro_rules! fail {
+    ($($arg:tt)*) => {{
+        eprintln!($($arg)*);
+        std::process::exit(1)
+    }};
+}
+
+/// Everything that wants the H.264 bitstream rebuilt from a pad's
+/// frames.
+struct VideoOutputs {
+    encapsulator: H264Encapsulator,
+    /// Nothing downstream can use frames until it sees an IDR.
+    have_idr: bool,
+    /// Decodes frames and hands them to an MjpegServer.
+    decoder: Option<ProcessDecoder>,
+    rtp: Option<RtpSink>,
+}
+
+impl VideoOutputs {
+    fn new() -> VideoOutputs {
+        return VideoOutputs {
+            encapsulator: H264Encapsulator::new(),
+            have_idr: false,
+            decoder: None,
+            rtp: None,
+        };
+    }
+
+    fn start_http(&mut self, addr: &str) -> std::io::Result<()> {
+        let server = MjpegServer::bind(addr, DEFAULT_JPEG_QUALITY)?;
+        self.decoder = Some(ProcessDecoder::ffmpeg(move |frame| server.publish(&frame))?);
+        return Ok(());
+    }
+
+    fn start_rtp(&mut self, addr: &str, sdp_path: &str) -> std::io::Result<()> {
+        let destination = match addr.parse() {
+            Ok(destination) => destination,
+            Err(err) => fail!("Bad RTP address {addr}: {err}"),
+        };
+        let sink = RtpSink::new(destination)?;
+        sink.write_sdp(sdp_path)?;
+        info!("Sending RTP to {destination}, SDP written to {sdp_path}");
+        self.rtp = Some(sink);
+        return Ok(());
+    }
+
+    fn is_empty(&self) -> bool {
+        return self.decoder.is_none() && self.rtp.is_none();
+    }
+
+    fn handle(&mut self, frame: &AssembledFrame) -> std::io::Result<()> {
+        if !self.have_idr {
+            if !frame.is_idr() {
+                return Ok(());
+            }
+            info!("Got the first IDR, starting video output");
+            self.have_idr = true;
+        }
+        let access_unit = self.encapsulator.encapsulate(frame);
+        if let Some(decoder) = &mut self.decoder {
+            decoder.decode(&access_unit)?;
+        }
+        if let Some(rtp) = &mut self.rtp {
+            rtp.send(&access_unit, frame.timestamp)?;
+        }
+        return Ok(());
+    }
+}
+
+fn run_session(config: SessionConfig, mut outputs: Option<VideoOutputs>) -> std::io::Result<()> {
+    let pad = config.pad;
+    let mut session = Session::start(config)?;
+
+    for event in session.events().iter() {
+        match event.kind {
+            SessionEventKind::Frame(frame) => {
+                debug!("{pad}: Processed frame {}", frame.timestamp);
+                if let Some(outputs) = &mut outputs {
+                    outputs.handle(&frame)?;
+                }
+            }
+            SessionEventKind::Stalled {
+                channel,
+                silent_for,
+            } => {
+                let stats = session.stats();
+                info!(
+                    "{pad}: no {channel:?} for {silent_for:?}; so far {} frames, {} dropped, {} video packets dropped by the kernel and {} in the queue",
+                    stats.frames,
+                    stats.dropped_frames,
+                    stats.video_kernel_drops,
+                    stats.video_queue.dropped_oldest + stats.video_queue.dropped_newest
+                );
+            }
+            SessionEventKind::Feedback(FeedbackEvent::Rumble { on, .. }) => {
+                info!("{pad}: Rumble {}", if on { "on" } else { "off" });
+            }
+            SessionEventKind::Feedback(FeedbackEvent::RumblePattern { pattern }) => {
+                let steps: String = pattern
+                    .iter()
+                    .map(|&on| if on { '#' } else { '.' })
+                    .collect();
+                info!("{pad}: Rumble pattern {steps}");
+            }
+            SessionEventKind::Error { channel, error } => {
+                error!("{pad}: {channel:?} channel failed: {error}");
+                break;
+            }
+            other => trace!("{pad}: {other:?}"),
+        }
+    }
+    return session.shutdown();
+}
 
 fn main() -> std::io::Result<()> {
     simple_logger::init_with_env().unwrap();
     {
-        //TODO: Bind to the appropriate IP address (It's usually
-        //192.168.1.11 but could be different)
-        let video_socket = sockets::get_vid_socket("0.0.0.0")?;
-
-        loop {
-            let mut buf = [0u8; WUP_VID_PACKET_BUFFER_SIZE];
-            video_socket.recv_from(&mut buf)?;
-
-            let packet = match incoming_packet_parser::process_video_packet(&buf) {
-                None => {
-                    error!("Didn't get a packet back from process");
-                    continue;
-                }
-                Some(val) => val,
-            };
+        let mut pads: &[Pad] = &[Pad::First];
+        let mut http_addr: Option<String> = None;
+        let mut rtp_addr: Option<String> = None;
+        let mut sdp_path = "drc-sim-rust.sdp".to_string();
+        let mut bind: Option<BindAddress> = None;
+        let mut console_ip: Option<IpAddr> = None;
+        let mut profile_path: Option<String> = None;
+        let mut receive_buffer_size: Option<usize> = None;
+        let mut video_overflow: Option<OverflowPolicy> = None;
+        let mut config = Config::default();
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--second-pad" => pads = &Pad::ALL,
+                "--http" => match args.next() {
+                    Some(addr) => http_addr = Some(addr),
+                    None => fail!("--http needs an address to listen on"),
+                },
+                "--rtp" => match args.next() {
+                    Some(addr) => rtp_addr = Some(addr),
+                    None => fail!("--rtp needs an address to send to"),
+                },
+                "--sdp" => match args.next() {
+                    Some(path) => sdp_path = path,
+                    None => fail!("--sdp needs a path"),
+                },
+                "--bind" => match args.next() {
+                    Some(addr) => bind = Some(BindAddress::parse(&addr)),
+                    None => fail!("--bind needs an address or interface name"),
+                },
+                "--console" => match args.next().map(|addr| addr.parse()) {
+                    Some(Ok(ip)) => console_ip = Some(ip),
+                    Some(Err(err)) => fail!("Bad console address: {err}"),
+                    None => fail!("--console needs an IP address"),
+                },
+                "--profile" => match args.next() {
+                    Some(path) => profile_path = Some(path),
+                    None => fail!("--profile needs a path"),
+                },
+                "--receive-buffer" => match args.next().map(|bytes| bytes.parse()) {
+                    Some(Ok(bytes)) => receive_buffer_size = Some(bytes),
+                    Some(Err(err)) => fail!("Bad receive buffer size: {err}"),
+                    None => fail!("--receive-buffer needs a size in bytes"),
+                },
+                "--video-overflow" => match args.next().map(|name| OverflowPolicy::parse(&name)) {
+                    Some(Some(policy)) => video_overflow = Some(policy),
+                    Some(None) => fail!("--video-overflow is drop-oldest, drop-newest or block"),
+                    None => fail!("--video-overflow needs a policy"),
+                },
+                "--config" => match args.next().map(Config::load) {
+                    Some(Ok(loaded)) => config = loaded,
+                    Some(Err(err)) => fail!("Can't read the config file: {err}"),
+                    None => fail!("--config needs a path"),
+                },
+                other => fail!("Unknown argument {other}"),
+            }
+        }
+
+        let bind = bind.or(config.bind).unwrap_or_default();
+        let bind_ip = match bind.resolve() {
+            Ok(ip) => ip,
+            Err(err) => fail!("Can't listen on {bind}: {err}"),
+        };
+        info!("Listening on {bind_ip}");
+        let profile = match profile_path.map(Into::into).or(config.profile) {
+            Some(path) => match DeviceProfile::load(&path) {
+                Ok(profile) => profile,
+                Err(err) => fail!("Can't read the device profile: {err}"),
+            },
+            None => DeviceProfile::default(),
+        };
+        let session_config = |pad| SessionConfig {
+            pad,
+            bind_ip: bind_ip.to_string(),
+            console_ip: console_ip
+                .or(config.console_ip)
+                .unwrap_or(SessionConfig::default().console_ip),
+            profile: profile.clone(),
+            receive_buffer_size: receive_buffer_size.or(config.receive_buffer_size),
+            video_overflow: video_overflow.or(config.video_overflow).unwrap_or_default(),
+            ..SessionConfig::default()
+        };
+
+        let mut outputs = VideoOutputs::new();
+        if let Some(addr) = http_addr {
+            outputs.start_http(&addr)?;
+        }
+        if let Some(addr) = rtp_addr {
+            outputs.start_rtp(&addr, &sdp_path)?;
+        }
+        let mut outputs = if outputs.is_empty() {
+            None
+        } else {
+            Some(outputs)
+        };
 
-            trace!("{packet:?}");
+        let receivers: Vec<_> = pads
+            .iter()
+            .map(|pad| {
+                let pad = *pad;
+                let outputs = match pad {
+                    Pad::First => outputs.take(),
+                    Pad::Second => None,
+                };
+                let config = session_config(pad);
+                thread::spawn(move || run_session(config, outputs))
+            })
+            .collect();
+        for receiver in receivers {
+            receiver.join().unwrap()?;
         }
         Ok(())
     }
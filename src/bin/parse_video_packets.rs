@@ -1,34 +1,34 @@
 // Copyright 2024 Dalton Durst and the drc-sim-rust contributors
 // SPDX-License-Identifier: MPL-2.0
 
+// This program reads the video_packets file written by
+// record_video_packets and assembles the packets into frames. Pass
+// --validate to also check the H.264 bitstream built from each frame.
+
 use std::{
-    cmp::Ordering,
-    collections::HashMap,
     fs::File,
     io::{BufReader, Read},
 };
 
 use drc_sim_rust_lib::{
-    incoming_packet_parser::{self, u32_paws_compare},
-    packet_organizer::{self, FrameAccumulator, IncompleteReason},
-    STALE_FRAME_THRESHOLD, WUP_VID_PACKET_BUFFER_SIZE,
+    h264::H264Encapsulator, h264_validator::BitstreamValidator, incoming_packet_parser,
+    packet_organizer::FrameAssembler, video_stats::VideoStats, WUP_VID_PACKET_BUFFER_SIZE,
 };
-use log::{debug, error, info, trace};
+use log::{debug, error, info, trace, warn};
 
 fn main() -> std::io::Result<()> {
     simple_logger::init_with_env().unwrap();
     {
+        let validate = std::env::args().any(|arg| arg == "--validate");
+
         let mut file_reader = BufReader::new(File::open("video_packets")?);
 
         let mut i = 0;
-        let mut completed_frames = 0;
-        let mut dropped_frames = 0;
-
-        // The newest timestamp that we have seen so far.
-        let mut high_water_mark: Option<u32> = None;
+        let mut assembler = FrameAssembler::new();
+        let mut encapsulator = H264Encapsulator::new();
+        let mut validator = BitstreamValidator::new();
+        let mut stats = VideoStats::default();
 
-        let mut frame_accumulators: HashMap<u32, packet_organizer::FrameAccumulator> =
-            HashMap::new();
         loop {
             i += 1;
             let mut buf = [0u8; WUP_VID_PACKET_BUFFER_SIZE];
@@ -58,77 +58,38 @@ fn main() -> std::io::Result<()> {
 
             trace!("Packet {i}: {packet:?}");
 
-            let timestamp = packet.timestamp;
-
-            if high_water_mark == None {
-                high_water_mark = Some(timestamp);
-            }
-
-            if u32_paws_compare(timestamp, high_water_mark.unwrap()) == Some(Ordering::Greater) {
-                debug!("New high water mark is {}", timestamp);
-                high_water_mark = Some(timestamp);
-            }
-
-            let mut accumulators_to_remove: Vec<u32> = Vec::new();
-            if frame_accumulators.len() > 1 {
-                for accu_timestamp in frame_accumulators.keys().cloned() {
-                    // This is "timestamp is less than our lowest acceptable timestamp"
-                    if u32_paws_compare(
-                        accu_timestamp,
-                        high_water_mark.unwrap() - STALE_FRAME_THRESHOLD,
-                    ) == Some(Ordering::Less)
-                    {
-                        accumulators_to_remove.push(accu_timestamp);
-                    }
+            let frame = match assembler.add_packet(packet) {
+                None => continue,
+                Some(frame) => frame,
+            };
+            info!("Processed frame {:?}", frame.timestamp);
+            debug!("{:?}", frame.packets);
+            let frame_stats = stats.record_frame(&frame);
+            debug!("{:?}", frame_stats);
+
+            if validate {
+                let access_unit = encapsulator.encapsulate(&frame);
+                let validation = validator.validate(frame.timestamp, frame.is_idr(), &access_unit);
+                for problem in &validation.problems {
+                    warn!("Frame {} bitstream problem: {}", frame.timestamp, problem);
                 }
             }
-            // I tried to avoid this separate loop by cloning the
-            // HashMap's keys and performing the removal inside the
-            // above loop, but the compiler always complained that I was
-            // trying to mutate the HashMap after requesting an
-            // immutable borrow of it at the declaration of the loop.
-            for to_remove in accumulators_to_remove {
-                error!("Dropping {} as it is too old", to_remove);
-                dropped_frames += 1;
-                frame_accumulators.remove(&to_remove);
-            }
-
-            let frame_accumulator = frame_accumulators
-                .entry(timestamp)
-                .or_insert(FrameAccumulator::new(timestamp));
-
-            let _ = frame_accumulator.add_packet(packet);
-
-            let frame_dgrams = match frame_accumulator.complete() {
-                Ok(data) => {
-                    completed_frames += 1;
-                    data
-                }
-                Err(err) => {
-                    match err.kind {
-                        packet_organizer::IncompleteReasonKind::TooManyPackets => {
-                            error!("Frame accumulator has too many packets, dropping.");
-                            frame_accumulators.remove(&timestamp);
-                            dropped_frames += 1;
-                            continue;
-                        }
-                        // All other reasons will be handled by us
-                        // dropping old accumulators.
-                        _ => continue,
-                    }
-                }
-            };
-            info!("Processed frame {:?}", frame_accumulator.timestamp());
-            debug!("{:?}", frame_dgrams);
-
-            frame_accumulators.remove(&timestamp);
         }
         info!(
-            "{:?} frames were incomplete at time of exiting, completed {} dropped {}.",
-            frame_accumulators.len(),
-            completed_frames,
-            dropped_frames,
+            "{:?} frames were incomplete at time of exiting, completed {} dropped {}, rejected {} packets.",
+            assembler.incomplete_frames(),
+            assembler.completed_frames(),
+            assembler.dropped_frames(),
+            assembler.rejected_packets(),
         );
+        info!("Video statistics:\n{}", stats.summary());
+        if validate {
+            info!(
+                "Validated {} frames, {} had bitstream problems.",
+                validator.frames_checked(),
+                validator.bad_frames(),
+            );
+        }
     }
     Ok(())
 }
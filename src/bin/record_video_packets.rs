@@ -2,34 +2,127 @@
 // SPDX-License-Identifier: MPL-2.0
 
 // This program records ten thousand packets to a file called
-// video_packets in your current directory.
+// video_packets in your current directory. Packets are read on a
+// thread of their own and written from a queue, so a slow disk doesn't
+// hold up the socket.
+//
+// Options:
+//   --bind ADDR    the address or interface name to listen on (default
+//                  every interface)
+//   --config PATH  read the bind address from a config file
 
-use drc_sim_rust_lib::{sockets, WUP_VID_PACKET_BUFFER_SIZE};
+use drc_sim_rust_lib::{
+    config::Config,
+    packet_queue::{OverflowPolicy, PacketQueue},
+    session::{StallDetector, DEFAULT_READ_TIMEOUT, DEFAULT_STALL_TIMEOUT},
+    sockets::{self, BindAddress, Pad},
+    WUP_VID_PACKET_BUFFER_SIZE,
+};
 
 use std::{
     fs::File,
-    io::{BufWriter, Write},
+    io::{BufWriter, ErrorKind, Write},
+    net::UdpSocket,
+    thread,
+    time::Instant,
 };
 
-use log::info;
+use log::{info, warn};
+
+/// Prints a message about a bad option or unusable setting and exits,
+/// rather than panicking with a backtrace.
+macro_rules! fail {
+    ($($arg:tt)*) => {{
+        eprintln!($($arg)*);
+        std::process::exit(1)
+    }};
+}
+
+/// How many packets can wait to be written.
+const QUEUE_CAPACITY: usize = 4096;
+
+/// Reads packets into queue until it is closed.
+fn receive(video_socket: UdpSocket, queue: PacketQueue<Vec<u8>>) -> std::io::Result<()> {
+    video_socket.set_read_timeout(Some(DEFAULT_READ_TIMEOUT))?;
+    let mut stalls = StallDetector::new(DEFAULT_STALL_TIMEOUT);
+    while !queue.is_closed() {
+        let mut buf = vec![0u8; WUP_VID_PACKET_BUFFER_SIZE];
+        match video_socket.recv_from(&mut buf) {
+            Ok(_) => {}
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                if let Some(silent_for) = stalls.check(Instant::now()) {
+                    warn!("No video for {silent_for:?}, waiting for the console");
+                }
+                continue;
+            }
+            Err(err) => {
+                queue.close();
+                return Err(err);
+            }
+        }
+        if let Some(stalled_for) = stalls.on_packet(Instant::now()) {
+            info!("Video resumed after {stalled_for:?}");
+        }
+        queue.push(buf);
+    }
+    Ok(())
+}
 
 fn main() -> std::io::Result<()> {
     simple_logger::init_with_env().unwrap();
     {
-        //TODO: Bind to the appropriate IP address (It's usually
-        //192.168.1.11 but could be different)
-        let video_socket = sockets::get_vid_socket("0.0.0.0")?;
+        let mut bind: Option<BindAddress> = None;
+        let mut config = Config::default();
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--bind" => match args.next() {
+                    Some(addr) => bind = Some(BindAddress::parse(&addr)),
+                    None => fail!("--bind needs an address or interface name"),
+                },
+                "--config" => match args.next().map(Config::load) {
+                    Some(Ok(loaded)) => config = loaded,
+                    Some(Err(err)) => fail!("Can't read the config file: {err}"),
+                    None => fail!("--config needs a path"),
+                },
+                other => fail!("Unknown argument {other}"),
+            }
+        }
+        let bind = bind.or(config.bind).unwrap_or_default();
+        let bind_ip = match bind.resolve() {
+            Ok(ip) => ip,
+            Err(err) => fail!("Can't listen on {bind}: {err}"),
+        };
+        let video_socket = sockets::get_vid_socket(&bind_ip.to_string(), Pad::First)?;
 
         let mut file_writer = BufWriter::new(File::create_new("video_packets")?);
 
-        for n in 0..10000 {
-            let mut buf = [0u8; WUP_VID_PACKET_BUFFER_SIZE];
-            video_socket.recv_from(&mut buf)?;
+        // Every packet matters in a recording, so the receiver waits for
+        // the writer rather than dropping any.
+        let queue = PacketQueue::new(QUEUE_CAPACITY, OverflowPolicy::Block);
+        let receiver_queue = queue.clone();
+        let receiver = thread::spawn(move || receive(video_socket, receiver_queue));
 
+        let mut n = 0;
+        while n < 10000 {
+            let Some(buf) = queue.pop_timeout(DEFAULT_READ_TIMEOUT) else {
+                if queue.is_closed() {
+                    break;
+                }
+                continue;
+            };
             let written = file_writer.write(&buf)?;
             assert!(written == WUP_VID_PACKET_BUFFER_SIZE);
             info!("{}", n);
+            n += 1;
         }
+        queue.close();
+        receiver.join().unwrap()?;
+        let stats = queue.stats();
+        info!(
+            "Recorded {n} packets; the receiver waited on the writer {} times, with up to {} packets queued",
+            stats.blocked, stats.max_len
+        );
         Ok(())
     }
 }
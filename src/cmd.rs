@@ -0,0 +1,508 @@
+// Copyright 2024 Dalton Durst and the drc-sim-rust contributors
+// SPDX-License-Identifier: MPL-2.0
+
+//! The command channel, where the console asks the GamePad questions
+//! and tells it to change settings.
+//!
+//! Every packet starts with an 8-byte little-endian header: packet
+//! type, command ID, payload size and sequence ID. Every request and
+//! response is acknowledged with a header-only packet carrying the same
+//! command and sequence IDs, and requests are answered with a response
+//! that reuses the request's sequence ID.
+//!
+//! The commands we know of, all from the original drc-sim:
+//!  - 0, generic commands. The payload starts with a 12-byte big-endian
+//!    header naming a service (id_primary) and method (id_secondary).
+//!  - 1, UVC/UIC commands with a 48-byte payload.
+//!  - 2, set the time.
+//!
+//! What most generic and UVC/UIC commands mean (brightness, volume,
+//! power and so on) is not known yet, so the GamePadState answering
+//! them sees the raw payloads.
+
+use core::fmt;
+use std::{
+    io,
+    net::{SocketAddr, UdpSocket},
+};
+
+use log::{debug, info, warn};
+
+use crate::{device_profile::DeviceProfile, hex};
+
+pub const CMD_HEADER_SIZE: usize = 8;
+pub const CMD0_HEADER_SIZE: usize = 12;
+pub const CMD1_PAYLOAD_SIZE: usize = 48;
+pub const CMD2_PAYLOAD_SIZE: usize = 8;
+
+/// The largest packet we expect on the command port.
+const CMD_BUFFER_SIZE: usize = 2048;
+
+/// What drc-sim answers every UVC/UIC command with.
+pub const DEFAULT_UVC_UIC_RESPONSE: [u8; 16] = [
+    0x00, 0x16, 0x00, 0x19, 0x9e, 0x00, 0x00, 0x00, 0x40, 0x00, 0x40, 0x00, 0x00, 0x00, 0x01, 0xff,
+];
+
+/// The generic command error code meaning success.
+pub const CMD0_OK: u16 = 0;
+
+pub struct CmdError {
+    pub kind: CmdErrorKind,
+    pub text: String,
+}
+
+impl CmdError {
+    fn new(kind: CmdErrorKind, text: String) -> CmdError {
+        return CmdError { kind, text };
+    }
+    fn format_error(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}: {}", self.kind, self.text)
+    }
+}
+
+impl fmt::Display for CmdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return self.format_error(f);
+    }
+}
+
+impl fmt::Debug for CmdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return self.format_error(f);
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CmdErrorKind {
+    /// The packet is shorter than its headers.
+    TooShort,
+    /// The packet type isn't one of the four we know.
+    BadPacketType,
+    /// The payload is a different size than the header says.
+    SizeMismatch,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacketType {
+    Request = 0,
+    RequestAck = 1,
+    Response = 2,
+    ResponseAck = 3,
+}
+
+impl PacketType {
+    pub fn from_u16(value: u16) -> Option<PacketType> {
+        return match value {
+            0 => Some(PacketType::Request),
+            1 => Some(PacketType::RequestAck),
+            2 => Some(PacketType::Response),
+            3 => Some(PacketType::ResponseAck),
+            _ => None,
+        };
+    }
+
+    /// The type of the packet that acknowledges this one, if it needs
+    /// one.
+    pub fn ack(&self) -> Option<PacketType> {
+        return match self {
+            PacketType::Request => Some(PacketType::RequestAck),
+            PacketType::Response => Some(PacketType::ResponseAck),
+            _ => None,
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CmdHeader {
+    pub packet_type: PacketType,
+    pub cmd_id: u16,
+    pub payload_size: u16,
+    pub seq_id: u16,
+}
+
+impl CmdHeader {
+    pub fn parse(packet: &[u8]) -> Result<CmdHeader, CmdError> {
+        if packet.len() < CMD_HEADER_SIZE {
+            return Err(CmdError::new(
+                CmdErrorKind::TooShort,
+                format!("{} bytes is too short for a command header", packet.len()),
+            ));
+        }
+        let word = |i: usize| u16::from_le_bytes([packet[i], packet[i + 1]]);
+        let packet_type = match PacketType::from_u16(word(0)) {
+            Some(packet_type) => packet_type,
+            None => {
+                return Err(CmdError::new(
+                    CmdErrorKind::BadPacketType,
+                    format!("unknown packet type {}", word(0)),
+                ))
+            }
+        };
+        return Ok(CmdHeader {
+            packet_type,
+            cmd_id: word(2),
+            payload_size: word(4),
+            seq_id: word(6),
+        });
+    }
+
+    pub fn encode(&self) -> [u8; CMD_HEADER_SIZE] {
+        let mut header = [0u8; CMD_HEADER_SIZE];
+        header[0..2].copy_from_slice(&(self.packet_type as u16).to_le_bytes());
+        header[2..4].copy_from_slice(&self.cmd_id.to_le_bytes());
+        header[4..6].copy_from_slice(&self.payload_size.to_le_bytes());
+        header[6..8].copy_from_slice(&self.seq_id.to_le_bytes());
+        return header;
+    }
+}
+
+/// The header at the start of a generic command's payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cmd0Header {
+    pub magic: u8,
+    pub unknown: [u8; 4],
+    pub flags: u8,
+    pub id_primary: u8,
+    pub id_secondary: u8,
+    pub error_code: u16,
+    pub payload_size: u16,
+}
+
+impl Cmd0Header {
+    pub fn parse(data: &[u8]) -> Result<Cmd0Header, CmdError> {
+        if data.len() < CMD0_HEADER_SIZE {
+            return Err(CmdError::new(
+                CmdErrorKind::TooShort,
+                format!("{} bytes is too short for a generic command", data.len()),
+            ));
+        }
+        return Ok(Cmd0Header {
+            magic: data[0],
+            unknown: [data[1], data[2], data[3], data[4]],
+            flags: data[5],
+            id_primary: data[6],
+            id_secondary: data[7],
+            error_code: u16::from_be_bytes([data[8], data[9]]),
+            payload_size: u16::from_be_bytes([data[10], data[11]]),
+        });
+    }
+
+    pub fn encode(&self) -> [u8; CMD0_HEADER_SIZE] {
+        let mut header = [0u8; CMD0_HEADER_SIZE];
+        header[0] = self.magic;
+        header[1..5].copy_from_slice(&self.unknown);
+        header[5] = self.flags;
+        header[6] = self.id_primary;
+        header[7] = self.id_secondary;
+        header[8..10].copy_from_slice(&self.error_code.to_be_bytes());
+        header[10..12].copy_from_slice(&self.payload_size.to_be_bytes());
+        return header;
+    }
+
+    /// The header for a response to this command, as drc-sim builds
+    /// it.
+    pub fn response(&self, error_code: u16, payload_size: u16) -> Cmd0Header {
+        return Cmd0Header {
+            flags: ((self.flags >> 3) & 0xfc) | 1,
+            error_code,
+            payload_size,
+            ..*self
+        };
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Generic {
+        header: Cmd0Header,
+        payload: Vec<u8>,
+    },
+    UvcUic {
+        payload: Vec<u8>,
+    },
+    /// The console's clock: a Julian day number and seconds.
+    SetTime {
+        jdn_base: u16,
+        seconds: u32,
+    },
+    /// A command ID we don't know, or a known one with an unexpected
+    /// size.
+    Unknown {
+        cmd_id: u16,
+        payload: Vec<u8>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CmdPacket {
+    pub header: CmdHeader,
+    pub command: Command,
+}
+
+impl CmdPacket {
+    pub fn parse(packet: &[u8]) -> Result<CmdPacket, CmdError> {
+        let header = CmdHeader::parse(packet)?;
+        let payload = &packet[CMD_HEADER_SIZE..];
+        if payload.len() != header.payload_size as usize {
+            return Err(CmdError::new(
+                CmdErrorKind::SizeMismatch,
+                format!(
+                    "header says {} bytes of payload, packet has {}",
+                    header.payload_size,
+                    payload.len()
+                ),
+            ));
+        }
+        let command = match (header.cmd_id, payload.len()) {
+            (0, size) if size >= CMD0_HEADER_SIZE => Command::Generic {
+                header: Cmd0Header::parse(payload)?,
+                payload: payload[CMD0_HEADER_SIZE..].to_vec(),
+            },
+            (1, CMD1_PAYLOAD_SIZE) => Command::UvcUic {
+                payload: payload.to_vec(),
+            },
+            (2, CMD2_PAYLOAD_SIZE) => Command::SetTime {
+                jdn_base: u16::from_le_bytes([payload[0], payload[1]]),
+                seconds: u32::from_le_bytes(payload[4..8].try_into().unwrap()),
+            },
+            (cmd_id, _) => Command::Unknown {
+                cmd_id,
+                payload: payload.to_vec(),
+            },
+        };
+        return Ok(CmdPacket { header, command });
+    }
+}
+
+/// The answer to a generic command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GenericReply {
+    pub error_code: u16,
+    pub data: Vec<u8>,
+}
+
+/// Whatever is pretending to be the GamePad's hardware, answering the
+/// console's commands.
+pub trait GamePadState: Send {
+    /// Answers a generic command. Returning None sends no response,
+    /// which is what drc-sim does for commands it doesn't know.
+    fn generic_command(
+        &mut self,
+        id_primary: u8,
+        id_secondary: u8,
+        payload: &[u8],
+    ) -> Option<GenericReply>;
+
+    /// Answers a UVC/UIC command.
+    fn uvc_uic_command(&mut self, payload: &[u8]) -> Option<Vec<u8>>;
+
+    /// Called when the console sets the time.
+    fn set_time(&mut self, jdn_base: u16, seconds: u32);
+}
+
+/// A GamePadState that answers from a DeviceProfile, the way drc-sim
+/// answers from its dumps.
+#[derive(Debug, Clone, Default)]
+pub struct BasicGamePadState {
+    pub profile: DeviceProfile,
+    pub time: Option<(u16, u32)>,
+}
+
+impl BasicGamePadState {
+    pub fn new(profile: DeviceProfile) -> BasicGamePadState {
+        return BasicGamePadState {
+            profile,
+            time: None,
+        };
+    }
+}
+
+impl GamePadState for BasicGamePadState {
+    fn generic_command(
+        &mut self,
+        id_primary: u8,
+        id_secondary: u8,
+        _payload: &[u8],
+    ) -> Option<GenericReply> {
+        return self.profile.generic_reply(id_primary, id_secondary);
+    }
+
+    fn uvc_uic_command(&mut self, _payload: &[u8]) -> Option<Vec<u8>> {
+        return Some(self.profile.uvc_uic_response.clone());
+    }
+
+    fn set_time(&mut self, jdn_base: u16, seconds: u32) {
+        self.time = Some((jdn_base, seconds));
+    }
+}
+
+/// Counts of what has gone through a CommandHandler.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandStats {
+    pub requests: u64,
+    /// Requests that repeated the previous request's sequence ID, which
+    /// happens when the console misses our ack or response.
+    pub retransmissions: u64,
+    pub responses_sent: u64,
+    /// Requests the GamePadState had no answer for.
+    pub unanswered: u64,
+    pub bad_packets: u64,
+}
+
+/// Answers command packets using a GamePadState.
+pub struct CommandHandler<S: GamePadState> {
+    state: S,
+    /// The command ID, sequence ID and response of the last request, so
+    /// that a retransmitted request gets the same answer.
+    last_request: Option<(u16, u16, Option<Vec<u8>>)>,
+    stats: CommandStats,
+}
+
+impl<S: GamePadState> CommandHandler<S> {
+    pub fn new(state: S) -> CommandHandler<S> {
+        return CommandHandler {
+            state,
+            last_request: None,
+            stats: CommandStats::default(),
+        };
+    }
+
+    pub fn state(&self) -> &S {
+        return &self.state;
+    }
+
+    pub fn state_mut(&mut self) -> &mut S {
+        return &mut self.state;
+    }
+
+    pub fn stats(&self) -> &CommandStats {
+        return &self.stats;
+    }
+
+    /// Handles one packet from the console and returns the packets to
+    /// send back, in order.
+    pub fn handle(&mut self, packet: &[u8]) -> Vec<Vec<u8>> {
+        let packet = match CmdPacket::parse(packet) {
+            Ok(packet) => packet,
+            Err(err) => {
+                self.stats.bad_packets += 1;
+                warn!("Bad command packet ({}): {}", err, hex(packet));
+                return Vec::new();
+            }
+        };
+        let header = packet.header;
+        let mut replies = Vec::new();
+        if let Some(ack_type) = header.packet_type.ack() {
+            let ack = CmdHeader {
+                packet_type: ack_type,
+                payload_size: 0,
+                ..header
+            };
+            replies.push(ack.encode().to_vec());
+        }
+        if header.packet_type != PacketType::Request {
+            // We never send requests, so there's nothing to match
+            // responses or acks to.
+            debug!("Ignoring {:?}", header);
+            return replies;
+        }
+
+        self.stats.requests += 1;
+        if let Some((cmd_id, seq_id, response)) = &self.last_request {
+            if *cmd_id == header.cmd_id && *seq_id == header.seq_id {
+                self.stats.retransmissions += 1;
+                debug!("Console repeated request {}", seq_id);
+                replies.extend(response.clone());
+                return replies;
+            }
+        }
+
+        let response = self.respond(&packet);
+        if response.is_some() {
+            self.stats.responses_sent += 1;
+        } else {
+            self.stats.unanswered += 1;
+        }
+        replies.extend(response.clone());
+        self.last_request = Some((header.cmd_id, header.seq_id, response));
+        return replies;
+    }
+
+    fn respond(&mut self, packet: &CmdPacket) -> Option<Vec<u8>> {
+        let payload = match &packet.command {
+            Command::Generic { header, payload } => {
+                debug!(
+                    "Generic command {}/{}: {}",
+                    header.id_primary,
+                    header.id_secondary,
+                    hex(payload)
+                );
+                let reply =
+                    self.state
+                        .generic_command(header.id_primary, header.id_secondary, payload);
+                let reply = match reply {
+                    Some(reply) => reply,
+                    None => {
+                        info!(
+                            "No answer for generic command {}/{}: {}",
+                            header.id_primary,
+                            header.id_secondary,
+                            hex(payload)
+                        );
+                        return None;
+                    }
+                };
+                let mut data = header
+                    .response(reply.error_code, reply_size(reply.data.len())?)
+                    .encode()
+                    .to_vec();
+                data.extend(reply.data);
+                data
+            }
+            Command::UvcUic { payload } => {
+                debug!("UVC/UIC command: {}", hex(payload));
+                self.state.uvc_uic_command(payload)?
+            }
+            Command::SetTime { jdn_base, seconds } => {
+                debug!("Time base {:04x} seconds {:08x}", jdn_base, seconds);
+                self.state.set_time(*jdn_base, *seconds);
+                Vec::new()
+            }
+            Command::Unknown { cmd_id, payload } => {
+                info!("Unknown command {}: {}", cmd_id, hex(payload));
+                return None;
+            }
+        };
+        let header = CmdHeader {
+            packet_type: PacketType::Response,
+            payload_size: reply_size(payload.len())?,
+            ..packet.header
+        };
+        let mut response = header.encode().to_vec();
+        response.extend(payload);
+        return Some(response);
+    }
+
+    /// Answers packets from socket, sending replies to console, until
+    /// the socket fails.
+    pub fn run(&mut self, socket: &UdpSocket, console: SocketAddr) -> io::Result<()> {
+        let mut buf = [0u8; CMD_BUFFER_SIZE];
+        loop {
+            let size = socket.recv(&mut buf)?;
+            for reply in self.handle(&buf[..size]) {
+                socket.send_to(&reply, console)?;
+            }
+        }
+    }
+}
+
+/// The length of a reply in a header's u16 size field. None, after
+/// logging, if it is too long to describe, so that we don't send a
+/// header that disagrees with its payload.
+fn reply_size(len: usize) -> Option<u16> {
+    let size = u16::try_from(len).ok();
+    if size.is_none() {
+        warn!("Not sending a {} byte reply, too long for its header", len);
+    }
+    return size;
+}
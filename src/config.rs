@@ -0,0 +1,136 @@
+// Copyright 2024 Dalton Durst and the drc-sim-rust contributors
+// SPDX-License-Identifier: MPL-2.0
+
+//! Settings the programs read from a file, so that they don't have to
+//! be given on the command line every time.
+//!
+//! The file has one setting per line, and `#` starts a comment.
+//!
+//! ```text
+//! bind wlan1             # an interface name or an IP address
+//! console 192.168.1.10   # the console's address
+//! profile pad.profile    # a DeviceProfile, relative to this file
+//! receive_buffer 4194304 # bytes of kernel buffer for video and audio
+//! video_overflow block   # or drop-oldest or drop-newest
+//! ```
+
+use core::fmt;
+use std::{
+    fs,
+    net::IpAddr,
+    path::{Path, PathBuf},
+};
+
+use crate::{packet_queue::OverflowPolicy, sockets::BindAddress};
+
+pub struct ConfigError {
+    pub kind: ConfigErrorKind,
+    pub text: String,
+}
+
+impl ConfigError {
+    fn new(kind: ConfigErrorKind, text: String) -> ConfigError {
+        return ConfigError { kind, text };
+    }
+    fn format_error(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}: {}", self.kind, self.text)
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return self.format_error(f);
+    }
+}
+
+impl fmt::Debug for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return self.format_error(f);
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ConfigErrorKind {
+    Io,
+    Syntax,
+}
+
+/// Settings from a config file. Anything the file doesn't set is None,
+/// so that command line options and defaults can fill it in.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    pub bind: Option<BindAddress>,
+    pub console_ip: Option<IpAddr>,
+    pub profile: Option<PathBuf>,
+    pub receive_buffer_size: Option<usize>,
+    /// What to do with video packets when the assembler falls behind.
+    pub video_overflow: Option<OverflowPolicy>,
+}
+
+impl Config {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Config, ConfigError> {
+        let path = path.as_ref();
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) => {
+                return Err(ConfigError::new(
+                    ConfigErrorKind::Io,
+                    format!("{}: {}", path.display(), err),
+                ))
+            }
+        };
+        let mut config = Config::parse(&text)?;
+        if let (Some(profile), Some(dir)) = (&config.profile, path.parent()) {
+            config.profile = Some(dir.join(profile));
+        }
+        return Ok(config);
+    }
+
+    pub fn parse(text: &str) -> Result<Config, ConfigError> {
+        let mut config = Config::default();
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.split('#').next().unwrap_or("");
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                [] => {}
+                ["bind", address] => config.bind = Some(BindAddress::parse(address)),
+                ["console", ip] => match ip.parse() {
+                    Ok(ip) => config.console_ip = Some(ip),
+                    Err(_) => {
+                        return Err(ConfigError::new(
+                            ConfigErrorKind::Syntax,
+                            format!("line {}: bad console address {}", line_number, ip),
+                        ))
+                    }
+                },
+                ["profile", path] => config.profile = Some(PathBuf::from(path)),
+                ["receive_buffer", bytes] => match bytes.parse() {
+                    Ok(bytes) => config.receive_buffer_size = Some(bytes),
+                    Err(_) => {
+                        return Err(ConfigError::new(
+                            ConfigErrorKind::Syntax,
+                            format!("line {}: bad receive buffer size {}", line_number, bytes),
+                        ))
+                    }
+                },
+                ["video_overflow", name] => match OverflowPolicy::parse(name) {
+                    Some(policy) => config.video_overflow = Some(policy),
+                    None => {
+                        return Err(ConfigError::new(
+                            ConfigErrorKind::Syntax,
+                            format!("line {}: unknown overflow policy {}", line_number, name),
+                        ))
+                    }
+                },
+                _ => {
+                    return Err(ConfigError::new(
+                        ConfigErrorKind::Syntax,
+                        format!("line {}: can't understand {:?}", line_number, line.trim()),
+                    ))
+                }
+            }
+        }
+        return Ok(config);
+    }
+}
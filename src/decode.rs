@@ -0,0 +1,164 @@
+// Copyright 2024 Dalton Durst and the drc-sim-rust contributors
+// SPDX-License-Identifier: MPL-2.0
+
+//! Decodes the Annex B access units built by H264Encapsulator into RGB
+//! pictures.
+//!
+//! We don't link against a decoder. Instead, access units are piped
+//! through an external process (ffmpeg by default) which writes raw
+//! RGB24 frames back to us.
+
+use std::{
+    io::{self, Read, Write},
+    process::{Child, ChildStdin, Command, Stdio},
+    thread::{self, JoinHandle},
+};
+
+use jpeg_encoder::{ColorType, Encoder, EncodingError};
+use log::{debug, error};
+
+use crate::{WUP_VIDEO_HEIGHT, WUP_VIDEO_WIDTH};
+
+/// The quality JPEGs are encoded at when the caller doesn't care.
+pub const DEFAULT_JPEG_QUALITY: u8 = 80;
+
+/// One decoded picture, stored as packed 8-bit RGB.
+#[derive(Clone, PartialEq)]
+pub struct DecodedFrame {
+    pub width: u16,
+    pub height: u16,
+    pub rgb: Vec<u8>,
+}
+
+impl DecodedFrame {
+    pub fn to_jpeg(&self, quality: u8) -> Result<Vec<u8>, EncodingError> {
+        let mut jpeg = Vec::new();
+        let encoder = Encoder::new(&mut jpeg, quality);
+        encoder.encode(&self.rgb, self.width, self.height, ColorType::Rgb)?;
+        return Ok(jpeg);
+    }
+}
+
+impl std::fmt::Debug for DecodedFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DecodedFrame")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("rgb", &format!("size {}", &self.rgb.len()))
+            .finish()
+    }
+}
+
+/// Decodes H.264 with a child process that reads Annex B on stdin and
+/// writes raw RGB24 frames of a fixed size to stdout. Decoded frames
+/// are handed to a callback on a thread owned by the ProcessDecoder.
+pub struct ProcessDecoder {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    reader: Option<JoinHandle<()>>,
+}
+
+impl ProcessDecoder {
+    /// Starts an ffmpeg process that decodes to WUP_VIDEO_WIDTH x
+    /// WUP_VIDEO_HEIGHT. ffmpeg must be in PATH.
+    pub fn ffmpeg<F>(on_frame: F) -> io::Result<ProcessDecoder>
+    where
+        F: FnMut(DecodedFrame) + Send + 'static,
+    {
+        let mut command = Command::new("ffmpeg");
+        command.args([
+            "-loglevel",
+            "error",
+            "-fflags",
+            "nobuffer",
+            "-flags",
+            "low_delay",
+            "-f",
+            "h264",
+            "-i",
+            "pipe:0",
+            "-f",
+            "rawvideo",
+            "-pix_fmt",
+            "rgb24",
+            "pipe:1",
+        ]);
+        return ProcessDecoder::spawn(
+            command,
+            WUP_VIDEO_WIDTH as u16,
+            WUP_VIDEO_HEIGHT as u16,
+            on_frame,
+        );
+    }
+
+    /// Starts command as a decoder which outputs frames of width x
+    /// height.
+    pub fn spawn<F>(
+        mut command: Command,
+        width: u16,
+        height: u16,
+        mut on_frame: F,
+    ) -> io::Result<ProcessDecoder>
+    where
+        F: FnMut(DecodedFrame) + Send + 'static,
+    {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take();
+        let mut stdout = child.stdout.take().unwrap();
+
+        let reader = thread::spawn(move || {
+            let frame_size = width as usize * height as usize * 3;
+            loop {
+                let mut rgb = vec![0u8; frame_size];
+                if let Err(err) = stdout.read_exact(&mut rgb) {
+                    debug!("Decoder output ended: {}", err);
+                    return;
+                }
+                on_frame(DecodedFrame { width, height, rgb });
+            }
+        });
+
+        return Ok(ProcessDecoder {
+            child,
+            stdin,
+            reader: Some(reader),
+        });
+    }
+
+    /// Sends an access unit to the decoder. The decoded picture is
+    /// passed to the callback once the decoder gets to it.
+    pub fn decode(&mut self, access_unit: &[u8]) -> io::Result<()> {
+        let stdin = match &mut self.stdin {
+            None => return Err(io::Error::from(io::ErrorKind::BrokenPipe)),
+            Some(stdin) => stdin,
+        };
+        stdin.write_all(access_unit)?;
+        return stdin.flush();
+    }
+
+    /// Closes the decoder's input and waits for it to exit and for its
+    /// last frames to be handed to the callback.
+    pub fn finish(&mut self) -> io::Result<()> {
+        drop(self.stdin.take());
+        let status = self.child.wait()?;
+        if let Some(reader) = self.reader.take() {
+            let _ = reader.join();
+        }
+        if !status.success() {
+            error!("Decoder exited with {}", status);
+        }
+        return Ok(());
+    }
+}
+
+impl Drop for ProcessDecoder {
+    fn drop(&mut self) {
+        if self.stdin.is_some() {
+            let _ = self.child.kill();
+            let _ = self.finish();
+        }
+    }
+}
@@ -0,0 +1,311 @@
+// Copyright 2024 Dalton Durst and the drc-sim-rust contributors
+// SPDX-License-Identifier: MPL-2.0
+
+//! The identity of the GamePad we pretend to be: what it answers when
+//! the console asks about it over the command channel.
+//!
+//! A profile names the GamePad's serial number, region and firmware
+//! versions. The only identity read that has been identified is the UIC
+//! EEPROM read (generic command 5/6), so these are answered through a
+//! UIC EEPROM image built from them. We don't know the real EEPROM's
+//! layout yet, so that image uses one of our own (see identity_eeprom)
+//! and may not satisfy every console; a dump captured from a real
+//! GamePad replaces it. Answers to generic
+//! commands nobody has named yet can be given by command ID, so
+//! different GamePad revisions can be emulated by capturing their
+//! replies into profiles.
+//!
+//! Profile files have one setting per line. `#` starts a comment, and
+//! hex bytes may be written with or without spaces between them.
+//!
+//! ```text
+//! name GamePad WUP-010 (USA)
+//! serial JW000000000            # up to 16 characters
+//! region usa                    # japan, usa or europe
+//! firmware 0001002a             # firmware versions, in hex
+//! uic_firmware 00000010
+//! uic_eeprom_file uic.bin       # a binary dump, relative to the profile
+//! uic_eeprom 00 16 00 19 ...    # or the dump itself, in hex
+//! uvc_uic 00160019 9e000000 40004000 000001ff
+//! generic 1 10 0a0b0c0d         # the answer to generic command 1/10
+//! ```
+
+use core::fmt;
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::cmd::{GenericReply, CMD0_OK, DEFAULT_UVC_UIC_RESPONSE};
+
+/// The generic command the console uses to read the UIC EEPROM.
+pub const UIC_EEPROM_COMMAND: (u8, u8) = (5, 6);
+
+/// The size of the image identity_eeprom builds.
+pub const IDENTITY_EEPROM_SIZE: usize = 0x300;
+/// Where identity_eeprom puts each field. Versions are big-endian, and
+/// the serial number is ASCII padded with zeros.
+const EEPROM_FIRMWARE_VERSION: usize = 0x00;
+const EEPROM_UIC_FIRMWARE_VERSION: usize = 0x04;
+const EEPROM_REGION: usize = 0x08;
+const EEPROM_SERIAL: usize = 0x10;
+pub const SERIAL_SIZE: usize = 16;
+
+pub struct ProfileError {
+    pub kind: ProfileErrorKind,
+    pub text: String,
+}
+
+impl ProfileError {
+    fn new(kind: ProfileErrorKind, text: String) -> ProfileError {
+        return ProfileError { kind, text };
+    }
+    fn format_error(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}: {}", self.kind, self.text)
+    }
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return self.format_error(f);
+    }
+}
+
+impl fmt::Debug for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return self.format_error(f);
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ProfileErrorKind {
+    /// The profile or a file it names couldn't be read.
+    Io,
+    /// A line isn't one of the settings we know.
+    Syntax,
+    /// A value that should be hex bytes isn't.
+    BadHex,
+    /// A value is the right shape but not one we can use, such as an
+    /// unknown region.
+    BadValue,
+}
+
+/// The region a GamePad was sold in, numbered as the Wii U numbers its
+/// regions.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    Japan = 1,
+    #[default]
+    Usa = 2,
+    Europe = 4,
+}
+
+impl Region {
+    pub fn parse(name: &str) -> Option<Region> {
+        return match name.to_ascii_lowercase().as_str() {
+            "japan" | "jpn" => Some(Region::Japan),
+            "usa" => Some(Region::Usa),
+            "europe" | "eur" => Some(Region::Europe),
+            _ => None,
+        };
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceProfile {
+    pub name: String,
+    /// The serial number on the GamePad's label. Only the first
+    /// SERIAL_SIZE bytes fit in the EEPROM image.
+    pub serial: String,
+    pub region: Region,
+    /// The version of the GamePad's main firmware.
+    pub firmware_version: u32,
+    /// The version of the UIC's firmware.
+    pub uic_firmware_version: u32,
+    /// Answers to generic commands, by primary and secondary ID. A UIC
+    /// EEPROM dump lives here under UIC_EEPROM_COMMAND.
+    pub generic_replies: HashMap<(u8, u8), GenericReply>,
+    /// The answer to every UVC/UIC command.
+    pub uvc_uic_response: Vec<u8>,
+}
+
+impl Default for DeviceProfile {
+    /// A USA GamePad with a made up serial number and the UVC/UIC
+    /// answer drc-sim uses.
+    fn default() -> DeviceProfile {
+        return DeviceProfile {
+            name: String::from("default"),
+            serial: String::from("DRCSIM000000"),
+            region: Region::default(),
+            firmware_version: 0,
+            uic_firmware_version: 0,
+            generic_replies: HashMap::new(),
+            uvc_uic_response: DEFAULT_UVC_UIC_RESPONSE.to_vec(),
+        };
+    }
+}
+
+impl DeviceProfile {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<DeviceProfile, ProfileError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|err| io_error(path, err))?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        return DeviceProfile::parse_in(&text, dir);
+    }
+
+    /// Reads a profile from text. Relative uic_eeprom_file paths are
+    /// relative to the working directory.
+    pub fn parse(text: &str) -> Result<DeviceProfile, ProfileError> {
+        return DeviceProfile::parse_in(text, Path::new(""));
+    }
+
+    fn parse_in(text: &str, dir: &Path) -> Result<DeviceProfile, ProfileError> {
+        let mut profile = DeviceProfile::default();
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.split('#').next().unwrap_or("");
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                [] => {}
+                ["name", name @ ..] if !name.is_empty() => {
+                    profile.name = name.join(" ");
+                }
+                ["serial", serial] => {
+                    profile.serial = serial.to_string();
+                }
+                ["region", region] => {
+                    profile.region = Region::parse(region).ok_or_else(|| {
+                        ProfileError::new(
+                            ProfileErrorKind::BadValue,
+                            format!("line {}: unknown region {}", line_number, region),
+                        )
+                    })?;
+                }
+                ["firmware", version] => {
+                    profile.firmware_version = parse_version(version, line_number)?;
+                }
+                ["uic_firmware", version] => {
+                    profile.uic_firmware_version = parse_version(version, line_number)?;
+                }
+                ["uic_eeprom_file", file] => {
+                    let path: PathBuf = dir.join(file);
+                    let data = fs::read(&path).map_err(|err| io_error(&path, err))?;
+                    profile.set_uic_eeprom(data);
+                }
+                ["uic_eeprom", data @ ..] => {
+                    profile.set_uic_eeprom(parse_hex(data, line_number)?);
+                }
+                ["uvc_uic", data @ ..] => {
+                    profile.uvc_uic_response = parse_hex(data, line_number)?;
+                }
+                ["generic", primary, secondary, data @ ..] => {
+                    let id = |word: &str| {
+                        word.parse::<u8>().map_err(|_| {
+                            ProfileError::new(
+                                ProfileErrorKind::Syntax,
+                                format!("line {}: bad command ID {}", line_number, word),
+                            )
+                        })
+                    };
+                    profile.generic_replies.insert(
+                        (id(primary)?, id(secondary)?),
+                        GenericReply {
+                            error_code: CMD0_OK,
+                            data: parse_hex(data, line_number)?,
+                        },
+                    );
+                }
+                _ => {
+                    return Err(ProfileError::new(
+                        ProfileErrorKind::Syntax,
+                        format!("line {}: can't understand {:?}", line_number, line.trim()),
+                    ))
+                }
+            }
+        }
+        return Ok(profile);
+    }
+
+    /// The UIC EEPROM dump if the profile has one, or else the image
+    /// built by identity_eeprom.
+    pub fn uic_eeprom(&self) -> Vec<u8> {
+        return match self.generic_replies.get(&UIC_EEPROM_COMMAND) {
+            Some(reply) => reply.data.clone(),
+            None => self.identity_eeprom(),
+        };
+    }
+
+    /// Builds a UIC EEPROM image holding this profile's identity.
+    /// Everything the layout doesn't cover is zero.
+    pub fn identity_eeprom(&self) -> Vec<u8> {
+        let mut eeprom = vec![0u8; IDENTITY_EEPROM_SIZE];
+        eeprom[EEPROM_FIRMWARE_VERSION..EEPROM_FIRMWARE_VERSION + 4]
+            .copy_from_slice(&self.firmware_version.to_be_bytes());
+        eeprom[EEPROM_UIC_FIRMWARE_VERSION..EEPROM_UIC_FIRMWARE_VERSION + 4]
+            .copy_from_slice(&self.uic_firmware_version.to_be_bytes());
+        eeprom[EEPROM_REGION] = self.region as u8;
+        let serial = self.serial.as_bytes();
+        let serial = &serial[..serial.len().min(SERIAL_SIZE)];
+        eeprom[EEPROM_SERIAL..EEPROM_SERIAL + serial.len()].copy_from_slice(serial);
+        return eeprom;
+    }
+
+    pub fn set_uic_eeprom(&mut self, data: Vec<u8>) {
+        self.generic_replies.insert(
+            UIC_EEPROM_COMMAND,
+            GenericReply {
+                error_code: CMD0_OK,
+                data,
+            },
+        );
+    }
+
+    /// The answer to a generic command. The UIC EEPROM read is always
+    /// answered, from the profile's identity if there is no dump.
+    pub fn generic_reply(&self, id_primary: u8, id_secondary: u8) -> Option<GenericReply> {
+        let id = (id_primary, id_secondary);
+        if let Some(reply) = self.generic_replies.get(&id) {
+            return Some(reply.clone());
+        }
+        if id == UIC_EEPROM_COMMAND {
+            return Some(GenericReply {
+                error_code: CMD0_OK,
+                data: self.identity_eeprom(),
+            });
+        }
+        return None;
+    }
+}
+
+fn io_error(path: &Path, err: io::Error) -> ProfileError {
+    return ProfileError::new(ProfileErrorKind::Io, format!("{}: {}", path.display(), err));
+}
+
+fn parse_version(word: &str, line_number: usize) -> Result<u32, ProfileError> {
+    let digits = word.strip_prefix("0x").unwrap_or(word);
+    return u32::from_str_radix(digits, 16).map_err(|_| {
+        ProfileError::new(
+            ProfileErrorKind::BadHex,
+            format!("line {}: bad version {}", line_number, word),
+        )
+    });
+}
+
+fn parse_hex(words: &[&str], line_number: usize) -> Result<Vec<u8>, ProfileError> {
+    let digits: String = words.concat();
+    let bad_hex = || {
+        ProfileError::new(
+            ProfileErrorKind::BadHex,
+            format!("line {}: bad hex {}", line_number, words.join(" ")),
+        )
+    };
+    if !digits.len().is_multiple_of(2) || !digits.is_ascii() {
+        return Err(bad_hex());
+    }
+    let mut data = Vec::with_capacity(digits.len() / 2);
+    for i in (0..digits.len()).step_by(2) {
+        data.push(u8::from_str_radix(&digits[i..i + 2], 16).map_err(|_| bad_hex())?);
+    }
+    return Ok(data);
+}
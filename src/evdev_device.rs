@@ -0,0 +1,57 @@
+// Copyright 2024 Dalton Durst and the drc-sim-rust contributors
+// SPDX-License-Identifier: MPL-2.0
+
+//! Reads a Linux input device and feeds its events through an
+//! InputMapper. Only built with the "evdev" feature.
+
+use std::{io, path::Path};
+
+use evdev::Device;
+use log::info;
+
+use crate::{
+    hid_sender::SharedInput,
+    input_mapping::{InputMapper, InputMapping, RawInputEvent},
+};
+
+pub struct EvdevInput {
+    device: Device,
+    mapper: InputMapper,
+}
+
+impl EvdevInput {
+    /// Opens the device at path, such as /dev/input/event5, and takes
+    /// axis ranges the mapping doesn't set from the device.
+    pub fn open<P: AsRef<Path>>(path: P, mapping: InputMapping) -> io::Result<EvdevInput> {
+        let device = Device::open(path.as_ref())?;
+        let mut mapper = InputMapper::new(mapping);
+        if let Some(axes) = device.supported_absolute_axes() {
+            let abs_state = device.get_abs_state()?;
+            for axis in axes.iter() {
+                let info = abs_state[axis.0 as usize];
+                mapper.set_device_range(axis.0, info.minimum, info.maximum);
+            }
+        }
+        info!(
+            "Reading input from {} ({})",
+            path.as_ref().display(),
+            device.name().unwrap_or("unnamed device")
+        );
+        return Ok(EvdevInput { device, mapper });
+    }
+
+    pub fn mapper(&self) -> &InputMapper {
+        return &self.mapper;
+    }
+
+    /// Reads events forever, updating input as they arrive. Returns if
+    /// the device can't be read, for example when it is unplugged.
+    pub fn run(&mut self, input: &SharedInput) -> io::Result<()> {
+        loop {
+            let events = self.device.fetch_events()?.map(|event| {
+                return RawInputEvent::new(event.event_type().0, event.code(), event.value());
+            });
+            self.mapper.run(events, input);
+        }
+    }
+}
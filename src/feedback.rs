@@ -0,0 +1,147 @@
+// Copyright 2024 Dalton Durst and the drc-sim-rust contributors
+// SPDX-License-Identifier: MPL-2.0
+
+//! Things the console asks the GamePad's hardware to do, turned into
+//! FeedbackEvents for whatever is standing in for that hardware.
+//!
+//! Rumble is the one we understand: every audio packet has a vibrate
+//! bit, and the motor runs while it is set. The original drc-sim reads
+//! it the same way. The console also sends packets to the GamePad's HID
+//! port. Those that carry a rumble pattern are read into RumblePattern
+//! events. The pattern is the one games hand to VPADControlMotor: up to
+//! 120 steps, one bit each, most significant bit first. We read it from
+//! a packet laid out as a type byte of 0x01, the pattern length in bits,
+//! then the pattern bytes. Anything else on the HID port, such as the
+//! LED commands, is passed on as a HidOutput event with its raw bytes
+//! so that it can be studied.
+
+use std::{
+    io,
+    net::UdpSocket,
+    sync::mpsc::{self, Receiver},
+};
+
+use log::{debug, info};
+
+use crate::{hex, incoming_packet_parser::WUPAudioPacket};
+
+/// The largest packet we expect on the HID port.
+const HID_OUTPUT_BUFFER_SIZE: usize = 2048;
+
+/// The first byte of a HID port packet carrying a rumble pattern.
+const HID_OUTPUT_RUMBLE_PATTERN: u8 = 0x01;
+
+/// The longest rumble pattern the console sends, 15 bytes of steps.
+pub const MAX_RUMBLE_PATTERN_BITS: usize = 120;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FeedbackEvent {
+    /// The rumble motor should start or stop. timestamp is from the
+    /// audio packet that changed it, so the pattern can be rebuilt
+    /// from a series of these.
+    Rumble { on: bool, timestamp: u32 },
+    /// The motor should play pattern, one step per entry, running
+    /// during the steps that are true.
+    RumblePattern { pattern: Vec<bool> },
+    /// A packet from the console to the HID port, which we don't know
+    /// how to read.
+    HidOutput { data: Vec<u8> },
+}
+
+/// Reads a packet from the HID port into the event it stands for. None
+/// if it isn't one we know the format of.
+pub fn parse_hid_output(data: &[u8]) -> Option<FeedbackEvent> {
+    return match data {
+        [HID_OUTPUT_RUMBLE_PATTERN, bits, pattern @ ..] => {
+            let bits = *bits as usize;
+            if bits == 0 || bits > MAX_RUMBLE_PATTERN_BITS || pattern.len() < bits.div_ceil(8) {
+                return None;
+            }
+            let pattern = (0..bits)
+                .map(|bit| pattern[bit / 8] & (0x80 >> (bit % 8)) != 0)
+                .collect();
+            Some(FeedbackEvent::RumblePattern { pattern })
+        }
+        _ => None,
+    };
+}
+
+/// Watches incoming packets for feedback and hands each change to a
+/// callback.
+pub struct FeedbackMonitor {
+    on_event: Box<dyn FnMut(FeedbackEvent) + Send>,
+    rumbling: bool,
+    hid_outputs: u64,
+}
+
+impl FeedbackMonitor {
+    pub fn new<F>(on_event: F) -> FeedbackMonitor
+    where
+        F: FnMut(FeedbackEvent) + Send + 'static,
+    {
+        return FeedbackMonitor {
+            on_event: Box::new(on_event),
+            rumbling: false,
+            hid_outputs: 0,
+        };
+    }
+
+    /// Creates a FeedbackMonitor that sends its events to the returned
+    /// Receiver.
+    pub fn with_channel() -> (FeedbackMonitor, Receiver<FeedbackEvent>) {
+        let (sender, receiver) = mpsc::channel();
+        let monitor = FeedbackMonitor::new(move |event| {
+            // Nobody listening isn't an error for us.
+            let _ = sender.send(event);
+        });
+        return (monitor, receiver);
+    }
+
+    pub fn is_rumbling(&self) -> bool {
+        return self.rumbling;
+    }
+
+    /// How many HID port packets have been passed on, read or not.
+    pub fn hid_outputs(&self) -> u64 {
+        return self.hid_outputs;
+    }
+
+    /// Checks an audio packet for a change in rumble.
+    pub fn on_audio_packet(&mut self, packet: &WUPAudioPacket) {
+        if packet.vibrate == self.rumbling {
+            return;
+        }
+        self.rumbling = packet.vibrate;
+        debug!(
+            "Rumble {} at {}",
+            if self.rumbling { "on" } else { "off" },
+            packet.timestamp
+        );
+        (self.on_event)(FeedbackEvent::Rumble {
+            on: self.rumbling,
+            timestamp: packet.timestamp,
+        });
+    }
+
+    /// Passes on a packet that arrived on the HID port.
+    pub fn on_hid_packet(&mut self, data: &[u8]) {
+        self.hid_outputs += 1;
+        if self.hid_outputs == 1 {
+            info!("Got the first HID output packet from the console");
+        }
+        debug!("HID output: {}", hex(data));
+        let event = parse_hid_output(data).unwrap_or_else(|| FeedbackEvent::HidOutput {
+            data: data.to_vec(),
+        });
+        (self.on_event)(event);
+    }
+
+    /// Reads packets from the HID port socket until it fails.
+    pub fn run_hid(&mut self, socket: &UdpSocket) -> io::Result<()> {
+        let mut buf = [0u8; HID_OUTPUT_BUFFER_SIZE];
+        loop {
+            let size = socket.recv(&mut buf)?;
+            self.on_hid_packet(&buf[..size]);
+        }
+    }
+}
@@ -0,0 +1,143 @@
+// Copyright 2024 Dalton Durst and the drc-sim-rust contributors
+// SPDX-License-Identifier: MPL-2.0
+
+//! Turns the slice data sent by the WUP into an H.264 Annex B
+//! bitstream that a normal decoder can handle.
+//!
+//! The WUP strips everything but the slice data from each frame. The
+//! parameter sets and slice headers it would have used are always the
+//! same, so we put them back ourselves. These values come from the
+//! original drc-sim.
+
+use crate::packet_organizer::AssembledFrame;
+
+pub const START_CODE: [u8; 4] = [0x00, 0x00, 0x00, 0x01];
+
+/// The sequence parameter set used by the WUP: High profile, level 3.2,
+/// 864x480 coded and cropped to 854x480, 8 bits of frame_num.
+pub const SPS: [u8; 11] = [
+    0x67, 0x64, 0x00, 0x20, 0xac, 0x2b, 0x40, 0x6c, 0x1e, 0xf3, 0x68,
+];
+
+/// The picture parameter set used by the WUP: CABAC, deblocking filter
+/// control present.
+pub const PPS: [u8; 5] = [0x68, 0xee, 0x06, 0x0c, 0xe8];
+
+/// NAL header and slice header for an IDR I slice.
+const IDR_SLICE_HEADER: u32 = 0x25b804ff;
+
+/// NAL header and slice header for a P slice. frame_num goes in bits
+/// 13 through 20.
+const P_SLICE_HEADER: u32 = 0x21e003ff;
+
+/// Builds Annex B access units out of AssembledFrames.
+#[derive(Default)]
+pub struct H264Encapsulator {
+    frame_num: u8,
+}
+
+impl H264Encapsulator {
+    pub fn new() -> H264Encapsulator {
+        return H264Encapsulator::default();
+    }
+
+    /// Returns a complete Annex B access unit for frame. IDR frames are
+    /// preceded by the SPS and PPS so that a decoder can join the
+    /// stream at any IDR.
+    pub fn encapsulate(&mut self, frame: &AssembledFrame) -> Vec<u8> {
+        return self.encapsulate_payload(frame.is_idr(), &frame.payload());
+    }
+
+    pub fn encapsulate_payload(&mut self, is_idr: bool, payload: &[u8]) -> Vec<u8> {
+        let slice_header = if is_idr {
+            self.frame_num = 0;
+            IDR_SLICE_HEADER
+        } else {
+            P_SLICE_HEADER | ((self.frame_num as u32) << 13)
+        };
+        self.frame_num = self.frame_num.wrapping_add(1);
+
+        let mut nals = Vec::with_capacity(payload.len() + payload.len() / 64 + 32);
+        if is_idr {
+            nals.extend_from_slice(&START_CODE);
+            nals.extend_from_slice(&SPS);
+            nals.extend_from_slice(&START_CODE);
+            nals.extend_from_slice(&PPS);
+        }
+        nals.extend_from_slice(&START_CODE);
+        nals.extend_from_slice(&slice_header.to_be_bytes());
+        add_emulation_prevention(&mut nals, payload);
+        return nals;
+    }
+}
+
+/// Appends data to nal, inserting an emulation_prevention_three_byte
+/// wherever data would otherwise contain a start code prefix.
+pub fn add_emulation_prevention(nal: &mut Vec<u8>, data: &[u8]) {
+    let mut zeros = 0;
+    for byte in data {
+        if zeros >= 2 && *byte <= 3 {
+            nal.push(3);
+            zeros = 0;
+        }
+        if *byte == 0 {
+            zeros += 1;
+        } else {
+            zeros = 0;
+        }
+        nal.push(*byte);
+    }
+}
+
+/// Removes emulation_prevention_three_bytes from a NAL unit's payload,
+/// returning the raw byte sequence payload.
+pub fn remove_emulation_prevention(nal: &[u8]) -> Vec<u8> {
+    let mut rbsp = Vec::with_capacity(nal.len());
+    let mut zeros = 0;
+    for byte in nal {
+        if zeros >= 2 && *byte == 3 {
+            zeros = 0;
+            continue;
+        }
+        if *byte == 0 {
+            zeros += 1;
+        } else {
+            zeros = 0;
+        }
+        rbsp.push(*byte);
+    }
+    return rbsp;
+}
+
+/// Splits an Annex B bitstream into its NAL units, without their start
+/// codes.
+pub fn split_annex_b(stream: &[u8]) -> Vec<&[u8]> {
+    let mut nals = Vec::new();
+    let mut start: Option<usize> = None;
+    let mut i = 0;
+    while i + 3 <= stream.len() {
+        if stream[i] == 0 && stream[i + 1] == 0 && stream[i + 2] == 1 {
+            if let Some(start) = start {
+                nals.push(trim_trailing_zeros(&stream[start..i]));
+            }
+            i += 3;
+            start = Some(i);
+            continue;
+        }
+        i += 1;
+    }
+    if let Some(start) = start {
+        nals.push(&stream[start..]);
+    }
+    return nals;
+}
+
+/// The zero byte of a four-byte start code (and any trailing_zero_8bits)
+/// belong to neither NAL unit around them.
+fn trim_trailing_zeros(nal: &[u8]) -> &[u8] {
+    let mut end = nal.len();
+    while end > 0 && nal[end - 1] == 0 {
+        end -= 1;
+    }
+    return &nal[..end];
+}
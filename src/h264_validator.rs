@@ -0,0 +1,790 @@
+// Copyright 2024 Dalton Durst and the drc-sim-rust contributors
+// SPDX-License-Identifier: MPL-2.0
+
+//! Checks the Annex B access units that we build from WUP frames
+//! without decoding them.
+//!
+//! FrameAssembler only hands out frames which received every dgram, so
+//! anything flagged here was broken by us putting the bitstream back
+//! together rather than by the network.
+//!
+//! The WUP uses CABAC, which can't be walked without a full decoder, so
+//! nothing here can tell whether a slice's data reaches the last
+//! macroblock it should. Macroblock checks only look at the slice
+//! headers: the first slice must start at macroblock 0, no two may start
+//! at the same one, and none may start past the end of the picture.
+//! Slice data that was truncated or partly lost is not detected; the
+//! encapsulator puts every frame into one slice, so its header always
+//! claims the whole picture.
+
+use core::fmt;
+
+use bitter::{BigEndianReader, BitReader};
+
+use crate::{
+    h264::{remove_emulation_prevention, split_annex_b},
+    WUP_VIDEO_HEIGHT, WUP_VIDEO_WIDTH,
+};
+
+const NAL_TYPE_NON_IDR_SLICE: u8 = 1;
+const NAL_TYPE_IDR_SLICE: u8 = 5;
+const NAL_TYPE_SPS: u8 = 7;
+const NAL_TYPE_PPS: u8 = 8;
+
+/// The widest or tallest picture any level allows, sqrt(8 * MaxFS) for
+/// level 6.2. An SPS bigger than this is garbage, and rejecting it keeps
+/// the macroblock arithmetic from overflowing.
+const MAX_DIMENSION_MBS: u32 = 1055;
+
+pub struct BitstreamProblem {
+    pub kind: BitstreamProblemKind,
+    pub text: String,
+}
+
+impl BitstreamProblem {
+    fn new(kind: BitstreamProblemKind, text: String) -> BitstreamProblem {
+        return BitstreamProblem { kind, text };
+    }
+    fn format_error(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}: {}", self.kind, self.text)
+    }
+}
+
+impl fmt::Display for BitstreamProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return self.format_error(f);
+    }
+}
+
+impl fmt::Debug for BitstreamProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return self.format_error(f);
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BitstreamProblemKind {
+    /// The access unit did not contain any NAL units.
+    NoNalUnits,
+    /// A NAL unit had its forbidden_zero_bit set.
+    ForbiddenBitSet,
+    /// A NAL unit contained a start code prefix or an
+    /// emulation_prevention_three_byte in a place it isn't allowed.
+    BadEmulationPrevention,
+    /// A parameter set could not be parsed or uses features that the
+    /// WUP doesn't.
+    BadParameterSet,
+    /// The SPS describes a picture which is not
+    /// WUP_VIDEO_WIDTH x WUP_VIDEO_HEIGHT.
+    WrongDimensions,
+    /// A slice arrived before the SPS and PPS it refers to.
+    MissingParameterSets,
+    /// A slice header could not be parsed.
+    BadSliceHeader,
+    /// The access unit did not contain a slice.
+    NoSlices,
+    /// A slice had a header but no slice data.
+    EmptySliceData,
+    /// The slice headers do not start at macroblock 0, start two slices
+    /// at the same macroblock, or start one past the end of the picture.
+    MacroblockCoverage,
+    /// frame_num does not follow on from the previous frame. The
+    /// encapsulator numbers frames itself, so this catches frames lost
+    /// between it and the validator, not frames lost on the network.
+    FrameNumGap,
+    /// An IDR NAL unit held something other than an I slice, or the
+    /// frame's IDR flag didn't match its NAL unit type.
+    IdrMismatch,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SliceType {
+    P,
+    B,
+    I,
+    SP,
+    SI,
+}
+
+impl SliceType {
+    fn from_ue(slice_type: u32) -> Option<SliceType> {
+        // Values 5-9 mean the same as 0-4 but promise that every slice
+        // in the picture has the same type.
+        return match slice_type % 5 {
+            0 => Some(SliceType::P),
+            1 => Some(SliceType::B),
+            2 => Some(SliceType::I),
+            3 => Some(SliceType::SP),
+            4 => Some(SliceType::SI),
+            _ => None,
+        };
+    }
+}
+
+/// What we learned about one slice from its header.
+#[derive(Debug, Clone)]
+pub struct SliceInfo {
+    pub nal_unit_type: u8,
+    pub slice_type: SliceType,
+    pub first_mb_in_slice: u32,
+    pub frame_num: u32,
+    /// Bytes of slice data following the header.
+    pub data_len: usize,
+}
+
+/// The result of validating one access unit.
+#[derive(Debug)]
+pub struct FrameValidation {
+    pub timestamp: u32,
+    pub slices: Vec<SliceInfo>,
+    /// The number of macroblocks from the first slice's
+    /// first_mb_in_slice to the end of the picture: what the slice
+    /// headers claim to cover, not what the slice data holds.
+    pub claimed_macroblocks: u32,
+    /// The number of macroblocks in a complete picture, if we know it.
+    pub expected_macroblocks: Option<u32>,
+    pub problems: Vec<BitstreamProblem>,
+}
+
+impl FrameValidation {
+    pub fn is_ok(&self) -> bool {
+        return self.problems.is_empty();
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SpsInfo {
+    log2_max_frame_num: u32,
+    pic_order_cnt_type: u32,
+    log2_max_pic_order_cnt_lsb: u32,
+    delta_pic_order_always_zero: bool,
+    frame_mbs_only: bool,
+    width_mbs: u32,
+    height_mbs: u32,
+    /// The picture size after cropping.
+    width: u32,
+    height: u32,
+}
+
+#[derive(Debug, Clone)]
+struct PpsInfo {
+    entropy_coding_mode: bool,
+    bottom_field_pic_order_in_frame_present: bool,
+    weighted_pred: bool,
+    weighted_bipred_idc: u32,
+    deblocking_filter_control_present: bool,
+    redundant_pic_cnt_present: bool,
+}
+
+/// Reads the Exp-Golomb and fixed-length fields out of an RBSP.
+struct RbspReader<'a> {
+    bits: BigEndianReader<'a>,
+}
+
+impl<'a> RbspReader<'a> {
+    fn new(rbsp: &'a [u8]) -> RbspReader<'a> {
+        return RbspReader {
+            bits: BigEndianReader::new(rbsp),
+        };
+    }
+
+    fn u(&mut self, count: u32) -> Option<u32> {
+        if count == 0 {
+            return Some(0);
+        }
+        if count > 32 {
+            return None;
+        }
+        return self.bits.read_bits(count).map(|v| v as u32);
+    }
+
+    fn flag(&mut self) -> Option<bool> {
+        return self.bits.read_bit();
+    }
+
+    fn ue(&mut self) -> Option<u32> {
+        let mut leading_zeros = 0;
+        while !self.bits.read_bit()? {
+            leading_zeros += 1;
+            if leading_zeros > 31 {
+                return None;
+            }
+        }
+        let value = (1u64 << leading_zeros) - 1 + self.u(leading_zeros)? as u64;
+        return u32::try_from(value).ok();
+    }
+
+    fn se(&mut self) -> Option<i32> {
+        let k = self.ue()? as i64;
+        let value = if k % 2 == 1 { (k + 1) / 2 } else { -(k / 2) };
+        return i32::try_from(value).ok();
+    }
+
+    fn byte_aligned(&self) -> bool {
+        return self.bits.byte_aligned();
+    }
+
+    fn bytes_remaining(&self) -> usize {
+        return self.bits.bytes_remaining();
+    }
+}
+
+/// Validates a stream of access units, keeping the state needed to
+/// check one frame against the next.
+#[derive(Default)]
+pub struct BitstreamValidator {
+    sps: Option<SpsInfo>,
+    pps: Option<PpsInfo>,
+    prev_frame_num: Option<u32>,
+    frames_checked: u64,
+    bad_frames: u64,
+}
+
+impl BitstreamValidator {
+    pub fn new() -> BitstreamValidator {
+        return BitstreamValidator::default();
+    }
+
+    pub fn frames_checked(&self) -> u64 {
+        return self.frames_checked;
+    }
+
+    pub fn bad_frames(&self) -> u64 {
+        return self.bad_frames;
+    }
+
+    /// Validates access_unit, an Annex B bitstream holding exactly one
+    /// picture. is_idr is whether the WUP marked the frame as an IDR.
+    pub fn validate(
+        &mut self,
+        timestamp: u32,
+        is_idr: bool,
+        access_unit: &[u8],
+    ) -> FrameValidation {
+        let mut result = FrameValidation {
+            timestamp,
+            slices: Vec::new(),
+            claimed_macroblocks: 0,
+            expected_macroblocks: None,
+            problems: Vec::new(),
+        };
+
+        let nals = split_annex_b(access_unit);
+        if nals.is_empty() {
+            result.problems.push(BitstreamProblem::new(
+                BitstreamProblemKind::NoNalUnits,
+                "no start code found".to_string(),
+            ));
+        }
+
+        for nal in nals {
+            self.validate_nal(nal, &mut result);
+        }
+
+        self.check_slices(is_idr, &mut result);
+
+        self.frames_checked += 1;
+        if !result.is_ok() {
+            self.bad_frames += 1;
+        }
+        return result;
+    }
+
+    fn validate_nal(&mut self, nal: &[u8], result: &mut FrameValidation) {
+        if nal.is_empty() {
+            result.problems.push(BitstreamProblem::new(
+                BitstreamProblemKind::NoNalUnits,
+                "empty NAL unit".to_string(),
+            ));
+            return;
+        }
+        if nal[0] & 0x80 != 0 {
+            result.problems.push(BitstreamProblem::new(
+                BitstreamProblemKind::ForbiddenBitSet,
+                format!("NAL header {:#04x}", nal[0]),
+            ));
+        }
+        if let Some(offset) = find_bad_emulation_prevention(nal) {
+            result.problems.push(BitstreamProblem::new(
+                BitstreamProblemKind::BadEmulationPrevention,
+                format!("at byte {} of {}", offset, nal.len()),
+            ));
+        }
+
+        let nal_ref_idc = (nal[0] >> 5) & 0x3;
+        let nal_unit_type = nal[0] & 0x1F;
+        let rbsp = remove_emulation_prevention(&nal[1..]);
+        match nal_unit_type {
+            NAL_TYPE_SPS => match parse_sps(&rbsp) {
+                Ok(sps) => {
+                    if sps.width != WUP_VIDEO_WIDTH || sps.height != WUP_VIDEO_HEIGHT {
+                        result.problems.push(BitstreamProblem::new(
+                            BitstreamProblemKind::WrongDimensions,
+                            format!(
+                                "SPS describes {}x{}, want {}x{}",
+                                sps.width, sps.height, WUP_VIDEO_WIDTH, WUP_VIDEO_HEIGHT
+                            ),
+                        ));
+                    }
+                    self.sps = Some(sps);
+                }
+                Err(text) => result.problems.push(BitstreamProblem::new(
+                    BitstreamProblemKind::BadParameterSet,
+                    format!("SPS: {}", text),
+                )),
+            },
+            NAL_TYPE_PPS => match parse_pps(&rbsp) {
+                Ok(pps) => self.pps = Some(pps),
+                Err(text) => result.problems.push(BitstreamProblem::new(
+                    BitstreamProblemKind::BadParameterSet,
+                    format!("PPS: {}", text),
+                )),
+            },
+            NAL_TYPE_NON_IDR_SLICE | NAL_TYPE_IDR_SLICE => {
+                let (sps, pps) = match (&self.sps, &self.pps) {
+                    (Some(sps), Some(pps)) => (sps, pps),
+                    _ => {
+                        result.problems.push(BitstreamProblem::new(
+                            BitstreamProblemKind::MissingParameterSets,
+                            "slice arrived before SPS and PPS".to_string(),
+                        ));
+                        return;
+                    }
+                };
+                match parse_slice_header(&rbsp, nal_unit_type, nal_ref_idc, sps, pps) {
+                    Ok(slice) => {
+                        if slice.data_len == 0 {
+                            result.problems.push(BitstreamProblem::new(
+                                BitstreamProblemKind::EmptySliceData,
+                                format!("slice at macroblock {}", slice.first_mb_in_slice),
+                            ));
+                        }
+                        result.slices.push(slice);
+                    }
+                    Err(text) => result.problems.push(BitstreamProblem::new(
+                        BitstreamProblemKind::BadSliceHeader,
+                        text,
+                    )),
+                }
+            }
+            // Anything else (SEI, AUD...) is not something we generate,
+            // but it's not invalid either.
+            _ => (),
+        }
+    }
+
+    fn check_slices(&mut self, is_idr: bool, result: &mut FrameValidation) {
+        if result.slices.is_empty() {
+            result.problems.push(BitstreamProblem::new(
+                BitstreamProblemKind::NoSlices,
+                "access unit has no slice NAL units".to_string(),
+            ));
+            return;
+        }
+
+        let first_slice = result.slices[0].clone();
+        let nal_is_idr = first_slice.nal_unit_type == NAL_TYPE_IDR_SLICE;
+        if nal_is_idr != is_idr {
+            result.problems.push(BitstreamProblem::new(
+                BitstreamProblemKind::IdrMismatch,
+                format!(
+                    "frame IDR flag is {} but NAL unit type is {}",
+                    is_idr, first_slice.nal_unit_type
+                ),
+            ));
+        }
+        for slice in &result.slices {
+            if slice.nal_unit_type == NAL_TYPE_IDR_SLICE && slice.slice_type != SliceType::I {
+                result.problems.push(BitstreamProblem::new(
+                    BitstreamProblemKind::IdrMismatch,
+                    format!("IDR NAL unit holds a {:?} slice", slice.slice_type),
+                ));
+            }
+            if slice.frame_num != first_slice.frame_num {
+                result.problems.push(BitstreamProblem::new(
+                    BitstreamProblemKind::FrameNumGap,
+                    format!(
+                        "slices in one picture have frame_num {} and {}",
+                        first_slice.frame_num, slice.frame_num
+                    ),
+                ));
+            }
+        }
+
+        if let Some(sps) = &self.sps {
+            let total = sps.width_mbs * sps.height_mbs;
+            result.expected_macroblocks = Some(total);
+
+            let mut first_mbs: Vec<u32> =
+                result.slices.iter().map(|s| s.first_mb_in_slice).collect();
+            first_mbs.sort_unstable();
+            first_mbs.dedup();
+            if first_mbs.len() != result.slices.len() {
+                result.problems.push(BitstreamProblem::new(
+                    BitstreamProblemKind::MacroblockCoverage,
+                    "two slices start at the same macroblock".to_string(),
+                ));
+            }
+            if first_mbs[0] != 0 {
+                result.problems.push(BitstreamProblem::new(
+                    BitstreamProblemKind::MacroblockCoverage,
+                    format!("first slice starts at macroblock {}", first_mbs[0]),
+                ));
+            }
+            let last = *first_mbs.last().unwrap();
+            if last >= total {
+                result.problems.push(BitstreamProblem::new(
+                    BitstreamProblemKind::MacroblockCoverage,
+                    format!("slice starts at macroblock {} of {}", last, total),
+                ));
+            }
+            result.claimed_macroblocks = total.saturating_sub(first_mbs[0]);
+
+            let max_frame_num = 1u32 << sps.log2_max_frame_num;
+            let frame_num = first_slice.frame_num;
+            if nal_is_idr {
+                if frame_num != 0 {
+                    result.problems.push(BitstreamProblem::new(
+                        BitstreamProblemKind::FrameNumGap,
+                        format!("IDR has frame_num {}", frame_num),
+                    ));
+                }
+            } else if let Some(prev) = self.prev_frame_num {
+                let expected = (prev + 1) % max_frame_num;
+                if frame_num != expected {
+                    result.problems.push(BitstreamProblem::new(
+                        BitstreamProblemKind::FrameNumGap,
+                        format!("frame_num is {} want {}", frame_num, expected),
+                    ));
+                }
+            }
+            self.prev_frame_num = Some(frame_num);
+        }
+    }
+}
+
+/// Returns the offset of the first three-byte sequence in nal which
+/// can't appear inside a NAL unit, if any.
+fn find_bad_emulation_prevention(nal: &[u8]) -> Option<usize> {
+    for i in 0..nal.len().saturating_sub(2) {
+        if nal[i] != 0 || nal[i + 1] != 0 {
+            continue;
+        }
+        match nal[i + 2] {
+            0..=2 => return Some(i),
+            // An emulation_prevention_three_byte must be followed by a
+            // byte that needed escaping, or end the NAL unit.
+            3 if i + 3 < nal.len() && nal[i + 3] > 3 => return Some(i),
+            _ => (),
+        }
+    }
+    return None;
+}
+
+fn parse_sps(rbsp: &[u8]) -> Result<SpsInfo, String> {
+    let truncated = || "truncated".to_string();
+    let mut r = RbspReader::new(rbsp);
+    let profile_idc = r.u(8).ok_or_else(truncated)?;
+    r.u(8).ok_or_else(truncated)?; // constraint flags
+    r.u(8).ok_or_else(truncated)?; // level_idc
+    r.ue().ok_or_else(truncated)?; // seq_parameter_set_id
+
+    if [100, 110, 122, 244, 44, 83, 86, 118, 128, 138, 139, 134, 135].contains(&profile_idc) {
+        let chroma_format_idc = r.ue().ok_or_else(truncated)?;
+        if chroma_format_idc != 1 {
+            return Err(format!(
+                "chroma_format_idc {} is not 4:2:0",
+                chroma_format_idc
+            ));
+        }
+        r.ue().ok_or_else(truncated)?; // bit_depth_luma_minus8
+        r.ue().ok_or_else(truncated)?; // bit_depth_chroma_minus8
+        r.flag().ok_or_else(truncated)?; // qpprime_y_zero_transform_bypass_flag
+        if r.flag().ok_or_else(truncated)? {
+            for i in 0..8 {
+                if r.flag().ok_or_else(truncated)? {
+                    skip_scaling_list(&mut r, if i < 6 { 16 } else { 64 }).ok_or_else(truncated)?;
+                }
+            }
+        }
+    }
+
+    let log2_max_frame_num = parse_log2_max(&mut r, "log2_max_frame_num")?;
+    let pic_order_cnt_type = r.ue().ok_or_else(truncated)?;
+    let mut log2_max_pic_order_cnt_lsb = 0;
+    let mut delta_pic_order_always_zero = false;
+    match pic_order_cnt_type {
+        0 => {
+            log2_max_pic_order_cnt_lsb = parse_log2_max(&mut r, "log2_max_pic_order_cnt_lsb")?;
+        }
+        1 => {
+            delta_pic_order_always_zero = r.flag().ok_or_else(truncated)?;
+            r.se().ok_or_else(truncated)?; // offset_for_non_ref_pic
+            r.se().ok_or_else(truncated)?; // offset_for_top_to_bottom_field
+            let cycle = r.ue().ok_or_else(truncated)?;
+            for _ in 0..cycle {
+                r.se().ok_or_else(truncated)?;
+            }
+        }
+        2 => (),
+        other => return Err(format!("pic_order_cnt_type {} is invalid", other)),
+    }
+    r.ue().ok_or_else(truncated)?; // max_num_ref_frames
+    r.flag().ok_or_else(truncated)?; // gaps_in_frame_num_value_allowed_flag
+    let width_mbs = r.ue().ok_or_else(truncated)?.saturating_add(1);
+    let height_map_units = r.ue().ok_or_else(truncated)?.saturating_add(1);
+    let frame_mbs_only = r.flag().ok_or_else(truncated)?;
+    if !frame_mbs_only {
+        r.flag().ok_or_else(truncated)?; // mb_adaptive_frame_field_flag
+    }
+    r.flag().ok_or_else(truncated)?; // direct_8x8_inference_flag
+    let mut crop = [0u32; 4];
+    if r.flag().ok_or_else(truncated)? {
+        for value in crop.iter_mut() {
+            *value = r.ue().ok_or_else(truncated)?;
+        }
+    }
+
+    let height_mbs = if frame_mbs_only {
+        height_map_units
+    } else {
+        height_map_units.saturating_mul(2)
+    };
+    if width_mbs > MAX_DIMENSION_MBS || height_mbs > MAX_DIMENSION_MBS {
+        return Err(format!(
+            "{}x{} macroblocks is larger than any level allows",
+            width_mbs, height_mbs
+        ));
+    }
+    // 4:2:0 crops in units of two pixels, and frame_mbs_only = 0
+    // doubles the vertical unit.
+    let crop_unit_y = if frame_mbs_only { 2 } else { 4 };
+    let crop_x = crop[0]
+        .checked_add(crop[1])
+        .and_then(|crop| crop.checked_mul(2));
+    let crop_y = crop[2]
+        .checked_add(crop[3])
+        .and_then(|crop| crop.checked_mul(crop_unit_y));
+    let width = crop_x
+        .and_then(|crop| (width_mbs * 16).checked_sub(crop))
+        .ok_or("cropped to nothing")?;
+    let height = crop_y
+        .and_then(|crop| (height_mbs * 16).checked_sub(crop))
+        .ok_or("cropped to nothing")?;
+    return Ok(SpsInfo {
+        log2_max_frame_num,
+        pic_order_cnt_type,
+        log2_max_pic_order_cnt_lsb,
+        delta_pic_order_always_zero,
+        frame_mbs_only,
+        width_mbs,
+        height_mbs,
+        width,
+        height,
+    });
+}
+
+/// Reads a log2_max_*_minus4 field, which the spec limits to 0..=12.
+fn parse_log2_max(r: &mut RbspReader, name: &str) -> Result<u32, String> {
+    let minus4 = r.ue().ok_or_else(|| "truncated".to_string())?;
+    if minus4 > 12 {
+        return Err(format!(
+            "{} {} is outside 4..=16",
+            name,
+            minus4.saturating_add(4)
+        ));
+    }
+    return Ok(minus4 + 4);
+}
+
+fn skip_scaling_list(r: &mut RbspReader, size: u32) -> Option<()> {
+    let mut last_scale = 8i32;
+    let mut next_scale = 8i32;
+    for _ in 0..size {
+        if next_scale != 0 {
+            let delta_scale = r.se()?;
+            next_scale = (last_scale + delta_scale.rem_euclid(256)) % 256;
+        }
+        if next_scale != 0 {
+            last_scale = next_scale;
+        }
+    }
+    return Some(());
+}
+
+fn parse_pps(rbsp: &[u8]) -> Result<PpsInfo, String> {
+    let truncated = || "truncated".to_string();
+    let mut r = RbspReader::new(rbsp);
+    r.ue().ok_or_else(truncated)?; // pic_parameter_set_id
+    r.ue().ok_or_else(truncated)?; // seq_parameter_set_id
+    let entropy_coding_mode = r.flag().ok_or_else(truncated)?;
+    let bottom_field_pic_order_in_frame_present = r.flag().ok_or_else(truncated)?;
+    let num_slice_groups = r.ue().ok_or_else(truncated)?.saturating_add(1);
+    if num_slice_groups != 1 {
+        return Err(format!(
+            "{} slice groups are not supported",
+            num_slice_groups
+        ));
+    }
+    r.ue().ok_or_else(truncated)?; // num_ref_idx_l0_default_active_minus1
+    r.ue().ok_or_else(truncated)?; // num_ref_idx_l1_default_active_minus1
+    let weighted_pred = r.flag().ok_or_else(truncated)?;
+    let weighted_bipred_idc = r.u(2).ok_or_else(truncated)?;
+    r.se().ok_or_else(truncated)?; // pic_init_qp_minus26
+    r.se().ok_or_else(truncated)?; // pic_init_qs_minus26
+    r.se().ok_or_else(truncated)?; // chroma_qp_index_offset
+    let deblocking_filter_control_present = r.flag().ok_or_else(truncated)?;
+    r.flag().ok_or_else(truncated)?; // constrained_intra_pred_flag
+    let redundant_pic_cnt_present = r.flag().ok_or_else(truncated)?;
+
+    return Ok(PpsInfo {
+        entropy_coding_mode,
+        bottom_field_pic_order_in_frame_present,
+        weighted_pred,
+        weighted_bipred_idc,
+        deblocking_filter_control_present,
+        redundant_pic_cnt_present,
+    });
+}
+
+fn parse_slice_header(
+    rbsp: &[u8],
+    nal_unit_type: u8,
+    nal_ref_idc: u8,
+    sps: &SpsInfo,
+    pps: &PpsInfo,
+) -> Result<SliceInfo, String> {
+    let truncated = || "slice header truncated".to_string();
+    let mut r = RbspReader::new(rbsp);
+    let first_mb_in_slice = r.ue().ok_or_else(truncated)?;
+    let raw_slice_type = r.ue().ok_or_else(truncated)?;
+    let slice_type = SliceType::from_ue(raw_slice_type)
+        .ok_or_else(|| format!("slice_type {} is invalid", raw_slice_type))?;
+    r.ue().ok_or_else(truncated)?; // pic_parameter_set_id
+    let frame_num = r.u(sps.log2_max_frame_num).ok_or_else(truncated)?;
+    let mut field_pic = false;
+    if !sps.frame_mbs_only {
+        field_pic = r.flag().ok_or_else(truncated)?;
+        if field_pic {
+            r.flag().ok_or_else(truncated)?; // bottom_field_flag
+        }
+    }
+    let is_idr = nal_unit_type == NAL_TYPE_IDR_SLICE;
+    if is_idr {
+        r.ue().ok_or_else(truncated)?; // idr_pic_id
+    }
+    if sps.pic_order_cnt_type == 0 {
+        r.u(sps.log2_max_pic_order_cnt_lsb).ok_or_else(truncated)?;
+        if pps.bottom_field_pic_order_in_frame_present && !field_pic {
+            r.se().ok_or_else(truncated)?; // delta_pic_order_cnt_bottom
+        }
+    }
+    if sps.pic_order_cnt_type == 1 && !sps.delta_pic_order_always_zero {
+        r.se().ok_or_else(truncated)?;
+        if pps.bottom_field_pic_order_in_frame_present && !field_pic {
+            r.se().ok_or_else(truncated)?;
+        }
+    }
+    if pps.redundant_pic_cnt_present {
+        r.ue().ok_or_else(truncated)?; // redundant_pic_cnt
+    }
+    if slice_type == SliceType::B {
+        r.flag().ok_or_else(truncated)?; // direct_spatial_mv_pred_flag
+    }
+    let is_p = matches!(slice_type, SliceType::P | SliceType::SP);
+    // num_ref_idx_active_override_flag
+    if (is_p || slice_type == SliceType::B) && r.flag().ok_or_else(truncated)? {
+        r.ue().ok_or_else(truncated)?; // num_ref_idx_l0_active_minus1
+        if slice_type == SliceType::B {
+            r.ue().ok_or_else(truncated)?; // num_ref_idx_l1_active_minus1
+        }
+    }
+    // ref_pic_list_modification()
+    if !matches!(slice_type, SliceType::I | SliceType::SI) {
+        let lists = if slice_type == SliceType::B { 2 } else { 1 };
+        for _ in 0..lists {
+            if r.flag().ok_or_else(truncated)? {
+                loop {
+                    let idc = r.ue().ok_or_else(truncated)?;
+                    if idc == 3 {
+                        break;
+                    }
+                    if idc > 5 {
+                        return Err(format!("modification_of_pic_nums_idc {} is invalid", idc));
+                    }
+                    r.ue().ok_or_else(truncated)?;
+                }
+            }
+        }
+    }
+    if (pps.weighted_pred && is_p) || (pps.weighted_bipred_idc == 1 && slice_type == SliceType::B) {
+        // The WUP doesn't use weighted prediction, so we don't bother
+        // skipping pred_weight_table().
+        return Err("weighted prediction is not supported".to_string());
+    }
+    if nal_ref_idc != 0 {
+        // dec_ref_pic_marking()
+        if is_idr {
+            r.flag().ok_or_else(truncated)?; // no_output_of_prior_pics_flag
+            r.flag().ok_or_else(truncated)?; // long_term_reference_flag
+        } else if r.flag().ok_or_else(truncated)? {
+            loop {
+                let operation = r.ue().ok_or_else(truncated)?;
+                match operation {
+                    0 => break,
+                    1 | 3 => {
+                        r.ue().ok_or_else(truncated)?;
+                        if operation == 3 {
+                            r.ue().ok_or_else(truncated)?;
+                        }
+                    }
+                    2 | 4 | 6 => {
+                        r.ue().ok_or_else(truncated)?;
+                    }
+                    5 => (),
+                    other => {
+                        return Err(format!(
+                            "memory_management_control_operation {} is invalid",
+                            other
+                        ))
+                    }
+                }
+            }
+        }
+    }
+    if pps.entropy_coding_mode && !matches!(slice_type, SliceType::I | SliceType::SI) {
+        let cabac_init_idc = r.ue().ok_or_else(truncated)?;
+        if cabac_init_idc > 2 {
+            return Err(format!("cabac_init_idc {} is invalid", cabac_init_idc));
+        }
+    }
+    r.se().ok_or_else(truncated)?; // slice_qp_delta
+    if matches!(slice_type, SliceType::SP | SliceType::SI) {
+        if slice_type == SliceType::SP {
+            r.flag().ok_or_else(truncated)?; // sp_for_switch_flag
+        }
+        r.se().ok_or_else(truncated)?; // slice_qs_delta
+    }
+    if pps.deblocking_filter_control_present {
+        let disable_deblocking_filter_idc = r.ue().ok_or_else(truncated)?;
+        if disable_deblocking_filter_idc != 1 {
+            r.se().ok_or_else(truncated)?; // slice_alpha_c0_offset_div2
+            r.se().ok_or_else(truncated)?; // slice_beta_offset_div2
+        }
+    }
+    if pps.entropy_coding_mode {
+        while !r.byte_aligned() {
+            if !r.flag().ok_or_else(truncated)? {
+                return Err("cabac_alignment_one_bit was zero".to_string());
+            }
+        }
+    }
+
+    return Ok(SliceInfo {
+        nal_unit_type,
+        slice_type,
+        first_mb_in_slice,
+        frame_num,
+        data_len: r.bytes_remaining(),
+    });
+}
@@ -0,0 +1,336 @@
+// Copyright 2024 Dalton Durst and the drc-sim-rust contributors
+// SPDX-License-Identifier: MPL-2.0
+
+//! The input report the GamePad sends to the console's HID port.
+//!
+//! The layout comes from the original drc-sim and libdrc. Every
+//! multi-byte field is little-endian except the main button word, which
+//! is big-endian. Fields we don't understand are left as zero.
+//!
+//! | Offset | Size | Field                                   |
+//! |--------|------|-----------------------------------------|
+//! | 0      | 2    | sequence number                         |
+//! | 2      | 2    | buttons (big-endian)                    |
+//! | 4      | 1    | power status                            |
+//! | 5      | 1    | battery charge                          |
+//! | 6      | 8    | left stick x, y, right stick x, y       |
+//! | 14     | 1    | volume slider                           |
+//! | 15     | 6    | accelerometer x, y, z                   |
+//! | 21     | 9    | gyroscope roll, yaw, pitch (24-bit)     |
+//! | 30     | 6    | magnetometer x, y, z                    |
+//! | 36     | 40   | 10 touch samples (x, y)                 |
+//! | 76     | 4    | timestamp                               |
+//! | 80     | 1    | extra buttons                           |
+//! | 127    | 1    | always 0xE0                             |
+
+use std::ops::{BitOr, BitOrAssign};
+
+/// The size of an input report on the wire.
+pub const INPUT_REPORT_SIZE: usize = 128;
+
+/// The number of touch samples the GamePad packs into each report.
+pub const TOUCH_SAMPLES: usize = 10;
+
+/// The largest raw touch panel coordinate.
+pub const TOUCH_MAX: u16 = 0xFFF;
+
+/// The largest touch pressure value.
+pub const TOUCH_PRESSURE_MAX: u16 = 0xFFF;
+
+const TOUCH_PRESSED: u16 = 0x8000;
+const REPORT_TRAILER: u8 = 0xE0;
+
+/// The buttons in the big-endian word at offset 2.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Buttons(pub u16);
+
+impl Buttons {
+    pub const NONE: Buttons = Buttons(0);
+    pub const SYNC: Buttons = Buttons(0x0001);
+    pub const HOME: Buttons = Buttons(0x0002);
+    pub const MINUS: Buttons = Buttons(0x0004);
+    pub const PLUS: Buttons = Buttons(0x0008);
+    pub const R: Buttons = Buttons(0x0010);
+    pub const L: Buttons = Buttons(0x0020);
+    pub const ZR: Buttons = Buttons(0x0040);
+    pub const ZL: Buttons = Buttons(0x0080);
+    pub const DOWN: Buttons = Buttons(0x0100);
+    pub const UP: Buttons = Buttons(0x0200);
+    pub const RIGHT: Buttons = Buttons(0x0400);
+    pub const LEFT: Buttons = Buttons(0x0800);
+    pub const Y: Buttons = Buttons(0x1000);
+    pub const X: Buttons = Buttons(0x2000);
+    pub const B: Buttons = Buttons(0x4000);
+    pub const A: Buttons = Buttons(0x8000);
+
+    pub fn contains(&self, other: Buttons) -> bool {
+        return self.0 & other.0 == other.0;
+    }
+
+    pub fn set(&mut self, other: Buttons, pressed: bool) {
+        if pressed {
+            self.0 |= other.0;
+        } else {
+            self.0 &= !other.0;
+        }
+    }
+}
+
+impl BitOr for Buttons {
+    type Output = Buttons;
+
+    fn bitor(self, rhs: Buttons) -> Buttons {
+        return Buttons(self.0 | rhs.0);
+    }
+}
+
+impl BitOrAssign for Buttons {
+    fn bitor_assign(&mut self, rhs: Buttons) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// The buttons that didn't fit in the main button word, at offset 80.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ExtraButtons(pub u8);
+
+impl ExtraButtons {
+    pub const NONE: ExtraButtons = ExtraButtons(0);
+    pub const TV: ExtraButtons = ExtraButtons(0x20);
+    pub const R3: ExtraButtons = ExtraButtons(0x40);
+    pub const L3: ExtraButtons = ExtraButtons(0x80);
+
+    pub fn contains(&self, other: ExtraButtons) -> bool {
+        return self.0 & other.0 == other.0;
+    }
+
+    pub fn set(&mut self, other: ExtraButtons, pressed: bool) {
+        if pressed {
+            self.0 |= other.0;
+        } else {
+            self.0 &= !other.0;
+        }
+    }
+}
+
+impl BitOr for ExtraButtons {
+    type Output = ExtraButtons;
+
+    fn bitor(self, rhs: ExtraButtons) -> ExtraButtons {
+        return ExtraButtons(self.0 | rhs.0);
+    }
+}
+
+impl BitOrAssign for ExtraButtons {
+    fn bitor_assign(&mut self, rhs: ExtraButtons) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// The power status byte. Only the bits libdrc has names for are
+/// listed here.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PowerStatus(pub u8);
+
+impl PowerStatus {
+    pub const AC_CONNECTED: PowerStatus = PowerStatus(0x01);
+    pub const CHARGING: PowerStatus = PowerStatus(0x02);
+    pub const POWER_BUTTON: PowerStatus = PowerStatus(0x10);
+    pub const HEADPHONES: PowerStatus = PowerStatus(0x20);
+
+    pub fn contains(&self, other: PowerStatus) -> bool {
+        return self.0 & other.0 == other.0;
+    }
+
+    pub fn set(&mut self, other: PowerStatus, on: bool) {
+        if on {
+            self.0 |= other.0;
+        } else {
+            self.0 &= !other.0;
+        }
+    }
+}
+
+/// One analog stick. Axes are 12-bit values centered on Stick::CENTER,
+/// with up and right being larger.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Stick {
+    pub x: u16,
+    pub y: u16,
+}
+
+impl Stick {
+    pub const CENTER: u16 = 0x800;
+
+    /// How far from the center a fully tilted stick reads.
+    pub const RANGE: u16 = 0x700;
+
+    /// Builds a stick position from x and y between -1.0 and 1.0.
+    /// Values outside of that range are clamped.
+    pub fn from_normalized(x: f32, y: f32) -> Stick {
+        return Stick {
+            x: normalized_axis(x),
+            y: normalized_axis(y),
+        };
+    }
+}
+
+impl Default for Stick {
+    fn default() -> Stick {
+        return Stick {
+            x: Stick::CENTER,
+            y: Stick::CENTER,
+        };
+    }
+}
+
+fn normalized_axis(value: f32) -> u16 {
+    let value = if value.is_nan() {
+        0.0
+    } else {
+        value.clamp(-1.0, 1.0)
+    };
+    return (Stick::CENTER as f32 + value * Stick::RANGE as f32).round() as u16;
+}
+
+/// A position on the touch panel in raw panel units, 0 through
+/// TOUCH_MAX on each axis.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TouchPoint {
+    pub x: u16,
+    pub y: u16,
+}
+
+/// A three-axis sensor reading.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Vector3<T> {
+    pub x: T,
+    pub y: T,
+    pub z: T,
+}
+
+/// Everything the GamePad tells the console about its inputs in one
+/// report.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GamePadInputReport {
+    pub sequence: u16,
+    pub buttons: Buttons,
+    pub extra_buttons: ExtraButtons,
+    pub power_status: PowerStatus,
+    pub battery: u8,
+    pub left_stick: Stick,
+    pub right_stick: Stick,
+    pub volume: u8,
+    pub accelerometer: Vector3<i16>,
+    /// Roll, yaw and pitch rates. Only the low 24 bits are sent.
+    pub gyroscope: Vector3<i32>,
+    pub magnetometer: Vector3<i16>,
+    /// The panel is sampled several times per report. Unpressed
+    /// samples are None.
+    pub touch: [Option<TouchPoint>; TOUCH_SAMPLES],
+    /// Pressure of the current touch, 0 through TOUCH_PRESSURE_MAX.
+    pub touch_pressure: u16,
+    /// Microseconds, on the GamePad's clock.
+    pub timestamp: u32,
+}
+
+impl Default for GamePadInputReport {
+    fn default() -> GamePadInputReport {
+        return GamePadInputReport {
+            sequence: 0,
+            buttons: Buttons::NONE,
+            extra_buttons: ExtraButtons::NONE,
+            power_status: PowerStatus::AC_CONNECTED,
+            battery: 0xFF,
+            left_stick: Stick::default(),
+            right_stick: Stick::default(),
+            volume: 0x80,
+            accelerometer: Vector3::default(),
+            gyroscope: Vector3::default(),
+            magnetometer: Vector3::default(),
+            touch: [None; TOUCH_SAMPLES],
+            touch_pressure: 0,
+            timestamp: 0,
+        };
+    }
+}
+
+impl GamePadInputReport {
+    pub fn new() -> GamePadInputReport {
+        return GamePadInputReport::default();
+    }
+
+    /// Reports point for every touch sample, or releases the panel if
+    /// point is None.
+    pub fn set_touch(&mut self, point: Option<TouchPoint>) {
+        self.touch = [point; TOUCH_SAMPLES];
+    }
+
+    pub fn is_touched(&self) -> bool {
+        return self.touch.iter().any(|sample| sample.is_some());
+    }
+
+    pub fn encode(&self) -> [u8; INPUT_REPORT_SIZE] {
+        let mut report = [0u8; INPUT_REPORT_SIZE];
+        report[0..2].copy_from_slice(&self.sequence.to_le_bytes());
+        report[2..4].copy_from_slice(&self.buttons.0.to_be_bytes());
+        report[4] = self.power_status.0;
+        report[5] = self.battery;
+
+        let axes = [
+            self.left_stick.x,
+            self.left_stick.y,
+            self.right_stick.x,
+            self.right_stick.y,
+        ];
+        for (i, axis) in axes.iter().enumerate() {
+            let offset = 6 + i * 2;
+            report[offset..offset + 2].copy_from_slice(&axis.to_le_bytes());
+        }
+
+        report[14] = self.volume;
+        write_i16_vector(&mut report[15..21], &self.accelerometer);
+        for (i, rate) in [self.gyroscope.x, self.gyroscope.y, self.gyroscope.z]
+            .iter()
+            .enumerate()
+        {
+            let offset = 21 + i * 3;
+            report[offset..offset + 3].copy_from_slice(&rate.to_le_bytes()[0..3]);
+        }
+        write_i16_vector(&mut report[30..36], &self.magnetometer);
+
+        // Each sample is an x and y word with the pressed flag in the
+        // top bit. The 12-bit pressure is split into 3-bit pieces
+        // stored in bits 12-14 of the words of the first two samples.
+        let pressure = self.touch_pressure.min(TOUCH_PRESSURE_MAX);
+        for (i, sample) in self.touch.iter().enumerate() {
+            let mut words = match sample {
+                None => [0, 0],
+                Some(point) => [
+                    TOUCH_PRESSED | point.x.min(TOUCH_MAX),
+                    TOUCH_PRESSED | point.y.min(TOUCH_MAX),
+                ],
+            };
+            if i < 2 && self.is_touched() {
+                for (j, word) in words.iter_mut().enumerate() {
+                    let shift = (i * 2 + j) * 3;
+                    *word |= ((pressure >> shift) & 0x7) << 12;
+                }
+            }
+            let offset = 36 + i * 4;
+            report[offset..offset + 2].copy_from_slice(&words[0].to_le_bytes());
+            report[offset + 2..offset + 4].copy_from_slice(&words[1].to_le_bytes());
+        }
+
+        report[76..80].copy_from_slice(&self.timestamp.to_le_bytes());
+        report[80] = self.extra_buttons.0;
+        report[127] = REPORT_TRAILER;
+        return report;
+    }
+}
+
+fn write_i16_vector(destination: &mut [u8], vector: &Vector3<i16>) {
+    destination[0..2].copy_from_slice(&vector.x.to_le_bytes());
+    destination[2..4].copy_from_slice(&vector.y.to_le_bytes());
+    destination[4..6].copy_from_slice(&vector.z.to_le_bytes());
+}
@@ -0,0 +1,201 @@
+// Copyright 2024 Dalton Durst and the drc-sim-rust contributors
+// SPDX-License-Identifier: MPL-2.0
+
+//! Sends input reports to the console at a fixed rate.
+//!
+//! The GamePad doesn't wait for its inputs to change. It sends a report
+//! on every tick, and the console treats a pause in reports as the
+//! GamePad going away. HidSender does the same from a thread of its
+//! own, sending whatever is in a SharedInput at each tick.
+
+use std::{
+    io,
+    net::{SocketAddr, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use log::{debug, warn};
+
+use crate::{hid::GamePadInputReport, join_thread};
+
+/// How often the GamePad sends an input report, about 180 times per
+/// second according to libdrc.
+pub const HID_REPORT_INTERVAL: Duration = Duration::from_micros(5555);
+
+/// Called on every tick, just before the report is sent, with the time
+/// since the previous report. Used for inputs that change on their own,
+/// like motion sensor noise.
+pub type ReportHook = Box<dyn FnMut(&mut GamePadInputReport, Duration) + Send>;
+
+/// The latest input state, shared between whatever produces input and
+/// the HidSender.
+#[derive(Debug, Clone, Default)]
+pub struct SharedInput {
+    report: Arc<Mutex<GamePadInputReport>>,
+}
+
+impl SharedInput {
+    pub fn new() -> SharedInput {
+        return SharedInput::default();
+    }
+
+    pub fn get(&self) -> GamePadInputReport {
+        return self.report.lock().unwrap().clone();
+    }
+
+    pub fn set(&self, report: GamePadInputReport) {
+        *self.report.lock().unwrap() = report;
+    }
+
+    /// Changes the state in place, so that callers only touch the
+    /// fields they own.
+    pub fn update<F: FnOnce(&mut GamePadInputReport)>(&self, f: F) {
+        f(&mut self.report.lock().unwrap());
+    }
+}
+
+/// Counters describing how well the sender kept to its cadence.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HidSenderStats {
+    pub reports_sent: u64,
+    /// Ticks whose report went out more than half an interval after it
+    /// was due.
+    pub late_ticks: u64,
+    /// Ticks that were never sent because the sender fell more than a
+    /// whole interval behind.
+    pub skipped_ticks: u64,
+    pub max_lateness: Duration,
+}
+
+/// A thread sending input reports. It stops when stop() is called or
+/// the HidSender is dropped.
+pub struct HidSender {
+    stop: Arc<AtomicBool>,
+    stats: Arc<Mutex<HidSenderStats>>,
+    thread: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl HidSender {
+    /// Starts sending the contents of input from socket to destination
+    /// once per interval. The sequence number and timestamp in input
+    /// are ignored; the sender keeps its own.
+    pub fn start(
+        socket: UdpSocket,
+        destination: SocketAddr,
+        input: SharedInput,
+        interval: Duration,
+    ) -> HidSender {
+        return HidSender::start_with_hook(socket, destination, input, interval, None);
+    }
+
+    /// Like start, but runs hook on every report before it is sent.
+    pub fn start_with_hook(
+        socket: UdpSocket,
+        destination: SocketAddr,
+        input: SharedInput,
+        interval: Duration,
+        hook: Option<ReportHook>,
+    ) -> HidSender {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stats = Arc::new(Mutex::new(HidSenderStats::default()));
+        let thread_stop = stop.clone();
+        let thread_stats = stats.clone();
+        let thread = thread::spawn(move || {
+            return send_reports(
+                socket,
+                destination,
+                input,
+                interval,
+                hook,
+                &thread_stop,
+                &thread_stats,
+            );
+        });
+        return HidSender {
+            stop,
+            stats,
+            thread: Some(thread),
+        };
+    }
+
+    pub fn stats(&self) -> HidSenderStats {
+        return self.stats.lock().unwrap().clone();
+    }
+
+    /// Stops sending and returns the error that ended the thread early,
+    /// if there was one.
+    pub fn stop(&mut self) -> io::Result<()> {
+        self.stop.store(true, Ordering::Relaxed);
+        return match self.thread.take() {
+            None => Ok(()),
+            Some(thread) => join_thread(thread),
+        };
+    }
+}
+
+impl Drop for HidSender {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+fn send_reports(
+    socket: UdpSocket,
+    destination: SocketAddr,
+    input: SharedInput,
+    interval: Duration,
+    mut hook: Option<ReportHook>,
+    stop: &AtomicBool,
+    stats: &Mutex<HidSenderStats>,
+) -> io::Result<()> {
+    let start = Instant::now();
+    let mut sequence: u16 = 0;
+    // Ticks are scheduled from the start time rather than from the
+    // previous send so that small delays don't add up to drift.
+    let mut deadline = start;
+    let mut last_sent = start;
+    while !stop.load(Ordering::Relaxed) {
+        let now = Instant::now();
+        if now < deadline {
+            thread::sleep(deadline - now);
+        }
+
+        let sent_at = Instant::now();
+        let mut report = input.get();
+        if let Some(hook) = &mut hook {
+            hook(&mut report, sent_at - last_sent);
+        }
+        last_sent = sent_at;
+        report.sequence = sequence;
+        report.timestamp = (sent_at - start).as_micros() as u32;
+        socket.send_to(&report.encode(), destination)?;
+        sequence = sequence.wrapping_add(1);
+
+        let lateness = sent_at - deadline;
+        let mut stats = stats.lock().unwrap();
+        stats.reports_sent += 1;
+        stats.max_lateness = stats.max_lateness.max(lateness);
+        if lateness > interval / 2 {
+            stats.late_ticks += 1;
+            debug!("HID report {} was {:?} late", report.sequence, lateness);
+        }
+        deadline += interval;
+        if lateness > interval {
+            // Sending the missed reports in a burst wouldn't help the
+            // console, so pick the cadence back up from now.
+            let skipped = (lateness.as_nanos() / interval.as_nanos()) as u64;
+            stats.skipped_ticks += skipped;
+            deadline = sent_at + interval;
+            warn!(
+                "HID sender fell {:?} behind, skipped {} reports",
+                lateness, skipped
+            );
+        }
+    }
+    return Ok(());
+}
@@ -5,9 +5,9 @@
 // const WII_VIDEO_HEIGHT: u16 = 480;
 
 use core::fmt;
-use std::cmp::Ordering;
+use std::{cmp::Ordering, time::SystemTime};
 
-use arbitrary_int::{u10, u11, u2, u4};
+use arbitrary_int::{u1, u10, u11, u2, u3, u4};
 use bitter::{BigEndianReader, BitReader};
 use log::error;
 
@@ -25,8 +25,11 @@ pub struct WUPVideoPacket {
     pub timestamp: u32, // 32, counts in microseconds, overflows every ~1.19 hours (64b/8B)
     pub extended_header: [u8; 8], // 64 (128b/16B)
     pub payload: Vec<u8>, // up to 2047 bytes, I've never seen larger than 1672
-                        // minimum 17B, maximum 2063B (but I don't think the WUP actually
-                        // sends dgrams that large)
+    // minimum 17B, maximum 2063B (but I don't think the WUP actually
+    // sends dgrams that large)
+    /// When the kernel received the packet, if the socket it came from
+    /// has receive timestamps enabled. Not part of the packet's bytes.
+    pub received_at: Option<SystemTime>,
 }
 
 impl fmt::Debug for WUPVideoPacket {
@@ -44,10 +47,66 @@ impl fmt::Debug for WUPVideoPacket {
             .field("timestamp", &self.timestamp)
             .field("extended_header", &self.extended_header)
             .field("payload", &format!("size {}", &self.payload.len()))
+            .field("received_at", &self.received_at)
             .finish()
     }
 }
 
+/// The size of the header on every packet the WUP sends to the audio
+/// port.
+pub const WUP_AUDIO_HEADER_SIZE: usize = 8;
+
+/// A packet from the audio port. packet_type 0 carries audio samples
+/// and 1 carries a message about the video format, which we don't
+/// parse yet.
+#[derive(PartialEq, Clone)]
+pub struct WUPAudioPacket {
+    pub format: u3,        // 3
+    pub channel: u1,       // 1
+    pub vibrate: bool,     // 1, the console wants the GamePad to rumble
+    pub packet_type: u1,   // 1
+    pub seq_id: u10,       // 10 (16b/2B)
+    pub payload_size: u16, // 16 (32b/4B)
+    pub timestamp: u32,    // 32, little-endian, microseconds (64b/8B)
+    pub payload: Vec<u8>,
+    /// As in WUPVideoPacket.
+    pub received_at: Option<SystemTime>,
+}
+
+impl fmt::Debug for WUPAudioPacket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WUPAudioPacket")
+            .field("format", &self.format)
+            .field("channel", &self.channel)
+            .field("vibrate", &self.vibrate)
+            .field("packet_type", &self.packet_type)
+            .field("seq_id", &self.seq_id)
+            .field("payload_size", &self.payload_size)
+            .field("timestamp", &self.timestamp)
+            .field("payload", &format!("size {}", &self.payload.len()))
+            .field("received_at", &self.received_at)
+            .finish()
+    }
+}
+
+impl WUPAudioPacket {
+    /// Builds the packet's bytes in the layout process_audio_packet
+    /// reads. The size in the header comes from the payload itself.
+    pub fn encode(&self) -> Vec<u8> {
+        let first: u16 = (u16::from(u8::from(self.format)) << 13)
+            | (u16::from(u8::from(self.channel)) << 12)
+            | ((self.vibrate as u16) << 11)
+            | (u16::from(u8::from(self.packet_type)) << 10)
+            | u16::from(self.seq_id);
+        let mut data = Vec::with_capacity(WUP_AUDIO_HEADER_SIZE + self.payload.len());
+        data.extend(first.to_be_bytes());
+        data.extend((self.payload.len() as u16).to_be_bytes());
+        data.extend(self.timestamp.to_le_bytes());
+        data.extend(&self.payload);
+        return data;
+    }
+}
+
 /// Compares s against t with the RFC 1323 PAWS algorithm. Returns None
 /// when s and t are exactly 0x80000000 apart as it is not possible to
 /// know which is higher. Returns the appropriate ordering for wrapping
@@ -155,14 +214,64 @@ pub fn process_video_packet(packet: &[u8]) -> Option<WUPVideoPacket> {
         magic: u4::new(magic),
         packet_type: u2::new(packet_type),
         seq_id: u10::new(seq_id),
-        init: init,
-        frame_begin: frame_begin,
-        chunk_end: chunk_end,
-        frame_end: frame_end,
-        has_timestamp: has_timestamp,
+        init,
+        frame_begin,
+        chunk_end,
+        frame_end,
+        has_timestamp,
         payload_size: u11::new(expected_payload_size_bytes),
-        timestamp: timestamp,
-        extended_header: extended_header,
+        timestamp,
+        extended_header,
         payload: packet[16..(expected_payload_size_bytes as usize + 16)].to_vec(),
+        received_at: None,
+    });
+}
+
+/// Parses a packet from the audio port, using the layout from the
+/// original drc-sim.
+pub fn process_audio_packet(packet: &[u8]) -> Option<WUPAudioPacket> {
+    if packet.len() < WUP_AUDIO_HEADER_SIZE {
+        error!("packet was too short to process as audio");
+        return None;
+    }
+    let mut bits = BigEndianReader::new(packet);
+    let len = bits.refill_lookahead();
+    assert!(len >= 32);
+
+    let format = bits.peek(3) as u8;
+    bits.consume(3);
+    let channel = bits.peek(1) as u8;
+    bits.consume(1);
+    let vibrate = bits.peek(1) != 0;
+    bits.consume(1);
+    let packet_type = bits.peek(1) as u8;
+    bits.consume(1);
+    let seq_id = bits.peek(10) as u16;
+    bits.consume(10);
+    let payload_size = bits.peek(16) as u16;
+    bits.consume(16);
+
+    let timestamp = u32::from_le_bytes(packet[4..8].try_into().unwrap());
+
+    let expected_packet_len = payload_size as usize + WUP_AUDIO_HEADER_SIZE;
+    if packet.len() < expected_packet_len {
+        error!(
+            "Audio packet was only {} bytes, need {}",
+            packet.len(),
+            expected_packet_len
+        );
+        return None;
+    }
+
+    return Some(WUPAudioPacket {
+        format: u3::new(format),
+        channel: u1::new(channel),
+        vibrate,
+        packet_type: u1::new(packet_type),
+        seq_id: u10::new(seq_id),
+        payload_size,
+        timestamp,
+        payload: packet[WUP_AUDIO_HEADER_SIZE..expected_packet_len].to_vec(),
+        received_at: None,
     });
 }
@@ -0,0 +1,554 @@
+// Copyright 2024 Dalton Durst and the drc-sim-rust contributors
+// SPDX-License-Identifier: MPL-2.0
+
+//! Maps Linux input events (evdev) from an ordinary controller onto
+//! GamePad buttons and sticks.
+//!
+//! The mapper works on RawInputEvents from any iterator, so it can be
+//! driven by a real device (see evdev_device, behind the "evdev"
+//! feature) or by a recorded event stream.
+//!
+//! Mapping files have one rule per line. Codes can be names like
+//! BTN_SOUTH and ABS_X or plain numbers. `#` starts a comment.
+//!
+//! ```text
+//! key BTN_SOUTH B             # a key or button press
+//! axis ABS_Y left_y invert    # an axis onto a stick axis
+//! trigger ABS_Z ZL            # an axis pressed past its midpoint
+//! hat ABS_HAT0X LEFT RIGHT    # an axis which is -1, 0 or 1
+//! range ABS_Z 0 1023          # an axis' range, if not the device's
+//! ```
+
+use core::fmt;
+use std::{collections::HashMap, fs, path::Path};
+
+use log::debug;
+
+use crate::{
+    hid::{Buttons, ExtraButtons, GamePadInputReport, Stick},
+    hid_sender::SharedInput,
+};
+
+pub const EV_SYN: u16 = 0x00;
+pub const EV_KEY: u16 = 0x01;
+pub const EV_ABS: u16 = 0x03;
+pub const SYN_REPORT: u16 = 0x00;
+
+/// The range assumed for an axis when neither the mapping nor the
+/// device says otherwise.
+pub const DEFAULT_AXIS_RANGE: (i32, i32) = (i16::MIN as i32, i16::MAX as i32);
+
+const EVENT_TYPE_NAMES: &[(&str, u16)] =
+    &[("EV_SYN", EV_SYN), ("EV_KEY", EV_KEY), ("EV_ABS", EV_ABS)];
+
+/// Names for the codes that game controllers commonly use.
+const CODE_NAMES: &[(&str, u16)] = &[
+    ("SYN_REPORT", SYN_REPORT),
+    ("ABS_X", 0x00),
+    ("ABS_Y", 0x01),
+    ("ABS_Z", 0x02),
+    ("ABS_RX", 0x03),
+    ("ABS_RY", 0x04),
+    ("ABS_RZ", 0x05),
+    ("ABS_GAS", 0x09),
+    ("ABS_BRAKE", 0x0a),
+    ("ABS_HAT0X", 0x10),
+    ("ABS_HAT0Y", 0x11),
+    ("BTN_SOUTH", 0x130),
+    ("BTN_A", 0x130),
+    ("BTN_EAST", 0x131),
+    ("BTN_B", 0x131),
+    ("BTN_C", 0x132),
+    ("BTN_NORTH", 0x133),
+    ("BTN_X", 0x133),
+    ("BTN_WEST", 0x134),
+    ("BTN_Y", 0x134),
+    ("BTN_Z", 0x135),
+    ("BTN_TL", 0x136),
+    ("BTN_TR", 0x137),
+    ("BTN_TL2", 0x138),
+    ("BTN_TR2", 0x139),
+    ("BTN_SELECT", 0x13a),
+    ("BTN_START", 0x13b),
+    ("BTN_MODE", 0x13c),
+    ("BTN_THUMBL", 0x13d),
+    ("BTN_THUMBR", 0x13e),
+    ("BTN_DPAD_UP", 0x220),
+    ("BTN_DPAD_DOWN", 0x221),
+    ("BTN_DPAD_LEFT", 0x222),
+    ("BTN_DPAD_RIGHT", 0x223),
+];
+
+/// The mapping used when no file is given, for controllers laid out
+/// like an Xbox controller. Buttons go by position, so BTN_SOUTH is B
+/// as it is on the GamePad.
+pub const DEFAULT_MAPPING: &str = "\
+key BTN_SOUTH B
+key BTN_EAST A
+key BTN_NORTH X
+key BTN_WEST Y
+key BTN_TL L
+key BTN_TR R
+key BTN_TL2 ZL
+key BTN_TR2 ZR
+key BTN_SELECT MINUS
+key BTN_START PLUS
+key BTN_MODE HOME
+key BTN_THUMBL L3
+key BTN_THUMBR R3
+key BTN_DPAD_UP UP
+key BTN_DPAD_DOWN DOWN
+key BTN_DPAD_LEFT LEFT
+key BTN_DPAD_RIGHT RIGHT
+axis ABS_X left_x
+axis ABS_Y left_y invert
+axis ABS_RX right_x
+axis ABS_RY right_y invert
+trigger ABS_Z ZL
+trigger ABS_RZ ZR
+hat ABS_HAT0X LEFT RIGHT
+hat ABS_HAT0Y UP DOWN
+";
+
+/// One event as read from an evdev device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawInputEvent {
+    pub event_type: u16,
+    pub code: u16,
+    pub value: i32,
+}
+
+impl RawInputEvent {
+    pub fn new(event_type: u16, code: u16, value: i32) -> RawInputEvent {
+        return RawInputEvent {
+            event_type,
+            code,
+            value,
+        };
+    }
+}
+
+pub struct MappingError {
+    pub kind: MappingErrorKind,
+    pub text: String,
+}
+
+impl MappingError {
+    fn new(kind: MappingErrorKind, text: String) -> MappingError {
+        return MappingError { kind, text };
+    }
+    fn format_error(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}: {}", self.kind, self.text)
+    }
+}
+
+impl fmt::Display for MappingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return self.format_error(f);
+    }
+}
+
+impl fmt::Debug for MappingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return self.format_error(f);
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MappingErrorKind {
+    /// The file couldn't be read.
+    Io,
+    /// A line doesn't have the shape of any rule.
+    Syntax,
+    /// An event type or code isn't a number or a name we know.
+    UnknownCode,
+    /// A GamePad button or stick axis name isn't one we know.
+    UnknownTarget,
+}
+
+/// A GamePad button, from either of the report's button fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ButtonTarget {
+    Main(Buttons),
+    Extra(ExtraButtons),
+}
+
+impl ButtonTarget {
+    pub fn from_name(name: &str) -> Option<ButtonTarget> {
+        let target = match name.to_ascii_uppercase().as_str() {
+            "A" => ButtonTarget::Main(Buttons::A),
+            "B" => ButtonTarget::Main(Buttons::B),
+            "X" => ButtonTarget::Main(Buttons::X),
+            "Y" => ButtonTarget::Main(Buttons::Y),
+            "L" => ButtonTarget::Main(Buttons::L),
+            "R" => ButtonTarget::Main(Buttons::R),
+            "ZL" => ButtonTarget::Main(Buttons::ZL),
+            "ZR" => ButtonTarget::Main(Buttons::ZR),
+            "PLUS" => ButtonTarget::Main(Buttons::PLUS),
+            "MINUS" => ButtonTarget::Main(Buttons::MINUS),
+            "HOME" => ButtonTarget::Main(Buttons::HOME),
+            "SYNC" => ButtonTarget::Main(Buttons::SYNC),
+            "UP" => ButtonTarget::Main(Buttons::UP),
+            "DOWN" => ButtonTarget::Main(Buttons::DOWN),
+            "LEFT" => ButtonTarget::Main(Buttons::LEFT),
+            "RIGHT" => ButtonTarget::Main(Buttons::RIGHT),
+            "L3" => ButtonTarget::Extra(ExtraButtons::L3),
+            "R3" => ButtonTarget::Extra(ExtraButtons::R3),
+            "TV" => ButtonTarget::Extra(ExtraButtons::TV),
+            _ => return None,
+        };
+        return Some(target);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StickAxis {
+    LeftX,
+    LeftY,
+    RightX,
+    RightY,
+}
+
+impl StickAxis {
+    pub fn from_name(name: &str) -> Option<StickAxis> {
+        return match name.to_ascii_lowercase().as_str() {
+            "left_x" => Some(StickAxis::LeftX),
+            "left_y" => Some(StickAxis::LeftY),
+            "right_x" => Some(StickAxis::RightX),
+            "right_y" => Some(StickAxis::RightY),
+            _ => None,
+        };
+    }
+}
+
+/// What an absolute axis drives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AxisTarget {
+    Stick {
+        axis: StickAxis,
+        invert: bool,
+    },
+    /// Pressed while the axis is past the middle of its range.
+    Trigger(ButtonTarget),
+    /// A d-pad reported as an axis: negative presses the first button
+    /// and positive the second.
+    Hat(ButtonTarget, ButtonTarget),
+}
+
+/// The rules read from a mapping file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InputMapping {
+    pub keys: HashMap<u16, ButtonTarget>,
+    pub axes: HashMap<u16, AxisTarget>,
+    pub ranges: HashMap<u16, (i32, i32)>,
+}
+
+impl InputMapping {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<InputMapping, MappingError> {
+        let path = path.as_ref();
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) => {
+                return Err(MappingError::new(
+                    MappingErrorKind::Io,
+                    format!("{}: {}", path.display(), err),
+                ))
+            }
+        };
+        return InputMapping::parse(&text);
+    }
+
+    pub fn parse(text: &str) -> Result<InputMapping, MappingError> {
+        let mut mapping = InputMapping::default();
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.split('#').next().unwrap_or("");
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                [] => {}
+                ["key", code, button] => {
+                    let code = parse_code(code, line_number)?;
+                    mapping
+                        .keys
+                        .insert(code, parse_button(button, line_number)?);
+                }
+                ["axis", code, axis, rest @ ..] if rest.is_empty() || rest == ["invert"] => {
+                    let code = parse_code(code, line_number)?;
+                    let axis = match StickAxis::from_name(axis) {
+                        Some(axis) => axis,
+                        None => {
+                            return Err(MappingError::new(
+                                MappingErrorKind::UnknownTarget,
+                                format!("line {}: unknown stick axis {}", line_number, axis),
+                            ))
+                        }
+                    };
+                    let invert = !rest.is_empty();
+                    mapping
+                        .axes
+                        .insert(code, AxisTarget::Stick { axis, invert });
+                }
+                ["trigger", code, button] => {
+                    let code = parse_code(code, line_number)?;
+                    let button = parse_button(button, line_number)?;
+                    mapping.axes.insert(code, AxisTarget::Trigger(button));
+                }
+                ["hat", code, negative, positive] => {
+                    let code = parse_code(code, line_number)?;
+                    let negative = parse_button(negative, line_number)?;
+                    let positive = parse_button(positive, line_number)?;
+                    mapping
+                        .axes
+                        .insert(code, AxisTarget::Hat(negative, positive));
+                }
+                ["range", code, min, max] => {
+                    let code = parse_code(code, line_number)?;
+                    let (min, max) = match (min.parse::<i32>(), max.parse::<i32>()) {
+                        (Ok(min), Ok(max)) if min < max => (min, max),
+                        _ => {
+                            return Err(MappingError::new(
+                                MappingErrorKind::Syntax,
+                                format!("line {}: bad range {} {}", line_number, min, max),
+                            ))
+                        }
+                    };
+                    mapping.ranges.insert(code, (min, max));
+                }
+                _ => {
+                    return Err(MappingError::new(
+                        MappingErrorKind::Syntax,
+                        format!("line {}: can't understand {:?}", line_number, line.trim()),
+                    ))
+                }
+            }
+        }
+        return Ok(mapping);
+    }
+}
+
+fn parse_button(name: &str, line_number: usize) -> Result<ButtonTarget, MappingError> {
+    return ButtonTarget::from_name(name).ok_or_else(|| {
+        MappingError::new(
+            MappingErrorKind::UnknownTarget,
+            format!("line {}: unknown GamePad button {}", line_number, name),
+        )
+    });
+}
+
+fn parse_code(code: &str, line_number: usize) -> Result<u16, MappingError> {
+    return code_from_name(code).ok_or_else(|| {
+        MappingError::new(
+            MappingErrorKind::UnknownCode,
+            format!("line {}: unknown event code {}", line_number, code),
+        )
+    });
+}
+
+/// Looks up an event code by name, or parses it as a decimal or 0x
+/// prefixed hex number.
+pub fn code_from_name(name: &str) -> Option<u16> {
+    return lookup_name(CODE_NAMES, name);
+}
+
+fn lookup_name(names: &[(&str, u16)], name: &str) -> Option<u16> {
+    if let Some(hex) = name.strip_prefix("0x") {
+        return u16::from_str_radix(hex, 16).ok();
+    }
+    if let Ok(number) = name.parse::<u16>() {
+        return Some(number);
+    }
+    return names
+        .iter()
+        .find(|(known, _)| *known == name)
+        .map(|(_, code)| *code);
+}
+
+/// Parses a recorded event stream with one `TYPE CODE VALUE` event per
+/// line, such as `EV_KEY BTN_SOUTH 1`. Blank lines and `#` comments
+/// are skipped.
+pub fn parse_recorded_events(text: &str) -> Result<Vec<RawInputEvent>, MappingError> {
+    let mut events = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.split('#').next().unwrap_or("");
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            [] => {}
+            [event_type, code, value] => {
+                let event_type = lookup_name(EVENT_TYPE_NAMES, event_type).ok_or_else(|| {
+                    MappingError::new(
+                        MappingErrorKind::UnknownCode,
+                        format!("line {}: unknown event type {}", line_number, event_type),
+                    )
+                })?;
+                let code = parse_code(code, line_number)?;
+                let value = value.parse::<i32>().map_err(|_| {
+                    MappingError::new(
+                        MappingErrorKind::Syntax,
+                        format!("line {}: bad value {}", line_number, value),
+                    )
+                })?;
+                events.push(RawInputEvent::new(event_type, code, value));
+            }
+            _ => {
+                return Err(MappingError::new(
+                    MappingErrorKind::Syntax,
+                    format!("line {}: expected TYPE CODE VALUE", line_number),
+                ))
+            }
+        }
+    }
+    return Ok(events);
+}
+
+/// The part of the GamePad's state that a mapped controller controls.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MappedState {
+    pub buttons: Buttons,
+    pub extra_buttons: ExtraButtons,
+    pub left_stick: Stick,
+    pub right_stick: Stick,
+}
+
+impl MappedState {
+    /// Copies this state into report, leaving fields that the
+    /// controller doesn't control alone.
+    pub fn apply_to(&self, report: &mut GamePadInputReport) {
+        report.buttons = self.buttons;
+        report.extra_buttons = self.extra_buttons;
+        report.left_stick = self.left_stick;
+        report.right_stick = self.right_stick;
+    }
+}
+
+/// Turns a stream of RawInputEvents into MappedState. Like evdev
+/// itself, changes are collected until a SYN_REPORT.
+pub struct InputMapper {
+    mapping: InputMapping,
+    /// Axis ranges reported by the device, used for axes that the
+    /// mapping doesn't give a range for.
+    device_ranges: HashMap<u16, (i32, i32)>,
+    state: MappedState,
+    /// The buttons held down and the event type and code holding each.
+    /// A button can have more than one source, such as BTN_TL2 and
+    /// ABS_Z both on ZL, and stays pressed until all of them let go.
+    held: Vec<(u16, u16, ButtonTarget)>,
+}
+
+impl Default for InputMapper {
+    fn default() -> InputMapper {
+        return InputMapper::new(InputMapping::parse(DEFAULT_MAPPING).unwrap());
+    }
+}
+
+impl InputMapper {
+    pub fn new(mapping: InputMapping) -> InputMapper {
+        return InputMapper {
+            mapping,
+            device_ranges: HashMap::new(),
+            state: MappedState::default(),
+            held: Vec::new(),
+        };
+    }
+
+    pub fn mapping(&self) -> &InputMapping {
+        return &self.mapping;
+    }
+
+    pub fn set_device_range(&mut self, code: u16, min: i32, max: i32) {
+        if min < max {
+            self.device_ranges.insert(code, (min, max));
+        }
+    }
+
+    pub fn range(&self, code: u16) -> (i32, i32) {
+        return self
+            .mapping
+            .ranges
+            .get(&code)
+            .or_else(|| self.device_ranges.get(&code))
+            .copied()
+            .unwrap_or(DEFAULT_AXIS_RANGE);
+    }
+
+    pub fn state(&self) -> &MappedState {
+        return &self.state;
+    }
+
+    /// Applies event to the state. Returns true if event was a
+    /// SYN_REPORT, meaning the state is ready to be sent.
+    pub fn handle(&mut self, event: RawInputEvent) -> bool {
+        match event.event_type {
+            EV_SYN => return event.code == SYN_REPORT,
+            EV_KEY => {
+                if let Some(button) = self.mapping.keys.get(&event.code).copied() {
+                    // 2 is autorepeat, which still means held.
+                    self.set_button((EV_KEY, event.code), button, event.value != 0);
+                }
+            }
+            EV_ABS => {
+                if let Some(target) = self.mapping.axes.get(&event.code).copied() {
+                    self.handle_axis(event.code, event.value, target);
+                }
+            }
+            _ => debug!("Ignoring input event {:?}", event),
+        }
+        return false;
+    }
+
+    /// Handles every event, updating input after each SYN_REPORT.
+    pub fn run<I: IntoIterator<Item = RawInputEvent>>(&mut self, events: I, input: &SharedInput) {
+        for event in events {
+            if self.handle(event) {
+                input.update(|report| self.state.apply_to(report));
+            }
+        }
+    }
+
+    fn handle_axis(&mut self, code: u16, value: i32, target: AxisTarget) {
+        match target {
+            AxisTarget::Stick { axis, invert } => {
+                let (min, max) = self.range(code);
+                let span = max as i64 - min as i64;
+                let mut normalized =
+                    ((value as i64 - min as i64) as f32 / span as f32).clamp(0.0, 1.0) * 2.0 - 1.0;
+                if invert {
+                    normalized = -normalized;
+                }
+                let position = Stick::from_normalized(normalized, 0.0).x;
+                match axis {
+                    StickAxis::LeftX => self.state.left_stick.x = position,
+                    StickAxis::LeftY => self.state.left_stick.y = position,
+                    StickAxis::RightX => self.state.right_stick.x = position,
+                    StickAxis::RightY => self.state.right_stick.y = position,
+                }
+            }
+            AxisTarget::Trigger(button) => {
+                let (min, max) = self.range(code);
+                let midpoint = (min as i64 + max as i64) / 2;
+                self.set_button((EV_ABS, code), button, value as i64 > midpoint);
+            }
+            AxisTarget::Hat(negative, positive) => {
+                self.set_button((EV_ABS, code), negative, value < 0);
+                self.set_button((EV_ABS, code), positive, value > 0);
+            }
+        }
+    }
+
+    /// Records whether source, an event type and code, holds button,
+    /// and presses button if anything holds it.
+    fn set_button(&mut self, source: (u16, u16), button: ButtonTarget, pressed: bool) {
+        let (event_type, code) = source;
+        let holds = |held: &(u16, u16, ButtonTarget)| *held == (event_type, code, button);
+        if !pressed {
+            self.held.retain(|held| !holds(held));
+        } else if !self.held.iter().any(holds) {
+            self.held.push((event_type, code, button));
+        }
+        let pressed = self.held.iter().any(|(_, _, held)| *held == button);
+        match button {
+            ButtonTarget::Main(button) => self.state.buttons.set(button, pressed),
+            ButtonTarget::Extra(button) => self.state.extra_buttons.set(button, pressed),
+        }
+    }
+}
@@ -0,0 +1,572 @@
+// Copyright 2024 Dalton Durst and the drc-sim-rust contributors
+// SPDX-License-Identifier: MPL-2.0
+
+//! Recording and replaying input as simple text scripts.
+//!
+//! A script is a list of statements, one per line, which run in order.
+//! Statements with a duration take that long before the next one runs
+//! and undo themselves at the end.
+//!
+//! ```text
+//! press A 100ms         # hold A for 100 ms
+//! press B               # hold B until released
+//! wait 1s
+//! release B
+//! touch 300 200 50ms    # touch pixel 300,200 of the video frame
+//! stick left 0 1 250ms  # push the left stick up
+//! tilt left 30          # tilt the GamePad 30 degrees to the left
+//! tilt flat
+//! tilt right 10 100ms   # tilt 10 degrees right, then back to flat
+//! ```
+//!
+//! Time is measured with the timestamps of received video frames
+//! rather than the local clock, so a script lines up with what the
+//! console was showing no matter how the simulator was scheduled.
+
+use core::fmt;
+use std::{cmp::Ordering, fmt::Write, fs, path::Path};
+
+use crate::{
+    hid::{Buttons, ExtraButtons, GamePadInputReport, Stick, TouchPoint, Vector3},
+    hid_sender::SharedInput,
+    incoming_packet_parser::u32_paws_compare,
+    input_mapping::ButtonTarget,
+    motion::{ImuNoise, MotionSource, Quaternion},
+    touch::TouchScreen,
+};
+
+const X_AXIS: Vector3<f64> = Vector3 {
+    x: 1.0,
+    y: 0.0,
+    z: 0.0,
+};
+const Y_AXIS: Vector3<f64> = Vector3 {
+    x: 0.0,
+    y: 1.0,
+    z: 0.0,
+};
+
+/// How far `tilt` turns the GamePad when no angle is given.
+pub const DEFAULT_TILT_DEGREES: f64 = 30.0;
+
+pub struct ScriptError {
+    pub kind: ScriptErrorKind,
+    pub text: String,
+}
+
+impl ScriptError {
+    fn new(kind: ScriptErrorKind, text: String) -> ScriptError {
+        return ScriptError { kind, text };
+    }
+    fn format_error(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}: {}", self.kind, self.text)
+    }
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return self.format_error(f);
+    }
+}
+
+impl fmt::Debug for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return self.format_error(f);
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ScriptErrorKind {
+    /// The script file couldn't be read.
+    Io,
+    /// A line isn't a statement we know.
+    Syntax,
+    /// A number or duration couldn't be parsed.
+    BadNumber,
+    /// A button name isn't one we know.
+    UnknownButton,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StickSide {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TiltDirection {
+    Flat,
+    Left,
+    Right,
+    Forward,
+    Back,
+}
+
+impl TiltDirection {
+    fn from_name(name: &str) -> Option<TiltDirection> {
+        return match name {
+            "flat" => Some(TiltDirection::Flat),
+            "left" => Some(TiltDirection::Left),
+            "right" => Some(TiltDirection::Right),
+            "forward" => Some(TiltDirection::Forward),
+            "back" => Some(TiltDirection::Back),
+            _ => None,
+        };
+    }
+
+    /// The orientation of a GamePad that started out flat and was
+    /// tilted by degrees in this direction.
+    pub fn orientation(&self, degrees: f64) -> Quaternion {
+        let (axis, degrees) = match self {
+            TiltDirection::Flat => return Quaternion::IDENTITY,
+            // Tilting left lowers the left edge: a turn around y.
+            TiltDirection::Left => (Y_AXIS, -degrees),
+            TiltDirection::Right => (Y_AXIS, degrees),
+            // Tilting forward lowers the top edge: a turn around x.
+            TiltDirection::Forward => (X_AXIS, -degrees),
+            TiltDirection::Back => (X_AXIS, degrees),
+        };
+        return Quaternion::from_axis_angle(axis, degrees);
+    }
+}
+
+/// One change to the input.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScriptAction {
+    Press(ButtonTarget),
+    Release(ButtonTarget),
+    /// Touch a pixel on the video frame.
+    Touch {
+        x: f32,
+        y: f32,
+    },
+    Untouch,
+    /// Move a stick, with x and y from -1.0 to 1.0.
+    Stick {
+        side: StickSide,
+        x: f32,
+        y: f32,
+    },
+    Tilt {
+        direction: TiltDirection,
+        degrees: f64,
+    },
+}
+
+/// An action and when it happens, in microseconds from the start of
+/// the script.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScriptEvent {
+    pub at_us: u64,
+    pub action: ScriptAction,
+}
+
+/// A parsed script, flattened into a timeline.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InputScript {
+    /// Sorted by at_us. Events at the same time keep script order.
+    pub events: Vec<ScriptEvent>,
+    /// When the last statement finishes.
+    pub length_us: u64,
+}
+
+impl InputScript {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<InputScript, ScriptError> {
+        let path = path.as_ref();
+        return match fs::read_to_string(path) {
+            Ok(text) => InputScript::parse(&text),
+            Err(err) => Err(ScriptError::new(
+                ScriptErrorKind::Io,
+                format!("{}: {}", path.display(), err),
+            )),
+        };
+    }
+
+    pub fn parse(text: &str) -> Result<InputScript, ScriptError> {
+        let mut script = InputScript::default();
+        let mut now: u64 = 0;
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.split('#').next().unwrap_or("");
+            let words: Vec<&str> = line.split_whitespace().collect();
+            // (action, undo at the end of the duration, duration word)
+            let (action, undo, duration) = match words.as_slice() {
+                [] => continue,
+                ["wait", duration] => {
+                    now += parse_duration(duration, line_number)?;
+                    continue;
+                }
+                ["press", button, rest @ ..] if rest.len() <= 1 => {
+                    let button = parse_button(button, line_number)?;
+                    (
+                        ScriptAction::Press(button),
+                        ScriptAction::Release(button),
+                        rest.first(),
+                    )
+                }
+                ["release", button] => {
+                    let button = parse_button(button, line_number)?;
+                    (
+                        ScriptAction::Release(button),
+                        ScriptAction::Release(button),
+                        None,
+                    )
+                }
+                ["touch", x, y, rest @ ..] if rest.len() <= 1 => (
+                    ScriptAction::Touch {
+                        x: parse_number(x, line_number)?,
+                        y: parse_number(y, line_number)?,
+                    },
+                    ScriptAction::Untouch,
+                    rest.first(),
+                ),
+                ["untouch"] => (ScriptAction::Untouch, ScriptAction::Untouch, None),
+                ["stick", side, x, y, rest @ ..] if rest.len() <= 1 => {
+                    let side = match *side {
+                        "left" => StickSide::Left,
+                        "right" => StickSide::Right,
+                        _ => return Err(syntax_error(line_number, line)),
+                    };
+                    (
+                        ScriptAction::Stick {
+                            side,
+                            x: parse_number(x, line_number)?,
+                            y: parse_number(y, line_number)?,
+                        },
+                        ScriptAction::Stick {
+                            side,
+                            x: 0.0,
+                            y: 0.0,
+                        },
+                        rest.first(),
+                    )
+                }
+                ["tilt", direction, rest @ ..] if rest.len() <= 2 => {
+                    let direction = match TiltDirection::from_name(direction) {
+                        Some(direction) => direction,
+                        None => return Err(syntax_error(line_number, line)),
+                    };
+                    // The angle is a bare number and the duration has a
+                    // unit, so a lone word can be told apart.
+                    let (degrees, duration) = match rest {
+                        [] => (DEFAULT_TILT_DEGREES, None),
+                        [word] if word.parse::<f64>().is_ok() => {
+                            (parse_number(word, line_number)?, None)
+                        }
+                        [duration] => (DEFAULT_TILT_DEGREES, Some(duration)),
+                        [degrees, duration] => {
+                            (parse_number(degrees, line_number)?, Some(duration))
+                        }
+                        _ => return Err(syntax_error(line_number, line)),
+                    };
+                    let flat = ScriptAction::Tilt {
+                        direction: TiltDirection::Flat,
+                        degrees: 0.0,
+                    };
+                    (ScriptAction::Tilt { direction, degrees }, flat, duration)
+                }
+                _ => return Err(syntax_error(line_number, line)),
+            };
+
+            script.events.push(ScriptEvent { at_us: now, action });
+            if let Some(duration) = duration {
+                now += parse_duration(duration, line_number)?;
+                script.events.push(ScriptEvent {
+                    at_us: now,
+                    action: undo,
+                });
+            }
+        }
+        script.length_us = now;
+        return Ok(script);
+    }
+}
+
+fn syntax_error(line_number: usize, line: &str) -> ScriptError {
+    return ScriptError::new(
+        ScriptErrorKind::Syntax,
+        format!("line {}: can't understand {:?}", line_number, line.trim()),
+    );
+}
+
+fn parse_button(name: &str, line_number: usize) -> Result<ButtonTarget, ScriptError> {
+    return ButtonTarget::from_name(name).ok_or_else(|| {
+        ScriptError::new(
+            ScriptErrorKind::UnknownButton,
+            format!("line {}: unknown GamePad button {}", line_number, name),
+        )
+    });
+}
+
+fn parse_number<T: std::str::FromStr>(word: &str, line_number: usize) -> Result<T, ScriptError> {
+    return word.parse::<T>().map_err(|_| {
+        ScriptError::new(
+            ScriptErrorKind::BadNumber,
+            format!("line {}: bad number {}", line_number, word),
+        )
+    });
+}
+
+/// Parses durations like 500us, 100ms or 2s into microseconds.
+fn parse_duration(word: &str, line_number: usize) -> Result<u64, ScriptError> {
+    let (number, scale) = if let Some(number) = word.strip_suffix("us") {
+        (number, 1.0)
+    } else if let Some(number) = word.strip_suffix("ms") {
+        (number, 1_000.0)
+    } else if let Some(number) = word.strip_suffix('s') {
+        (number, 1_000_000.0)
+    } else {
+        return Err(ScriptError::new(
+            ScriptErrorKind::BadNumber,
+            format!("line {}: {} needs a unit (us, ms or s)", line_number, word),
+        ));
+    };
+    let number: f64 = parse_number(number, line_number)?;
+    if !number.is_finite() || number < 0.0 {
+        return Err(ScriptError::new(
+            ScriptErrorKind::BadNumber,
+            format!("line {}: bad duration {}", line_number, word),
+        ));
+    }
+    return Ok((number * scale).round() as u64);
+}
+
+/// Keeps time from the timestamps of received video frames. The first
+/// frame is time zero.
+#[derive(Debug, Default)]
+struct FrameClock {
+    last_timestamp: Option<u32>,
+    elapsed_us: u64,
+}
+
+impl FrameClock {
+    /// Returns microseconds since the first frame. Frames older than
+    /// the newest one seen don't move the clock.
+    fn on_frame(&mut self, timestamp: u32) -> u64 {
+        match self.last_timestamp {
+            None => self.last_timestamp = Some(timestamp),
+            Some(last) => {
+                if u32_paws_compare(timestamp, last) == Some(Ordering::Greater) {
+                    self.elapsed_us += timestamp.wrapping_sub(last) as u64;
+                    self.last_timestamp = Some(timestamp);
+                }
+            }
+        }
+        return self.elapsed_us;
+    }
+}
+
+/// Plays an InputScript into a SharedInput as video frames arrive.
+pub struct ScriptPlayer {
+    script: InputScript,
+    next_event: usize,
+    clock: FrameClock,
+    touch_screen: TouchScreen,
+    motion: MotionSource,
+}
+
+impl ScriptPlayer {
+    pub fn new(script: InputScript) -> ScriptPlayer {
+        let mut motion = MotionSource::at_rest();
+        // Scripts should do the same thing every time.
+        motion.noise = ImuNoise::NONE;
+        return ScriptPlayer {
+            script,
+            next_event: 0,
+            clock: FrameClock::default(),
+            touch_screen: TouchScreen::default(),
+            motion,
+        };
+    }
+
+    /// Sets how pixel positions in touch statements are mapped.
+    pub fn set_touch_screen(&mut self, touch_screen: TouchScreen) {
+        self.touch_screen = touch_screen;
+    }
+
+    pub fn is_finished(&self) -> bool {
+        return self.next_event >= self.script.events.len();
+    }
+
+    /// Advances the script to the time of the video frame with
+    /// timestamp and applies every action that is now due. Returns how
+    /// many actions were applied.
+    pub fn on_frame(&mut self, timestamp: u32, input: &SharedInput) -> usize {
+        let now = self.clock.on_frame(timestamp);
+        let start = self.next_event;
+        while let Some(event) = self.script.events.get(self.next_event) {
+            if event.at_us > now {
+                break;
+            }
+            self.next_event += 1;
+        }
+        let due = &self.script.events[start..self.next_event];
+        if !due.is_empty() {
+            let touch_screen = self.touch_screen;
+            let motion = &mut self.motion;
+            input.update(|report| {
+                for event in due {
+                    apply_action(report, event.action, &touch_screen, motion);
+                }
+            });
+        }
+        return due.len();
+    }
+}
+
+fn apply_action(
+    report: &mut GamePadInputReport,
+    action: ScriptAction,
+    touch_screen: &TouchScreen,
+    motion: &mut MotionSource,
+) {
+    match action {
+        ScriptAction::Press(ButtonTarget::Main(button)) => report.buttons.set(button, true),
+        ScriptAction::Press(ButtonTarget::Extra(button)) => report.extra_buttons.set(button, true),
+        ScriptAction::Release(ButtonTarget::Main(button)) => report.buttons.set(button, false),
+        ScriptAction::Release(ButtonTarget::Extra(button)) => {
+            report.extra_buttons.set(button, false)
+        }
+        ScriptAction::Touch { x, y } => {
+            report.set_touch(touch_screen.to_raw(x, y));
+            report.touch_pressure = if report.is_touched() { 0x800 } else { 0 };
+        }
+        ScriptAction::Untouch => touch_screen.release(report),
+        ScriptAction::Stick { side, x, y } => {
+            let stick = Stick::from_normalized(x, y);
+            match side {
+                StickSide::Left => report.left_stick = stick,
+                StickSide::Right => report.right_stick = stick,
+            }
+        }
+        ScriptAction::Tilt { direction, degrees } => {
+            motion.orientation = direction.orientation(degrees);
+            motion.apply_to(report);
+        }
+    }
+}
+
+/// Writes a script that reproduces the buttons, sticks and touches
+/// seen in a series of reports. Motion isn't recorded.
+pub struct ScriptRecorder {
+    clock: FrameClock,
+    last_report: GamePadInputReport,
+    last_event_us: u64,
+    touch_screen: TouchScreen,
+    script: String,
+}
+
+impl Default for ScriptRecorder {
+    fn default() -> ScriptRecorder {
+        return ScriptRecorder::new();
+    }
+}
+
+impl ScriptRecorder {
+    pub fn new() -> ScriptRecorder {
+        return ScriptRecorder {
+            clock: FrameClock::default(),
+            last_report: GamePadInputReport::new(),
+            last_event_us: 0,
+            touch_screen: TouchScreen::default(),
+            script: String::new(),
+        };
+    }
+
+    /// Records the state of report at the time of the video frame with
+    /// timestamp.
+    pub fn on_frame(&mut self, timestamp: u32, report: &GamePadInputReport) {
+        let now = self.clock.on_frame(timestamp);
+        let mut lines = Vec::new();
+
+        for bit in 0..16 {
+            let button = Buttons(1 << bit);
+            let was = self.last_report.buttons.contains(button);
+            let is = report.buttons.contains(button);
+            if was != is {
+                lines.extend(button_line(is, ButtonTarget::Main(button)));
+            }
+        }
+        for bit in 0..8 {
+            let button = ExtraButtons(1 << bit);
+            let was = self.last_report.extra_buttons.contains(button);
+            let is = report.extra_buttons.contains(button);
+            if was != is {
+                lines.extend(button_line(is, ButtonTarget::Extra(button)));
+            }
+        }
+        for (side, last, stick) in [
+            ("left", self.last_report.left_stick, report.left_stick),
+            ("right", self.last_report.right_stick, report.right_stick),
+        ] {
+            if last != stick {
+                lines.push(format!(
+                    "stick {} {} {}",
+                    side,
+                    stick_fraction(stick.x),
+                    stick_fraction(stick.y)
+                ));
+            }
+        }
+        if report.touch[0] != self.last_report.touch[0] {
+            match report.touch[0] {
+                None => lines.push("untouch".to_string()),
+                Some(point) => {
+                    let (x, y) = self.raw_to_pixel(point);
+                    lines.push(format!("touch {} {}", x, y));
+                }
+            }
+        }
+
+        if !lines.is_empty() {
+            if now > self.last_event_us {
+                let _ = writeln!(self.script, "wait {}us", now - self.last_event_us);
+            }
+            for line in lines {
+                let _ = writeln!(self.script, "{}", line);
+            }
+            self.last_event_us = now;
+        }
+        self.last_report = report.clone();
+    }
+
+    pub fn script(&self) -> &str {
+        return &self.script;
+    }
+
+    fn raw_to_pixel(&self, point: TouchPoint) -> (f32, f32) {
+        let calibration = &self.touch_screen.calibration;
+        let fraction = |value: u16, start: u16, end: u16| {
+            (value as f32 - start as f32) / (end as f32 - start as f32)
+        };
+        let x = fraction(point.x, calibration.left, calibration.right)
+            * (self.touch_screen.width - 1) as f32;
+        let y = fraction(point.y, calibration.top, calibration.bottom)
+            * (self.touch_screen.height - 1) as f32;
+        return (x.round(), y.round());
+    }
+}
+
+/// Returns None for bits that aren't a known button.
+fn button_line(pressed: bool, button: ButtonTarget) -> Option<String> {
+    let verb = if pressed { "press" } else { "release" };
+    return button_name(button).map(|name| format!("{} {}", verb, name));
+}
+
+fn stick_fraction(axis: u16) -> f32 {
+    let fraction = (axis as f32 - Stick::CENTER as f32) / Stick::RANGE as f32;
+    // Three decimal places is finer than the stick's resolution.
+    return (fraction * 1000.0).round() / 1000.0;
+}
+
+/// The name ButtonTarget::from_name accepts for button.
+pub fn button_name(button: ButtonTarget) -> Option<&'static str> {
+    const NAMES: &[&str] = &[
+        "A", "B", "X", "Y", "L", "R", "ZL", "ZR", "PLUS", "MINUS", "HOME", "SYNC", "UP", "DOWN",
+        "LEFT", "RIGHT", "L3", "R3", "TV",
+    ];
+    return NAMES
+        .iter()
+        .find(|name| ButtonTarget::from_name(name) == Some(button))
+        .copied();
+}
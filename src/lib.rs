@@ -1,6 +1,30 @@
+#[cfg(feature = "tokio")]
+pub mod async_net;
+pub mod cmd;
+pub mod config;
+pub mod decode;
+pub mod device_profile;
+#[cfg(feature = "evdev")]
+pub mod evdev_device;
+pub mod feedback;
+pub mod h264;
+pub mod h264_validator;
+pub mod hid;
+pub mod hid_sender;
 pub mod incoming_packet_parser;
+pub mod input_mapping;
+pub mod input_script;
+pub mod mic;
+pub mod mjpeg_server;
+pub mod motion;
+pub mod msg;
 pub mod packet_organizer;
+pub mod packet_queue;
+pub mod rtp;
+pub mod session;
 pub mod sockets;
+pub mod touch;
+pub mod video_stats;
 
 /// The largest dgram that we expect to receive from the WUP.
 /// 2063 is the maximum theoretical size of the WUP video packet. I've
@@ -8,6 +32,35 @@ pub mod sockets;
 /// doesn't hurt _much_
 pub const WUP_VID_PACKET_BUFFER_SIZE: usize = 2048;
 
+/// The size of the GamePad's screen, and of the picture described by the
+/// SPS in h264::SPS (from the original drc-sim) after cropping: 54x30
+/// macroblocks with frame_crop_right_offset 5, so 864 - 10 = 854 wide.
+/// The commented-out WII_VIDEO_WIDTH in incoming_packet_parser says 848,
+/// but the stream doesn't decode to that.
+pub const WUP_VIDEO_WIDTH: u32 = 854;
+pub const WUP_VIDEO_HEIGHT: u32 = 480;
+
 /// The amount of time, according to dgram timestamps, after which a
 /// frame is considered no longer completeable.
 pub const STALE_FRAME_THRESHOLD: u32 = 16683 * 5; // 5 frames at ~16ms per frame
+
+/// Formats bytes as space-separated hex for logging packets we don't
+/// understand.
+pub(crate) fn hex(data: &[u8]) -> String {
+    return data
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<String>>()
+        .join(" ");
+}
+
+/// Waits for a worker thread and returns its result. A thread that
+/// panicked comes back as an error, so stopping from Drop can't panic.
+pub(crate) fn join_thread(
+    thread: std::thread::JoinHandle<std::io::Result<()>>,
+) -> std::io::Result<()> {
+    return match thread.join() {
+        Ok(result) => result,
+        Err(_) => Err(std::io::Error::other("worker thread panicked")),
+    };
+}
@@ -0,0 +1,386 @@
+// Copyright 2024 Dalton Durst and the drc-sim-rust contributors
+// SPDX-License-Identifier: MPL-2.0
+
+//! Microphone audio from the GamePad to the console.
+//!
+//! Mic audio goes to the console's audio port in packets with the same
+//! header as the audio the console sends us. The format number, sample
+//! rate and packet size the console expects haven't been confirmed
+//! from captures, so they are kept in MicConfig where they can be
+//! corrected. Samples are signed 16-bit little-endian mono PCM.
+
+use core::fmt;
+use std::{
+    fs, io,
+    net::{SocketAddr, UdpSocket},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use arbitrary_int::{u1, u10, u3};
+use log::debug;
+
+use crate::{incoming_packet_parser::WUPAudioPacket, join_thread};
+
+/// The most 16-bit samples whose size in bytes fits in a packet
+/// header's payload_size.
+const MAX_SAMPLES_PER_PACKET: usize = u16::MAX as usize / 2;
+
+/// How the outgoing mic audio is framed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MicConfig {
+    /// The format field in each packet's header.
+    pub format: u3,
+    pub sample_rate: u32,
+    pub samples_per_packet: usize,
+}
+
+impl Default for MicConfig {
+    fn default() -> MicConfig {
+        return MicConfig {
+            format: u3::new(6),
+            sample_rate: 16000,
+            samples_per_packet: 256,
+        };
+    }
+}
+
+impl MicConfig {
+    /// How long the audio in one packet lasts.
+    pub fn packet_duration(&self) -> Duration {
+        let micros = self.samples_per_packet as u64 * 1_000_000 / self.sample_rate as u64;
+        return Duration::from_micros(micros);
+    }
+
+    /// Rejects configs that can't be sent: no sample rate, empty
+    /// packets, or packets too big for the header's size field.
+    fn check(&self) -> io::Result<()> {
+        if self.sample_rate == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "mic sample_rate must not be 0",
+            ));
+        }
+        if self.samples_per_packet == 0 || self.samples_per_packet > MAX_SAMPLES_PER_PACKET {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "mic samples_per_packet is {}, want 1 to {}",
+                    self.samples_per_packet, MAX_SAMPLES_PER_PACKET
+                ),
+            ));
+        }
+        return Ok(());
+    }
+}
+
+pub struct WavError {
+    pub kind: WavErrorKind,
+    pub text: String,
+}
+
+impl WavError {
+    fn new(kind: WavErrorKind, text: String) -> WavError {
+        return WavError { kind, text };
+    }
+    fn format_error(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}: {}", self.kind, self.text)
+    }
+}
+
+impl fmt::Display for WavError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return self.format_error(f);
+    }
+}
+
+impl fmt::Debug for WavError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return self.format_error(f);
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum WavErrorKind {
+    Io,
+    /// The file isn't a RIFF WAVE file, or is cut short.
+    NotWav,
+    /// The file is a WAV, but not 16-bit PCM.
+    Unsupported,
+}
+
+/// The contents of a 16-bit PCM WAV file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WavAudio {
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// Interleaved samples.
+    pub samples: Vec<i16>,
+}
+
+impl WavAudio {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<WavAudio, WavError> {
+        let path = path.as_ref();
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(err) => {
+                return Err(WavError::new(
+                    WavErrorKind::Io,
+                    format!("{}: {}", path.display(), err),
+                ))
+            }
+        };
+        return WavAudio::parse(&data);
+    }
+
+    pub fn parse(data: &[u8]) -> Result<WavAudio, WavError> {
+        let not_wav = |text: &str| WavError::new(WavErrorKind::NotWav, String::from(text));
+        if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+            return Err(not_wav("missing RIFF WAVE header"));
+        }
+        let mut format: Option<(u16, u16, u32, u16)> = None;
+        let mut offset = 12;
+        while offset + 8 <= data.len() {
+            let id = &data[offset..offset + 4];
+            let size = u32::from_le_bytes(data[offset + 4..offset + 8].try_into().unwrap());
+            let start = offset + 8;
+            let end = start.saturating_add(size as usize);
+            if end > data.len() {
+                return Err(not_wav("chunk runs past the end of the file"));
+            }
+            let chunk = &data[start..end];
+            match id {
+                b"fmt " => {
+                    if chunk.len() < 16 {
+                        return Err(not_wav("fmt chunk is too short"));
+                    }
+                    let word = |i: usize| u16::from_le_bytes([chunk[i], chunk[i + 1]]);
+                    let sample_rate = u32::from_le_bytes(chunk[4..8].try_into().unwrap());
+                    format = Some((word(0), word(2), sample_rate, word(14)));
+                }
+                b"data" => {
+                    let (audio_format, channels, sample_rate, bits) = match format {
+                        Some(format) => format,
+                        None => return Err(not_wav("data chunk before fmt chunk")),
+                    };
+                    if audio_format != 1 || bits != 16 || channels == 0 || sample_rate == 0 {
+                        return Err(WavError::new(
+                            WavErrorKind::Unsupported,
+                            format!(
+                                "format {} with {} channels of {} bits at {} Hz, need 16-bit PCM",
+                                audio_format, channels, bits, sample_rate
+                            ),
+                        ));
+                    }
+                    let samples = chunk
+                        .chunks_exact(2)
+                        .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+                        .collect();
+                    return Ok(WavAudio {
+                        sample_rate,
+                        channels,
+                        samples,
+                    });
+                }
+                _ => {}
+            }
+            // Chunks are padded to an even length.
+            offset = end + (size as usize & 1);
+        }
+        return Err(not_wav("no data chunk"));
+    }
+
+    /// Mixes the channels down to one and resamples to sample_rate.
+    pub fn to_mono(&self, sample_rate: u32) -> Vec<i16> {
+        let channels = self.channels as usize;
+        let mono: Vec<i16> = self
+            .samples
+            .chunks_exact(channels)
+            .map(|frame| {
+                let sum: i32 = frame.iter().map(|&sample| sample as i32).sum();
+                (sum / channels as i32) as i16
+            })
+            .collect();
+        return resample(&mono, self.sample_rate, sample_rate);
+    }
+}
+
+/// Changes samples' rate with linear interpolation. Good enough for
+/// voice and blowing, which is what games listen for.
+pub fn resample(samples: &[i16], from_rate: u32, to_rate: u32) -> Vec<i16> {
+    if from_rate == to_rate || samples.is_empty() {
+        return samples.to_vec();
+    }
+    let length = (samples.len() as u64 * to_rate as u64 / from_rate as u64) as usize;
+    let step = from_rate as f64 / to_rate as f64;
+    return (0..length)
+        .map(|i| {
+            let position = i as f64 * step;
+            let index = position as usize;
+            let next = samples[(index + 1).min(samples.len() - 1)] as f64;
+            let fraction = position - index as f64;
+            (samples[index] as f64 * (1.0 - fraction) + next * fraction).round() as i16
+        })
+        .collect();
+}
+
+/// Turns runs of samples into mic packets, keeping the sequence ID.
+#[derive(Debug, Clone)]
+pub struct MicPacketizer {
+    config: MicConfig,
+    seq_id: u10,
+}
+
+impl MicPacketizer {
+    pub fn new(config: MicConfig) -> MicPacketizer {
+        return MicPacketizer {
+            config,
+            seq_id: u10::new(0),
+        };
+    }
+
+    pub fn packet(&mut self, samples: &[i16], timestamp: u32) -> Vec<u8> {
+        let payload: Vec<u8> = samples
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect();
+        let packet = WUPAudioPacket {
+            format: self.config.format,
+            channel: u1::new(0),
+            vibrate: false,
+            packet_type: u1::new(0),
+            seq_id: self.seq_id,
+            payload_size: payload.len() as u16,
+            timestamp,
+            payload,
+            received_at: None,
+        };
+        self.seq_id = self.seq_id.wrapping_add(u10::new(1));
+        return packet.encode();
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MicSenderStats {
+    pub packets_sent: u64,
+    pub samples_sent: u64,
+}
+
+/// A thread streaming samples to the console in real time. It stops
+/// when the samples run out, stop() is called or the MicSender is
+/// dropped.
+pub struct MicSender {
+    stop: Arc<AtomicBool>,
+    stats: Arc<Mutex<MicSenderStats>>,
+    thread: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl MicSender {
+    /// Sends samples, which must already be mono at config's sample
+    /// rate, from socket to destination. An endless iterator, like a
+    /// live capture, is fine. Fails with InvalidInput if config has no
+    /// sample rate or an unusable packet size.
+    pub fn start<I>(
+        socket: UdpSocket,
+        destination: SocketAddr,
+        samples: I,
+        config: MicConfig,
+    ) -> io::Result<MicSender>
+    where
+        I: IntoIterator<Item = i16>,
+        I::IntoIter: Send + 'static,
+    {
+        config.check()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let stats = Arc::new(Mutex::new(MicSenderStats::default()));
+        let thread_stop = stop.clone();
+        let thread_stats = stats.clone();
+        let samples = samples.into_iter();
+        let thread = thread::spawn(move || {
+            return send_samples(
+                socket,
+                destination,
+                samples,
+                config,
+                &thread_stop,
+                &thread_stats,
+            );
+        });
+        return Ok(MicSender {
+            stop,
+            stats,
+            thread: Some(thread),
+        });
+    }
+
+    pub fn stats(&self) -> MicSenderStats {
+        return self.stats.lock().unwrap().clone();
+    }
+
+    /// Whether every sample has been sent or the thread failed.
+    pub fn is_finished(&self) -> bool {
+        return match &self.thread {
+            None => true,
+            Some(thread) => thread.is_finished(),
+        };
+    }
+
+    /// Stops sending and returns the error that ended the thread early,
+    /// if there was one.
+    pub fn stop(&mut self) -> io::Result<()> {
+        self.stop.store(true, Ordering::Relaxed);
+        return match self.thread.take() {
+            None => Ok(()),
+            Some(thread) => join_thread(thread),
+        };
+    }
+}
+
+impl Drop for MicSender {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+fn send_samples<I: Iterator<Item = i16>>(
+    socket: UdpSocket,
+    destination: SocketAddr,
+    mut samples: I,
+    config: MicConfig,
+    stop: &AtomicBool,
+    stats: &Mutex<MicSenderStats>,
+) -> io::Result<()> {
+    let mut packetizer = MicPacketizer::new(config);
+    let interval = config.packet_duration();
+    let start = Instant::now();
+    let mut deadline = start;
+    let mut buffer = Vec::with_capacity(config.samples_per_packet);
+    while !stop.load(Ordering::Relaxed) {
+        buffer.clear();
+        buffer.extend(samples.by_ref().take(config.samples_per_packet));
+        if buffer.is_empty() {
+            debug!("Mic audio finished");
+            break;
+        }
+
+        let now = Instant::now();
+        if now < deadline {
+            thread::sleep(deadline - now);
+        }
+        let timestamp = (Instant::now() - start).as_micros() as u32;
+        socket.send_to(&packetizer.packet(&buffer, timestamp), destination)?;
+
+        let mut stats = stats.lock().unwrap();
+        stats.packets_sent += 1;
+        stats.samples_sent += buffer.len() as u64;
+        // Scheduling from the start keeps the rate right on average,
+        // which is what the console's buffer cares about.
+        deadline += interval;
+    }
+    return Ok(());
+}
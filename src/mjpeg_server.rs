@@ -0,0 +1,267 @@
+// Copyright 2024 Dalton Durst and the drc-sim-rust contributors
+// SPDX-License-Identifier: MPL-2.0
+
+//! A small HTTP server for watching decoded frames in a browser.
+//!
+//! Routes:
+//!  - `/` is a page showing the stream
+//!  - `/stream.mjpg` is a multipart MJPEG stream of every frame
+//!  - `/frame.jpg` is the latest frame as a single JPEG
+//!
+//! This is meant for local debugging, so it spends one thread per
+//! client and makes no attempt at being a complete HTTP server.
+
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use log::{debug, error, info};
+
+use crate::decode::DecodedFrame;
+
+const BOUNDARY: &str = "drcsimframe";
+
+/// How long a streaming client waits for a new frame before checking
+/// whether it is still connected.
+const STREAM_WAIT: Duration = Duration::from_secs(5);
+
+const INDEX_PAGE: &str = "<!DOCTYPE html>\n<html><head><title>drc-sim-rust</title></head>\
+<body style=\"margin:0;background:#000\">\
+<img src=\"/stream.mjpg\" style=\"display:block;margin:auto;max-width:100%\">\
+</body></html>\n";
+
+/// The most recent JPEG, along with a count of how many have been
+/// published so that streaming clients can tell when it changes.
+#[derive(Default)]
+struct LatestJpeg {
+    frame: Mutex<(u64, Option<Arc<Vec<u8>>>)>,
+    changed: Condvar,
+    /// Set when the server stops, which ends every client.
+    stop: AtomicBool,
+}
+
+impl LatestJpeg {
+    fn get(&self) -> (u64, Option<Arc<Vec<u8>>>) {
+        return self.frame.lock().unwrap().clone();
+    }
+
+    /// Waits until a frame newer than seen is published or STREAM_WAIT
+    /// passes.
+    fn wait_newer(&self, seen: u64) -> (u64, Option<Arc<Vec<u8>>>) {
+        let guard = self.frame.lock().unwrap();
+        let (guard, _) = self
+            .changed
+            .wait_timeout_while(guard, STREAM_WAIT, |(count, _)| {
+                *count <= seen && !self.stop.load(Ordering::Relaxed)
+            })
+            .unwrap();
+        return guard.clone();
+    }
+
+    fn is_stopped(&self) -> bool {
+        return self.stop.load(Ordering::Relaxed);
+    }
+}
+
+/// Serves frames until it is stopped or dropped.
+pub struct MjpegServer {
+    latest: Arc<LatestJpeg>,
+    local_addr: SocketAddr,
+    quality: u8,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MjpegServer {
+    /// Starts serving on addr. Frames are encoded at quality (1-100).
+    pub fn bind<A: ToSocketAddrs>(addr: A, quality: u8) -> io::Result<MjpegServer> {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        let latest = Arc::new(LatestJpeg::default());
+
+        let accept_latest = latest.clone();
+        let thread = thread::spawn(move || {
+            for stream in listener.incoming() {
+                if accept_latest.is_stopped() {
+                    break;
+                }
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        error!("Failed to accept HTTP connection: {}", err);
+                        continue;
+                    }
+                };
+                let latest = accept_latest.clone();
+                thread::spawn(move || {
+                    let peer = stream.peer_addr();
+                    if let Err(err) = handle_client(stream, &latest) {
+                        debug!("HTTP client {:?} went away: {}", peer, err);
+                    }
+                });
+            }
+        });
+
+        info!("Serving MJPEG on http://{}/", local_addr);
+        return Ok(MjpegServer {
+            latest,
+            local_addr,
+            quality,
+            thread: Some(thread),
+        });
+    }
+
+    /// Stops accepting connections, ends every client and waits for the
+    /// accepting thread, which frees the port.
+    pub fn stop(&mut self) {
+        let Some(thread) = self.thread.take() else {
+            return;
+        };
+        self.latest.stop.store(true, Ordering::Relaxed);
+        {
+            // Take the lock so no streaming client misses the wakeup
+            // between checking stop and starting to wait.
+            let _frame = self.latest.frame.lock().unwrap();
+            self.latest.changed.notify_all();
+        }
+        // The accepting thread only looks at stop when a connection
+        // arrives, so make one.
+        let mut wake_addr = self.local_addr;
+        if wake_addr.ip().is_unspecified() {
+            wake_addr.set_ip(match wake_addr.ip() {
+                IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+            });
+        }
+        if let Err(err) = TcpStream::connect(wake_addr) {
+            error!("Failed to wake the MJPEG server to stop it: {}", err);
+            return;
+        }
+        let _ = thread.join();
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        return self.local_addr;
+    }
+
+    /// Encodes frame and makes it the latest frame.
+    pub fn publish(&self, frame: &DecodedFrame) {
+        match frame.to_jpeg(self.quality) {
+            Ok(jpeg) => self.publish_jpeg(jpeg),
+            Err(err) => error!("Failed to encode frame as JPEG: {}", err),
+        }
+    }
+
+    /// Makes an already-encoded JPEG the latest frame.
+    pub fn publish_jpeg(&self, jpeg: Vec<u8>) {
+        let mut frame = self.latest.frame.lock().unwrap();
+        frame.0 += 1;
+        frame.1 = Some(Arc::new(jpeg));
+        self.latest.changed.notify_all();
+    }
+}
+
+impl Drop for MjpegServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn handle_client(stream: TcpStream, latest: &LatestJpeg) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Read and ignore the headers.
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header == "\r\n" || header == "\n" {
+            break;
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let path = parts.next().unwrap_or("");
+    let path = path.split('?').next().unwrap_or("");
+
+    let mut stream = stream;
+    if method != "GET" {
+        return write_response(&mut stream, "405 Method Not Allowed", "text/plain", b"");
+    }
+    return match path {
+        "/" => write_response(&mut stream, "200 OK", "text/html", INDEX_PAGE.as_bytes()),
+        "/frame.jpg" => match latest.get() {
+            (_, Some(jpeg)) => write_response(&mut stream, "200 OK", "image/jpeg", &jpeg),
+            (_, None) => write_response(
+                &mut stream,
+                "503 Service Unavailable",
+                "text/plain",
+                b"no frame yet\n",
+            ),
+        },
+        "/stream.mjpg" => stream_frames(&mut stream, latest),
+        _ => write_response(&mut stream, "404 Not Found", "text/plain", b"not found\n"),
+    };
+}
+
+fn write_response(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    return stream.flush();
+}
+
+fn stream_frames(stream: &mut TcpStream, latest: &LatestJpeg) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: multipart/x-mixed-replace; boundary={}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        BOUNDARY
+    )?;
+    let (mut seen, mut jpeg) = latest.get();
+    loop {
+        if let Some(jpeg) = &jpeg {
+            write!(
+                stream,
+                "--{}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+                BOUNDARY,
+                jpeg.len()
+            )?;
+            stream.write_all(jpeg)?;
+            stream.write_all(b"\r\n")?;
+            stream.flush()?;
+        } else {
+            // There's no frame to resend yet, so write a blank line of
+            // preamble, which clients ignore, to find out if the client
+            // has gone away.
+            stream.write_all(b"\r\n")?;
+            stream.flush()?;
+        }
+        let (count, newer) = latest.wait_newer(seen);
+        if latest.is_stopped() {
+            return Ok(());
+        }
+        if count == seen {
+            // Nothing new arrived. Resend the last frame so that we
+            // notice if the client has gone away.
+            continue;
+        }
+        seen = count;
+        jpeg = newer;
+    }
+}
@@ -0,0 +1,320 @@
+// Copyright 2024 Dalton Durst and the drc-sim-rust contributors
+// SPDX-License-Identifier: MPL-2.0
+
+//! A model of the GamePad's motion sensors, for games that read them.
+//!
+//! Everything here uses the GamePad's own axes: x points to the right
+//! of the screen, y towards the top of the screen and z out of the
+//! screen at the player. The world has x east, y north and z up, so
+//! the identity orientation is the GamePad lying flat, screen up, with
+//! its top pointing north.
+//!
+//! The counts-per-unit scales are estimates. Nobody has published the
+//! sensors' exact calibration, so they are kept in ImuScale where they
+//! can be corrected.
+
+use std::time::Duration;
+
+use crate::hid::{GamePadInputReport, Vector3};
+
+/// The Earth's magnetic field in world coordinates, in microtesla.
+/// This is roughly what it is at mid-northern latitudes.
+pub const EARTH_MAGNETIC_FIELD_UT: Vector3<f64> = Vector3 {
+    x: 0.0,
+    y: 20.0,
+    z: -44.0,
+};
+
+/// A rotation, stored as a unit quaternion.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quaternion {
+    pub w: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Default for Quaternion {
+    fn default() -> Quaternion {
+        return Quaternion::IDENTITY;
+    }
+}
+
+impl Quaternion {
+    pub const IDENTITY: Quaternion = Quaternion {
+        w: 1.0,
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    };
+
+    /// A rotation of degrees around axis, right-handed.
+    pub fn from_axis_angle(axis: Vector3<f64>, degrees: f64) -> Quaternion {
+        let length = (axis.x * axis.x + axis.y * axis.y + axis.z * axis.z).sqrt();
+        if length == 0.0 {
+            return Quaternion::IDENTITY;
+        }
+        let half = degrees.to_radians() / 2.0;
+        let s = half.sin() / length;
+        return Quaternion {
+            w: half.cos(),
+            x: axis.x * s,
+            y: axis.y * s,
+            z: axis.z * s,
+        };
+    }
+
+    /// The rotation that applies other first and then self.
+    pub fn mul(&self, other: &Quaternion) -> Quaternion {
+        return Quaternion {
+            w: self.w * other.w - self.x * other.x - self.y * other.y - self.z * other.z,
+            x: self.w * other.x + self.x * other.w + self.y * other.z - self.z * other.y,
+            y: self.w * other.y - self.x * other.z + self.y * other.w + self.z * other.x,
+            z: self.w * other.z + self.x * other.y - self.y * other.x + self.z * other.w,
+        };
+    }
+
+    pub fn conjugate(&self) -> Quaternion {
+        return Quaternion {
+            w: self.w,
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+        };
+    }
+
+    pub fn normalize(&self) -> Quaternion {
+        let length = (self.w * self.w + self.x * self.x + self.y * self.y + self.z * self.z).sqrt();
+        if length == 0.0 {
+            return Quaternion::IDENTITY;
+        }
+        return Quaternion {
+            w: self.w / length,
+            x: self.x / length,
+            y: self.y / length,
+            z: self.z / length,
+        };
+    }
+
+    pub fn rotate(&self, v: Vector3<f64>) -> Vector3<f64> {
+        let p = Quaternion {
+            w: 0.0,
+            x: v.x,
+            y: v.y,
+            z: v.z,
+        };
+        let r = self.mul(&p).mul(&self.conjugate());
+        return Vector3 {
+            x: r.x,
+            y: r.y,
+            z: r.z,
+        };
+    }
+}
+
+/// How many raw counts each sensor reports per unit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImuScale {
+    pub accelerometer_per_g: f64,
+    pub gyroscope_per_degree_per_second: f64,
+    pub magnetometer_per_microtesla: f64,
+}
+
+impl Default for ImuScale {
+    fn default() -> ImuScale {
+        return ImuScale {
+            accelerometer_per_g: 4096.0,
+            gyroscope_per_degree_per_second: 1000.0,
+            magnetometer_per_microtesla: 10.0,
+        };
+    }
+}
+
+/// The standard deviation of the noise added to each reading.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImuNoise {
+    pub accelerometer_g: f64,
+    pub gyroscope_degrees_per_second: f64,
+    pub magnetometer_microtesla: f64,
+}
+
+impl ImuNoise {
+    pub const NONE: ImuNoise = ImuNoise {
+        accelerometer_g: 0.0,
+        gyroscope_degrees_per_second: 0.0,
+        magnetometer_microtesla: 0.0,
+    };
+}
+
+impl Default for ImuNoise {
+    /// About what a consumer MEMS IMU shows sitting on a desk.
+    fn default() -> ImuNoise {
+        return ImuNoise {
+            accelerometer_g: 0.004,
+            gyroscope_degrees_per_second: 0.08,
+            magnetometer_microtesla: 0.3,
+        };
+    }
+}
+
+/// One reading from each sensor, in the report's raw units.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImuSample {
+    pub accelerometer: Vector3<i16>,
+    /// Roll (around y), yaw (around z) and pitch (around x), in the
+    /// order the report holds them.
+    pub gyroscope: Vector3<i32>,
+    pub magnetometer: Vector3<i16>,
+}
+
+/// Produces sensor readings for a GamePad with a given orientation and
+/// motion.
+pub struct MotionSource {
+    /// Rotates GamePad coordinates into world coordinates.
+    pub orientation: Quaternion,
+    /// Degrees per second around the GamePad's x, y and z axes.
+    pub angular_velocity: Vector3<f64>,
+    /// Acceleration in g on top of gravity, in GamePad coordinates.
+    pub linear_acceleration: Vector3<f64>,
+    pub scale: ImuScale,
+    pub noise: ImuNoise,
+    rng: u64,
+}
+
+impl Default for MotionSource {
+    fn default() -> MotionSource {
+        return MotionSource::at_rest();
+    }
+}
+
+impl MotionSource {
+    /// A GamePad lying flat on a table, screen up, with realistic
+    /// noise.
+    pub fn at_rest() -> MotionSource {
+        return MotionSource::with_seed(0x5DEE_CE66_D1CE_4E5B);
+    }
+
+    /// Like at_rest, but with noise from a different seed. The same
+    /// seed always produces the same readings.
+    pub fn with_seed(seed: u64) -> MotionSource {
+        return MotionSource {
+            orientation: Quaternion::IDENTITY,
+            angular_velocity: Vector3::default(),
+            linear_acceleration: Vector3::default(),
+            scale: ImuScale::default(),
+            noise: ImuNoise::default(),
+            // xorshift gets stuck at zero
+            rng: seed.max(1),
+        };
+    }
+
+    /// Turns the GamePad by angular_velocity for elapsed.
+    pub fn step(&mut self, elapsed: Duration) {
+        let v = self.angular_velocity;
+        let speed = (v.x * v.x + v.y * v.y + v.z * v.z).sqrt();
+        if speed == 0.0 {
+            return;
+        }
+        let turn = Quaternion::from_axis_angle(v, speed * elapsed.as_secs_f64());
+        // The angular velocity is in GamePad coordinates, so the turn
+        // happens before the existing orientation.
+        self.orientation = self.orientation.mul(&turn).normalize();
+    }
+
+    /// Returns the current readings.
+    pub fn sample(&mut self) -> ImuSample {
+        let to_device = self.orientation.conjugate();
+        // An accelerometer at rest measures the table pushing up.
+        let gravity = to_device.rotate(Vector3 {
+            x: 0.0,
+            y: 0.0,
+            z: 1.0,
+        });
+        let noise = self.noise;
+        let scale = self.scale;
+        let acceleration = Vector3 {
+            x: gravity.x + self.linear_acceleration.x + self.gaussian(noise.accelerometer_g),
+            y: gravity.y + self.linear_acceleration.y + self.gaussian(noise.accelerometer_g),
+            z: gravity.z + self.linear_acceleration.z + self.gaussian(noise.accelerometer_g),
+        };
+
+        let gyro_noise = noise.gyroscope_degrees_per_second;
+        let rates = Vector3 {
+            x: self.angular_velocity.x + self.gaussian(gyro_noise),
+            y: self.angular_velocity.y + self.gaussian(gyro_noise),
+            z: self.angular_velocity.z + self.gaussian(gyro_noise),
+        };
+
+        let field = to_device.rotate(EARTH_MAGNETIC_FIELD_UT);
+        let mag_noise = noise.magnetometer_microtesla;
+        let field = Vector3 {
+            x: field.x + self.gaussian(mag_noise),
+            y: field.y + self.gaussian(mag_noise),
+            z: field.z + self.gaussian(mag_noise),
+        };
+
+        let gyro_scale = scale.gyroscope_per_degree_per_second;
+        return ImuSample {
+            accelerometer: scale_i16(acceleration, scale.accelerometer_per_g),
+            gyroscope: Vector3 {
+                x: to_i24(rates.y * gyro_scale),
+                y: to_i24(rates.z * gyro_scale),
+                z: to_i24(rates.x * gyro_scale),
+            },
+            magnetometer: scale_i16(field, scale.magnetometer_per_microtesla),
+        };
+    }
+
+    /// Writes a fresh sample into report.
+    pub fn apply_to(&mut self, report: &mut GamePadInputReport) {
+        let sample = self.sample();
+        report.accelerometer = sample.accelerometer;
+        report.gyroscope = sample.gyroscope;
+        report.magnetometer = sample.magnetometer;
+    }
+
+    /// Steps by elapsed and then writes a fresh sample into report, for
+    /// use as a HidSender hook.
+    pub fn advance(&mut self, report: &mut GamePadInputReport, elapsed: Duration) {
+        self.step(elapsed);
+        self.apply_to(report);
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        // xorshift64*
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        return self.rng.wrapping_mul(0x2545_F491_4F6C_DD1D);
+    }
+
+    /// Returns normally distributed noise with the given standard
+    /// deviation.
+    fn gaussian(&mut self, deviation: f64) -> f64 {
+        if deviation == 0.0 {
+            return 0.0;
+        }
+        // Box-Muller. u1 must not be 0 for the log.
+        let u1 = ((self.next_u64() >> 11) as f64 + 1.0) / (1u64 << 53) as f64;
+        let u2 = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        return deviation * (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+    }
+}
+
+fn scale_i16(v: Vector3<f64>, scale: f64) -> Vector3<i16> {
+    let convert = |value: f64| {
+        (value * scale)
+            .round()
+            .clamp(i16::MIN as f64, i16::MAX as f64) as i16
+    };
+    return Vector3 {
+        x: convert(v.x),
+        y: convert(v.y),
+        z: convert(v.z),
+    };
+}
+
+fn to_i24(value: f64) -> i32 {
+    const MAX: f64 = ((1 << 23) - 1) as f64;
+    return value.round().clamp(-MAX - 1.0, MAX) as i32;
+}
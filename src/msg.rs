@@ -0,0 +1,95 @@
+// Copyright 2024 Dalton Durst and the drc-sim-rust contributors
+// SPDX-License-Identifier: MPL-2.0
+
+//! The message channel, where the console sends the GamePad
+//! out-of-band notifications.
+//!
+//! Nothing about these messages' format is known. The original drc-sim
+//! only prints them. We do the same, in hex, and count them so that a
+//! session can tell when the console is asking for something we don't
+//! answer. A MessageResponder can be plugged in to answer messages once
+//! their meaning is worked out.
+
+use std::{io, net::UdpSocket};
+
+use log::{debug, info};
+
+use crate::hex;
+
+/// The largest packet we expect on the message port.
+const MSG_BUFFER_SIZE: usize = 2048;
+
+/// Answers a message from the console. Returning None leaves the
+/// message unanswered and counts it as unknown.
+pub type MessageResponder = Box<dyn FnMut(&[u8]) -> Option<Vec<u8>> + Send>;
+
+/// Counts of what has gone through a MessageHandler.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MessageStats {
+    pub received: u64,
+    /// Messages the responder had no answer for.
+    pub unknown: u64,
+    pub responses_sent: u64,
+}
+
+/// Receives messages from the console and answers the ones it can.
+pub struct MessageHandler {
+    responder: Option<MessageResponder>,
+    stats: MessageStats,
+}
+
+impl Default for MessageHandler {
+    fn default() -> MessageHandler {
+        return MessageHandler::new(None);
+    }
+}
+
+impl MessageHandler {
+    pub fn new(responder: Option<MessageResponder>) -> MessageHandler {
+        return MessageHandler {
+            responder,
+            stats: MessageStats::default(),
+        };
+    }
+
+    pub fn stats(&self) -> &MessageStats {
+        return &self.stats;
+    }
+
+    /// Handles one message from the console and returns the reply to
+    /// send back, if there is one.
+    pub fn handle(&mut self, message: &[u8]) -> Option<Vec<u8>> {
+        self.stats.received += 1;
+        let reply = match &mut self.responder {
+            Some(responder) => responder(message),
+            None => None,
+        };
+        match &reply {
+            Some(reply) => {
+                self.stats.responses_sent += 1;
+                debug!("Message {} answered with {}", hex(message), hex(reply));
+            }
+            None => {
+                self.stats.unknown += 1;
+                info!(
+                    "Unknown message from the console ({} so far): {}",
+                    self.stats.unknown,
+                    hex(message)
+                );
+            }
+        }
+        return reply;
+    }
+
+    /// Answers messages from socket, replying to whoever sent them,
+    /// until the socket fails.
+    pub fn run(&mut self, socket: &UdpSocket) -> io::Result<()> {
+        let mut buf = [0u8; MSG_BUFFER_SIZE];
+        loop {
+            let (size, sender) = socket.recv_from(&mut buf)?;
+            if let Some(reply) = self.handle(&buf[..size]) {
+                socket.send_to(&reply, sender)?;
+            }
+        }
+    }
+}
@@ -1,9 +1,15 @@
 use core::fmt;
-use std::{cmp::Ordering, collections::HashMap};
+use std::{cmp::Ordering, collections::HashMap, time::SystemTime};
 
 use arbitrary_int::{u10, Number};
 
-use crate::incoming_packet_parser::WUPVideoPacket;
+use log::{debug, error};
+
+use crate::{
+    incoming_packet_parser::{u32_paws_compare, WUPVideoPacket},
+    sockets::Pad,
+    STALE_FRAME_THRESHOLD,
+};
 
 pub struct FrameAccumulator {
     timestamp_: u32,
@@ -96,15 +102,15 @@ impl FrameAccumulator {
         if !packet.timestamp == self.timestamp_ {
             return Err(PacketRejectReason::WrongTimestamp);
         }
-        let incoming_seq_id = packet.seq_id.clone();
+        let incoming_seq_id = packet.seq_id;
         if packet.frame_begin {
-            if self.begin_packet_ != None {
+            if self.begin_packet_.is_some() {
                 return Err(PacketRejectReason::AlreadyHaveBegin);
             }
             self.begin_packet_ = Some(incoming_seq_id);
         }
         if packet.frame_end {
-            if self.end_packet_ != None {
+            if self.end_packet_.is_some() {
                 return Err(PacketRejectReason::AlreadyHaveEnd);
             }
             self.end_packet_ = Some(incoming_seq_id);
@@ -116,7 +122,7 @@ impl FrameAccumulator {
             return Err(PacketRejectReason::AlreadyHaveSeq);
         }
         let existing = self.packets.insert(incoming_seq_id, packet);
-        if existing != None {
+        if existing.is_some() {
             panic!(
                 "Clobbered a packet in FrameAccumulator with timestamp {} seq_id {}",
                 self.timestamp_, incoming_seq_id
@@ -126,17 +132,17 @@ impl FrameAccumulator {
     }
 
     pub fn complete(&self) -> Result<Vec<&WUPVideoPacket>, IncompleteReason> {
-        if self.begin_packet_ == None && self.end_packet_ == None {
+        if self.begin_packet_.is_none() && self.end_packet_.is_none() {
             return Err(IncompleteReason::new(
                 IncompleteReasonKind::NoBeginEndPacket,
                 "have neither begin nor end packet.".to_string(),
             ));
-        } else if self.begin_packet_ == None {
+        } else if self.begin_packet_.is_none() {
             return Err(IncompleteReason::new(
                 IncompleteReasonKind::NoBeginPacket,
                 "have end packet but not begin packet.".to_string(),
             ));
-        } else if self.end_packet_ == None {
+        } else if self.end_packet_.is_none() {
             return Err(IncompleteReason::new(
                 IncompleteReasonKind::NoEndPacket,
                 "have begin packet but not end packet.".to_string(),
@@ -195,4 +201,205 @@ impl FrameAccumulator {
 
         return Ok(sorted_packets);
     }
+
+    /// Like complete(), but consumes this FrameAccumulator and returns
+    /// the packets themselves rather than references to them.
+    pub fn into_complete(mut self) -> Result<Vec<WUPVideoPacket>, IncompleteReason> {
+        let seq_ids: Vec<u10> = self.complete()?.iter().map(|p| p.seq_id).collect();
+        return Ok(seq_ids
+            .iter()
+            .map(|seq_id| self.packets.remove(seq_id).unwrap())
+            .collect());
+    }
+}
+
+/// A frame which has received all of its dgrams, sorted by seq_id.
+pub struct AssembledFrame {
+    /// The GamePad whose stream this frame came from.
+    pub pad: Pad,
+    pub timestamp: u32,
+    pub packets: Vec<WUPVideoPacket>,
+}
+
+impl AssembledFrame {
+    /// Whether the WUP marked this frame as an IDR frame. It does this
+    /// by placing 0x80 somewhere in the extended header of the frame's
+    /// packets.
+    pub fn is_idr(&self) -> bool {
+        return self
+            .packets
+            .iter()
+            .any(|p| p.extended_header.contains(&0x80));
+    }
+
+    /// The payloads of every packet in this frame, concatenated. This
+    /// is the frame's H.264 slice data without a NAL or slice header.
+    pub fn payload(&self) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(self.payload_len());
+        for packet in &self.packets {
+            payload.extend_from_slice(&packet.payload);
+        }
+        return payload;
+    }
+
+    pub fn payload_len(&self) -> usize {
+        return self.packets.iter().map(|p| p.payload.len()).sum();
+    }
+
+    /// When the first of the frame's packets reached us, by the
+    /// kernel's receive timestamps. None if they weren't enabled.
+    pub fn first_received_at(&self) -> Option<SystemTime> {
+        return self.packets.iter().filter_map(|p| p.received_at).min();
+    }
+
+    /// When the last of the frame's packets reached us, which is when
+    /// the whole frame was available.
+    pub fn last_received_at(&self) -> Option<SystemTime> {
+        return self.packets.iter().filter_map(|p| p.received_at).max();
+    }
+}
+
+impl fmt::Debug for AssembledFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AssembledFrame")
+            .field("pad", &self.pad)
+            .field("timestamp", &self.timestamp)
+            .field("packets", &format!("{} packets", &self.packets.len()))
+            .finish()
+    }
+}
+
+/// Sorts incoming WUPVideoPackets into FrameAccumulators by timestamp
+/// and hands back frames as they complete. Frames which fall more than
+/// STALE_FRAME_THRESHOLD behind the newest timestamp we've seen are
+/// dropped.
+///
+/// Each video stream needs its own FrameAssembler.
+#[derive(Default)]
+pub struct FrameAssembler {
+    pad: Pad,
+    /// The newest timestamp that we have seen so far.
+    high_water_mark: Option<u32>,
+    frame_accumulators: HashMap<u32, FrameAccumulator>,
+    completed_frames: u64,
+    dropped_frames: u64,
+    rejected_packets: u64,
+}
+
+impl FrameAssembler {
+    pub fn new() -> FrameAssembler {
+        return FrameAssembler::default();
+    }
+
+    /// Creates a FrameAssembler for the video stream sent to pad.
+    pub fn for_pad(pad: Pad) -> FrameAssembler {
+        return FrameAssembler {
+            pad,
+            ..Default::default()
+        };
+    }
+
+    pub fn pad(&self) -> Pad {
+        return self.pad;
+    }
+
+    /// Adds packet to the frame it belongs to. Returns the frame if
+    /// this packet completed it.
+    pub fn add_packet(&mut self, packet: WUPVideoPacket) -> Option<AssembledFrame> {
+        let timestamp = packet.timestamp;
+        let seq_id = packet.seq_id;
+        // Without a timestamp we don't know which frame the packet is
+        // for, so it mustn't move the high water mark or start one.
+        if !packet.has_timestamp {
+            self.reject(seq_id, timestamp, PacketRejectReason::NoTimestamp);
+            return None;
+        }
+
+        let high_water_mark = match self.high_water_mark {
+            Some(hwm) if u32_paws_compare(timestamp, hwm) != Some(Ordering::Greater) => hwm,
+            _ => {
+                debug!("New high water mark is {}", timestamp);
+                self.high_water_mark = Some(timestamp);
+                timestamp
+            }
+        };
+
+        if self.frame_accumulators.len() > 1 {
+            let oldest_acceptable = high_water_mark.wrapping_sub(STALE_FRAME_THRESHOLD);
+            let dropped_before = self.frame_accumulators.len();
+            self.frame_accumulators.retain(|accu_timestamp, _| {
+                // This is "timestamp is less than our lowest acceptable
+                // timestamp"
+                let stale =
+                    u32_paws_compare(*accu_timestamp, oldest_acceptable) == Some(Ordering::Less);
+                if stale {
+                    error!("{}: Dropping {} as it is too old", self.pad, accu_timestamp);
+                }
+                return !stale;
+            });
+            self.dropped_frames += (dropped_before - self.frame_accumulators.len()) as u64;
+        }
+
+        let frame_accumulator = self
+            .frame_accumulators
+            .entry(timestamp)
+            .or_insert(FrameAccumulator::new(timestamp));
+
+        if let Err(reason) = frame_accumulator.add_packet(packet) {
+            self.reject(seq_id, timestamp, reason);
+            return None;
+        }
+
+        match frame_accumulator.complete() {
+            Ok(_) => (),
+            Err(err) => {
+                if let IncompleteReasonKind::TooManyPackets = err.kind {
+                    error!("Frame accumulator has too many packets, dropping.");
+                    self.frame_accumulators.remove(&timestamp);
+                    self.dropped_frames += 1;
+                }
+                // All other reasons will be handled by us dropping old
+                // accumulators.
+                return None;
+            }
+        }
+
+        let packets = match self.frame_accumulators.remove(&timestamp)?.into_complete() {
+            Ok(packets) => packets,
+            Err(err) => panic!("Frame {} was complete but now isn't: {}", timestamp, err),
+        };
+        self.completed_frames += 1;
+        return Some(AssembledFrame {
+            pad: self.pad,
+            timestamp,
+            packets,
+        });
+    }
+
+    pub fn completed_frames(&self) -> u64 {
+        return self.completed_frames;
+    }
+
+    pub fn dropped_frames(&self) -> u64 {
+        return self.dropped_frames;
+    }
+
+    /// Packets that didn't fit the frame they claimed to be part of,
+    /// such as duplicates or a second frame_begin.
+    pub fn rejected_packets(&self) -> u64 {
+        return self.rejected_packets;
+    }
+
+    fn reject(&mut self, seq_id: u10, timestamp: u32, reason: PacketRejectReason) {
+        debug!(
+            "{}: Rejected packet {} for frame {}: {:?}",
+            self.pad, seq_id, timestamp, reason
+        );
+        self.rejected_packets += 1;
+    }
+
+    /// The number of frames which are still waiting for packets.
+    pub fn incomplete_frames(&self) -> usize {
+        return self.frame_accumulators.len();
+    }
 }
@@ -0,0 +1,192 @@
+// Copyright 2024 Dalton Durst and the drc-sim-rust contributors
+// SPDX-License-Identifier: MPL-2.0
+
+//! A bounded queue between a thread that receives packets and the
+//! threads that use them.
+//!
+//! The receiving thread has to get back to the socket quickly, or the
+//! kernel's buffer fills and datagrams are lost where we can't see
+//! them. With a queue in between, a slow consumer costs packets in the
+//! queue instead, where the OverflowPolicy decides which ones and the
+//! QueueStats count them.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Condvar, Mutex},
+    time::Duration,
+};
+
+/// What push does when the queue is full.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Throw away the packet that has waited longest. Best for video,
+    /// where a late packet is worth less than a new one.
+    #[default]
+    DropOldest,
+    /// Throw away the packet being pushed.
+    DropNewest,
+    /// Wait for room. Nothing is lost in the queue, but the receiving
+    /// thread stops reading, so the kernel may drop packets instead.
+    Block,
+}
+
+impl OverflowPolicy {
+    /// Reads the names used on the command line and in config files:
+    /// drop-oldest, drop-newest and block.
+    pub fn parse(name: &str) -> Option<OverflowPolicy> {
+        return match name {
+            "drop-oldest" => Some(OverflowPolicy::DropOldest),
+            "drop-newest" => Some(OverflowPolicy::DropNewest),
+            "block" => Some(OverflowPolicy::Block),
+            _ => None,
+        };
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueueStats {
+    pub pushed: u64,
+    pub popped: u64,
+    /// Packets thrown away under DropOldest.
+    pub dropped_oldest: u64,
+    /// Packets thrown away under DropNewest.
+    pub dropped_newest: u64,
+    /// Packets pushed after close, under any policy.
+    pub dropped_after_close: u64,
+    /// Pushes that had to wait for room under Block.
+    pub blocked: u64,
+    /// The most packets that were ever waiting at once.
+    pub max_len: usize,
+}
+
+struct State<T> {
+    items: VecDeque<T>,
+    closed: bool,
+    stats: QueueStats,
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    not_empty: Condvar,
+    not_full: Condvar,
+}
+
+/// A bounded queue of packets. Clones share the same queue, so one can
+/// go to the receiving thread and one to the consumer.
+pub struct PacketQueue<T> {
+    shared: Arc<Shared<T>>,
+    capacity: usize,
+    policy: OverflowPolicy,
+}
+
+impl<T> Clone for PacketQueue<T> {
+    fn clone(&self) -> PacketQueue<T> {
+        return PacketQueue {
+            shared: self.shared.clone(),
+            capacity: self.capacity,
+            policy: self.policy,
+        };
+    }
+}
+
+impl<T> PacketQueue<T> {
+    pub fn new(capacity: usize, policy: OverflowPolicy) -> PacketQueue<T> {
+        let capacity = capacity.max(1);
+        return PacketQueue {
+            shared: Arc::new(Shared {
+                state: Mutex::new(State {
+                    items: VecDeque::with_capacity(capacity),
+                    closed: false,
+                    stats: QueueStats::default(),
+                }),
+                not_empty: Condvar::new(),
+                not_full: Condvar::new(),
+            }),
+            capacity,
+            policy,
+        };
+    }
+
+    pub fn capacity(&self) -> usize {
+        return self.capacity;
+    }
+
+    pub fn policy(&self) -> OverflowPolicy {
+        return self.policy;
+    }
+
+    pub fn len(&self) -> usize {
+        return self.shared.state.lock().unwrap().items.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.len() == 0;
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        return self.shared.state.lock().unwrap().stats.clone();
+    }
+
+    /// Adds item, handling a full queue by the policy. Returns false if
+    /// item was thrown away, either by DropNewest or because the queue
+    /// is closed. The two are counted apart in QueueStats.
+    pub fn push(&self, item: T) -> bool {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.items.len() >= self.capacity && !state.closed {
+            match self.policy {
+                OverflowPolicy::DropOldest => {
+                    state.items.pop_front();
+                    state.stats.dropped_oldest += 1;
+                }
+                OverflowPolicy::DropNewest => {
+                    state.stats.dropped_newest += 1;
+                    return false;
+                }
+                OverflowPolicy::Block => {
+                    state.stats.blocked += 1;
+                    while state.items.len() >= self.capacity && !state.closed {
+                        state = self.shared.not_full.wait(state).unwrap();
+                    }
+                }
+            }
+        }
+        if state.closed {
+            state.stats.dropped_after_close += 1;
+            return false;
+        }
+        state.items.push_back(item);
+        state.stats.pushed += 1;
+        state.stats.max_len = state.stats.max_len.max(state.items.len());
+        self.shared.not_empty.notify_one();
+        return true;
+    }
+
+    /// Takes the oldest item, waiting up to timeout for one. Returns
+    /// None on timeout, or once the queue is closed and empty.
+    pub fn pop_timeout(&self, timeout: Duration) -> Option<T> {
+        let state = self.shared.state.lock().unwrap();
+        let (mut state, _) = self
+            .shared
+            .not_empty
+            .wait_timeout_while(state, timeout, |state| {
+                state.items.is_empty() && !state.closed
+            })
+            .unwrap();
+        let item = state.items.pop_front()?;
+        state.stats.popped += 1;
+        self.shared.not_full.notify_one();
+        return Some(item);
+    }
+
+    /// Stops the queue taking new items and wakes everyone waiting on
+    /// it. Items already queued can still be popped.
+    pub fn close(&self) {
+        self.shared.state.lock().unwrap().closed = true;
+        self.shared.not_empty.notify_all();
+        self.shared.not_full.notify_all();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        return self.shared.state.lock().unwrap().closed;
+    }
+}
@@ -0,0 +1,266 @@
+// Copyright 2024 Dalton Durst and the drc-sim-rust contributors
+// SPDX-License-Identifier: MPL-2.0
+
+//! Re-streams reconstructed H.264 over RTP (RFC 3550) using the RFC
+//! 6184 payload format, so that tools like ffplay, VLC or GStreamer
+//! can watch the GamePad's video.
+//!
+//! NAL units which fit in one packet are sent as Single NAL Unit
+//! packets and larger ones are split into FU-A fragments
+//! (packetization-mode=1).
+
+use std::{
+    cmp::Ordering,
+    fs, io,
+    net::{SocketAddr, UdpSocket},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    h264::{split_annex_b, PPS, SPS},
+    incoming_packet_parser::u32_paws_compare,
+};
+
+/// The dynamic payload type we announce in the SDP.
+pub const H264_PAYLOAD_TYPE: u8 = 96;
+
+/// The RTP clock rate for video.
+pub const RTP_CLOCK_RATE: u64 = 90_000;
+
+/// The largest RTP packet we send, chosen to fit in an Ethernet MTU
+/// after IP and UDP headers.
+pub const DEFAULT_MAX_PACKET_SIZE: usize = 1400;
+
+const RTP_HEADER_SIZE: usize = 12;
+const FU_A_TYPE: u8 = 28;
+
+/// Converts WUP timestamps (microseconds, wrapping every ~1.19 hours)
+/// to a 90 kHz RTP clock which keeps counting through the wrap.
+#[derive(Debug, Default)]
+pub struct RtpClock {
+    base: u32,
+    first_wup_timestamp: Option<u32>,
+    /// Microseconds from the first WUP timestamp to the latest one.
+    elapsed_us: i64,
+    last_wup_timestamp: u32,
+}
+
+impl RtpClock {
+    /// Creates a clock whose first timestamp is base.
+    pub fn new(base: u32) -> RtpClock {
+        return RtpClock {
+            base,
+            ..Default::default()
+        };
+    }
+
+    pub fn convert(&mut self, wup_timestamp: u32) -> u32 {
+        if self.first_wup_timestamp.is_none() {
+            self.first_wup_timestamp = Some(wup_timestamp);
+            self.last_wup_timestamp = wup_timestamp;
+        }
+        // Timestamps from frames that completed late go backwards.
+        let delta = match u32_paws_compare(wup_timestamp, self.last_wup_timestamp) {
+            Some(Ordering::Less) => -(self.last_wup_timestamp.wrapping_sub(wup_timestamp) as i64),
+            _ => wup_timestamp.wrapping_sub(self.last_wup_timestamp) as i64,
+        };
+        self.elapsed_us += delta;
+        self.last_wup_timestamp = wup_timestamp;
+        let ticks = self.elapsed_us * RTP_CLOCK_RATE as i64 / 1_000_000;
+        return self.base.wrapping_add(ticks as u32);
+    }
+}
+
+/// Splits Annex B access units into RTP packets.
+pub struct RtpH264Packetizer {
+    ssrc: u32,
+    sequence_number: u16,
+    max_packet_size: usize,
+}
+
+impl RtpH264Packetizer {
+    pub fn new(ssrc: u32, first_sequence_number: u16, max_packet_size: usize) -> RtpH264Packetizer {
+        assert!(
+            max_packet_size > RTP_HEADER_SIZE + 2,
+            "max_packet_size {} leaves no room for payload",
+            max_packet_size
+        );
+        return RtpH264Packetizer {
+            ssrc,
+            sequence_number: first_sequence_number,
+            max_packet_size,
+        };
+    }
+
+    pub fn ssrc(&self) -> u32 {
+        return self.ssrc;
+    }
+
+    /// Returns the RTP packets carrying access_unit. The last one has
+    /// the marker bit set.
+    pub fn packetize(&mut self, access_unit: &[u8], rtp_timestamp: u32) -> Vec<Vec<u8>> {
+        let max_payload = self.max_packet_size - RTP_HEADER_SIZE;
+        let mut payloads: Vec<Vec<u8>> = Vec::new();
+        for nal in split_annex_b(access_unit) {
+            if nal.is_empty() {
+                continue;
+            }
+            if nal.len() <= max_payload {
+                payloads.push(nal.to_vec());
+                continue;
+            }
+            // FU-A: the NAL header is replaced by an FU indicator
+            // (F, NRI and type 28) and an FU header (S, E, R and the
+            // original type).
+            let fu_indicator = (nal[0] & 0xE0) | FU_A_TYPE;
+            let nal_type = nal[0] & 0x1F;
+            let chunks: Vec<&[u8]> = nal[1..].chunks(max_payload - 2).collect();
+            for (i, chunk) in chunks.iter().enumerate() {
+                let mut fu_header = nal_type;
+                if i == 0 {
+                    fu_header |= 0x80;
+                }
+                if i == chunks.len() - 1 {
+                    fu_header |= 0x40;
+                }
+                let mut payload = Vec::with_capacity(chunk.len() + 2);
+                payload.push(fu_indicator);
+                payload.push(fu_header);
+                payload.extend_from_slice(chunk);
+                payloads.push(payload);
+            }
+        }
+
+        let count = payloads.len();
+        return payloads
+            .into_iter()
+            .enumerate()
+            .map(|(i, payload)| self.packet(&payload, rtp_timestamp, i == count - 1))
+            .collect();
+    }
+
+    fn packet(&mut self, payload: &[u8], rtp_timestamp: u32, marker: bool) -> Vec<u8> {
+        let mut packet = Vec::with_capacity(RTP_HEADER_SIZE + payload.len());
+        // Version 2, no padding, no extension, no CSRCs
+        packet.push(0x80);
+        packet.push(((marker as u8) << 7) | H264_PAYLOAD_TYPE);
+        packet.extend_from_slice(&self.sequence_number.to_be_bytes());
+        packet.extend_from_slice(&rtp_timestamp.to_be_bytes());
+        packet.extend_from_slice(&self.ssrc.to_be_bytes());
+        packet.extend_from_slice(payload);
+        self.sequence_number = self.sequence_number.wrapping_add(1);
+        return packet;
+    }
+}
+
+/// Sends access units to a single RTP receiver.
+pub struct RtpSink {
+    socket: UdpSocket,
+    destination: SocketAddr,
+    clock: RtpClock,
+    packetizer: RtpH264Packetizer,
+}
+
+impl RtpSink {
+    /// Creates a sink which sends to destination from an ephemeral
+    /// port.
+    pub fn new(destination: SocketAddr) -> io::Result<RtpSink> {
+        let bind_addr: SocketAddr = match destination {
+            SocketAddr::V4(_) => "0.0.0.0:0".parse().unwrap(),
+            SocketAddr::V6(_) => "[::]:0".parse().unwrap(),
+        };
+        let socket = UdpSocket::bind(bind_addr)?;
+        // RFC 3550 wants the SSRC, sequence number and timestamp to
+        // start out random. This doesn't need to be good randomness.
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0)
+            ^ std::process::id() as u64;
+        let random = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        return Ok(RtpSink {
+            socket,
+            destination,
+            clock: RtpClock::new((random >> 32) as u32),
+            packetizer: RtpH264Packetizer::new(
+                random as u32,
+                (random >> 16) as u16,
+                DEFAULT_MAX_PACKET_SIZE,
+            ),
+        });
+    }
+
+    pub fn destination(&self) -> SocketAddr {
+        return self.destination;
+    }
+
+    /// Sends access_unit, which was built from the frame with the given
+    /// WUP timestamp.
+    pub fn send(&mut self, access_unit: &[u8], wup_timestamp: u32) -> io::Result<()> {
+        let rtp_timestamp = self.clock.convert(wup_timestamp);
+        for packet in self.packetizer.packetize(access_unit, rtp_timestamp) {
+            self.socket.send_to(&packet, self.destination)?;
+        }
+        return Ok(());
+    }
+
+    /// Returns an SDP description of this stream.
+    pub fn sdp(&self) -> String {
+        return sdp(self.destination);
+    }
+
+    pub fn write_sdp<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        return fs::write(path, self.sdp());
+    }
+}
+
+/// Returns an SDP description of an H.264 RTP stream sent to
+/// destination using the WUP's parameter sets.
+pub fn sdp(destination: SocketAddr) -> String {
+    let address_type = match destination {
+        SocketAddr::V4(_) => "IP4",
+        SocketAddr::V6(_) => "IP6",
+    };
+    return format!(
+        "v=0\r\n\
+         o=- 0 0 IN {address_type} {ip}\r\n\
+         s=drc-sim-rust\r\n\
+         c=IN {address_type} {ip}\r\n\
+         t=0 0\r\n\
+         m=video {port} RTP/AVP {pt}\r\n\
+         a=rtpmap:{pt} H264/{rate}\r\n\
+         a=fmtp:{pt} packetization-mode=1;profile-level-id={profile:02x}{constraints:02x}{level:02x};sprop-parameter-sets={sps},{pps}\r\n",
+        address_type = address_type,
+        ip = destination.ip(),
+        port = destination.port(),
+        pt = H264_PAYLOAD_TYPE,
+        rate = RTP_CLOCK_RATE,
+        profile = SPS[1],
+        constraints = SPS[2],
+        level = SPS[3],
+        sps = base64(&SPS),
+        pps = base64(&PPS),
+    );
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let group = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(group >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    return encoded;
+}
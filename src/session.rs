@@ -0,0 +1,765 @@
+// Copyright 2024 Dalton Durst and the drc-sim-rust contributors
+// SPDX-License-Identifier: MPL-2.0
+
+//! Everything one GamePad does, brought up together.
+//!
+//! A Session owns the GamePad's sockets, runs a thread for each channel
+//! the console talks to us on and sends input reports at the GamePad's
+//! rate. What comes in is handed to the application as SessionEvents on
+//! a single channel, stamped with the time since the session started.
+//! Video is read and parsed on one thread and assembled into frames on
+//! another, with a PacketQueue between them.
+//!
+//! ```no_run
+//! use drc_sim_rust_lib::session::{Session, SessionConfig, SessionEventKind};
+//!
+//! let mut session = Session::start(SessionConfig::default())?;
+//! for event in session.events().iter().take(100) {
+//!     if let SessionEventKind::Frame(frame) = event.kind {
+//!         println!("frame at {}", frame.timestamp);
+//!     }
+//! }
+//! session.shutdown()?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::{
+    io,
+    net::{IpAddr, SocketAddr, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use log::{debug, error, info, trace, warn};
+
+use crate::{
+    cmd::{BasicGamePadState, CmdPacket, CommandHandler, CommandStats},
+    device_profile::DeviceProfile,
+    feedback::{FeedbackEvent, FeedbackMonitor},
+    hid_sender::{HidSender, HidSenderStats, ReportHook, SharedInput, HID_REPORT_INTERVAL},
+    incoming_packet_parser::{
+        process_audio_packet, process_video_packet, WUPAudioPacket, WUPVideoPacket,
+    },
+    join_thread,
+    mic::{MicConfig, MicSender},
+    msg::{MessageHandler, MessageStats},
+    packet_organizer::{AssembledFrame, FrameAssembler},
+    packet_queue::{OverflowPolicy, PacketQueue, QueueStats},
+    sockets::{self, console_aud_addr, console_hid_addr, Datagram, Pad, DEFAULT_CONSOLE_IP},
+    WUP_VID_PACKET_BUFFER_SIZE,
+};
+
+#[cfg(target_os = "linux")]
+use crate::sockets::BatchReceiver;
+
+/// How long the receive threads wait for a packet before checking
+/// whether the session is shutting down or a stream has stalled.
+pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// How long video or audio can go quiet before the session reports it
+/// stalled. The console sends both many times a second while it is
+/// streaming.
+pub const DEFAULT_STALL_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How many events can wait for the application before new ones are
+/// dropped.
+pub const DEFAULT_EVENT_CAPACITY: usize = 256;
+
+/// How many video packets can wait between the receiving thread and
+/// the assembler, several frames' worth.
+pub const DEFAULT_VIDEO_QUEUE_CAPACITY: usize = 512;
+
+pub struct SessionConfig {
+    pub pad: Pad,
+    /// The address our sockets bind to.
+    pub bind_ip: String,
+    pub console_ip: IpAddr,
+    /// What we answer the console's commands with.
+    pub profile: DeviceProfile,
+    pub hid_interval: Duration,
+    /// Runs on every input report before it is sent.
+    pub hid_hook: Option<ReportHook>,
+    pub event_capacity: usize,
+    /// How many parsed video packets can wait for the assembler, and
+    /// what happens to the rest when it falls behind.
+    pub video_queue_capacity: usize,
+    pub video_overflow: OverflowPolicy,
+    /// How long each socket read waits, which is also how often stalls
+    /// and shutdown are noticed.
+    pub read_timeout: Duration,
+    /// How long the video and audio streams can go without a packet
+    /// before a Stalled event, or None to never report stalls.
+    pub stall_timeout: Option<Duration>,
+    /// Receive video this many datagrams at a time with recvmmsg,
+    /// rather than one per syscall. Only works on Linux; elsewhere
+    /// video is always read one datagram at a time.
+    pub batch_size: Option<usize>,
+    /// Stamp every video and audio packet, and so every frame, with
+    /// when the kernel received it. Only works on Linux.
+    pub receive_timestamps: bool,
+    /// Ask the kernel for receive buffers this big on the video and
+    /// audio sockets, so bursts of video aren't dropped before we read
+    /// them. Only works on Linux.
+    pub receive_buffer_size: Option<usize>,
+}
+
+impl Default for SessionConfig {
+    fn default() -> SessionConfig {
+        return SessionConfig {
+            pad: Pad::First,
+            bind_ip: String::from("0.0.0.0"),
+            console_ip: DEFAULT_CONSOLE_IP.parse().unwrap(),
+            profile: DeviceProfile::default(),
+            hid_interval: HID_REPORT_INTERVAL,
+            hid_hook: None,
+            event_capacity: DEFAULT_EVENT_CAPACITY,
+            video_queue_capacity: DEFAULT_VIDEO_QUEUE_CAPACITY,
+            video_overflow: OverflowPolicy::default(),
+            read_timeout: DEFAULT_READ_TIMEOUT,
+            stall_timeout: Some(DEFAULT_STALL_TIMEOUT),
+            batch_size: None,
+            receive_timestamps: false,
+            receive_buffer_size: None,
+        };
+    }
+}
+
+/// The sockets a Session uses and where it sends what it produces.
+pub struct SessionSockets {
+    pub video: UdpSocket,
+    pub audio: UdpSocket,
+    pub hid: UdpSocket,
+    pub cmd: UdpSocket,
+    pub msg: UdpSocket,
+    pub hid_destination: SocketAddr,
+    pub mic_destination: SocketAddr,
+}
+
+impl SessionSockets {
+    /// Binds the GamePad's usual ports for config's pad.
+    pub fn bind(config: &SessionConfig) -> io::Result<SessionSockets> {
+        let ip = config.bind_ip.as_str();
+        let pad = config.pad;
+        return Ok(SessionSockets {
+            video: sockets::get_vid_socket(ip, pad)?,
+            audio: sockets::get_aud_socket(ip, pad)?,
+            hid: sockets::get_hid_socket(ip, pad)?,
+            cmd: sockets::get_cmd_socket(ip, pad)?,
+            msg: sockets::get_msg_socket(ip, pad)?,
+            hid_destination: console_hid_addr(config.console_ip, pad),
+            mic_destination: console_aud_addr(config.console_ip, pad),
+        });
+    }
+}
+
+/// The channels a Session runs a thread for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Channel {
+    Video,
+    Audio,
+    Hid,
+    Command,
+    Message,
+}
+
+#[derive(Debug)]
+pub enum SessionEventKind {
+    Frame(AssembledFrame),
+    Audio(WUPAudioPacket),
+    Feedback(FeedbackEvent),
+    /// A packet from the console on the command channel. It has
+    /// already been answered.
+    Command(CmdPacket),
+    Message(Vec<u8>),
+    /// A channel's thread stopped because of an error. The rest of the
+    /// session keeps running.
+    Error {
+        channel: Channel,
+        error: String,
+    },
+    /// A stream that was flowing has had no packets for silent_for,
+    /// which usually means the console stopped streaming or went away.
+    Stalled {
+        channel: Channel,
+        silent_for: Duration,
+    },
+    /// Packets arrived on a stalled stream again.
+    Resumed {
+        channel: Channel,
+        stalled_for: Duration,
+    },
+}
+
+#[derive(Debug)]
+pub struct SessionEvent {
+    /// The time since the session started.
+    pub at: Duration,
+    pub kind: SessionEventKind,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SessionStats {
+    pub video_packets: u64,
+    pub bad_video_packets: u64,
+    pub frames: u64,
+    /// Frames the assembler gave up on because packets never came.
+    pub dropped_frames: u64,
+    /// Video packets the assembler couldn't use, such as duplicates.
+    pub rejected_video_packets: u64,
+    /// Video datagrams the kernel threw away because the socket's
+    /// receive buffer was full. Dropped frames beyond what these
+    /// explain were lost over the air. Always 0 off Linux.
+    pub video_kernel_drops: u64,
+    pub audio_packets: u64,
+    pub bad_audio_packets: u64,
+    pub audio_kernel_drops: u64,
+    /// The queue between the video socket and the assembler.
+    pub video_queue: QueueStats,
+    /// Events dropped because the application wasn't reading them.
+    pub dropped_events: u64,
+    pub stalls: u64,
+    pub hid: HidSenderStats,
+    pub commands: CommandStats,
+    pub messages: MessageStats,
+}
+
+/// Hands events to the application, dropping them when it falls
+/// behind rather than letting them pile up.
+#[derive(Clone)]
+struct EventSink {
+    sender: SyncSender<SessionEvent>,
+    start: Instant,
+    stats: Arc<Mutex<SessionStats>>,
+}
+
+impl EventSink {
+    fn send(&self, kind: SessionEventKind) {
+        let event = SessionEvent {
+            at: self.start.elapsed(),
+            kind,
+        };
+        match self.sender.try_send(event) {
+            Ok(()) => {}
+            Err(TrySendError::Full(event)) => {
+                let mut stats = self.stats.lock().unwrap();
+                stats.dropped_events += 1;
+                if stats.dropped_events == 1 {
+                    warn!("Events are coming faster than they're read, dropping some");
+                }
+                trace!("Dropped {:?}", event);
+            }
+            // Nobody listening isn't an error for us.
+            Err(TrySendError::Disconnected(_)) => {}
+        }
+    }
+
+    /// Records the kernel's drop count for channel's socket, which
+    /// comes with every datagram once it is non-zero.
+    fn kernel_drops(&self, channel: Channel, datagram: &Datagram) {
+        let Some(drops) = datagram.kernel_drops else {
+            return;
+        };
+        let mut stats = self.stats.lock().unwrap();
+        let count = match channel {
+            Channel::Video => &mut stats.video_kernel_drops,
+            Channel::Audio => &mut stats.audio_kernel_drops,
+            _ => return,
+        };
+        let drops = drops as u64;
+        if drops > *count {
+            warn!(
+                "The kernel dropped {} {:?} packets because the receive buffer was full",
+                drops - *count,
+                channel
+            );
+            *count = drops;
+        }
+    }
+}
+
+/// One emulated GamePad talking to the console.
+pub struct Session {
+    pad: Pad,
+    start: Instant,
+    input: SharedInput,
+    events: Receiver<SessionEvent>,
+    stats: Arc<Mutex<SessionStats>>,
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<io::Result<()>>>,
+    hid_sender: Option<HidSender>,
+    mic_sender: Option<MicSender>,
+    audio_socket: UdpSocket,
+    mic_destination: SocketAddr,
+    video_queue: PacketQueue<WUPVideoPacket>,
+}
+
+impl Session {
+    /// Binds the GamePad's ports and starts every channel.
+    pub fn start(config: SessionConfig) -> io::Result<Session> {
+        let sockets = SessionSockets::bind(&config)?;
+        return Session::start_with_sockets(config, sockets);
+    }
+
+    /// Starts every channel on sockets that are already bound. The
+    /// addresses in config are not used.
+    pub fn start_with_sockets(
+        config: SessionConfig,
+        sockets: SessionSockets,
+    ) -> io::Result<Session> {
+        let start = Instant::now();
+        let pad = config.pad;
+        let stop = Arc::new(AtomicBool::new(false));
+        let stats = Arc::new(Mutex::new(SessionStats::default()));
+        let (sender, events) = mpsc::sync_channel(config.event_capacity);
+        let sink = EventSink {
+            sender,
+            start,
+            stats: stats.clone(),
+        };
+        for socket in [
+            &sockets.video,
+            &sockets.audio,
+            &sockets.hid,
+            &sockets.cmd,
+            &sockets.msg,
+        ] {
+            socket.set_read_timeout(Some(config.read_timeout))?;
+        }
+        #[cfg(target_os = "linux")]
+        for (channel, socket) in [
+            (Channel::Video, &sockets.video),
+            (Channel::Audio, &sockets.audio),
+        ] {
+            crate::sockets::enable_drop_counter(socket)?;
+            if config.receive_timestamps {
+                crate::sockets::enable_receive_timestamps(socket)?;
+            }
+            if let Some(requested) = config.receive_buffer_size {
+                let size = crate::sockets::set_receive_buffer_size(socket, requested)?;
+                if size < requested {
+                    warn!(
+                        "{}: asked for a {} byte {:?} receive buffer but got {}; raise net.core.rmem_max",
+                        pad, requested, channel, size
+                    );
+                } else {
+                    info!("{}: {:?} receive buffer is {} bytes", pad, channel, size);
+                }
+            }
+        }
+
+        let input = SharedInput::new();
+        let hid_sender = HidSender::start_with_hook(
+            sockets.hid.try_clone()?,
+            sockets.hid_destination,
+            input.clone(),
+            config.hid_interval,
+            config.hid_hook,
+        );
+
+        let mut session = Session {
+            pad,
+            start,
+            input,
+            events,
+            stats: stats.clone(),
+            stop: stop.clone(),
+            threads: Vec::new(),
+            hid_sender: Some(hid_sender),
+            mic_sender: None,
+            audio_socket: sockets.audio.try_clone()?,
+            mic_destination: sockets.mic_destination,
+            video_queue: PacketQueue::new(config.video_queue_capacity, config.video_overflow),
+        };
+
+        // The video thread only reads and parses, so that a slow
+        // assembler or application costs packets in the queue, where
+        // they are counted, rather than in the kernel.
+        let video_queue = session.video_queue.clone();
+        session.spawn(
+            Channel::Video,
+            sockets.video,
+            ChannelReader::new(config.batch_size, true),
+            config.stall_timeout,
+            &sink,
+            move |datagram, _, sink| {
+                sink.kernel_drops(Channel::Video, &datagram);
+                let mut packet = match process_video_packet(datagram.data) {
+                    Some(packet) => packet,
+                    None => {
+                        sink.stats.lock().unwrap().bad_video_packets += 1;
+                        return Ok(());
+                    }
+                };
+                packet.received_at = datagram.received_at;
+                sink.stats.lock().unwrap().video_packets += 1;
+                video_queue.push(packet);
+                return Ok(());
+            },
+        );
+        session.spawn_assembler(&sink, config.read_timeout);
+
+        let feedback_sink = sink.clone();
+        let mut rumble = FeedbackMonitor::new(move |event| {
+            feedback_sink.send(SessionEventKind::Feedback(event));
+        });
+        session.spawn(
+            Channel::Audio,
+            sockets.audio,
+            ChannelReader::new(None, true),
+            config.stall_timeout,
+            &sink,
+            move |datagram, _, sink| {
+                sink.kernel_drops(Channel::Audio, &datagram);
+                let mut packet = match process_audio_packet(datagram.data) {
+                    Some(packet) => packet,
+                    None => {
+                        sink.stats.lock().unwrap().bad_audio_packets += 1;
+                        return Ok(());
+                    }
+                };
+                packet.received_at = datagram.received_at;
+                sink.stats.lock().unwrap().audio_packets += 1;
+                rumble.on_audio_packet(&packet);
+                sink.send(SessionEventKind::Audio(packet));
+                return Ok(());
+            },
+        );
+
+        let feedback_sink = sink.clone();
+        let mut hid_output = FeedbackMonitor::new(move |event| {
+            feedback_sink.send(SessionEventKind::Feedback(event));
+        });
+        session.spawn(
+            Channel::Hid,
+            sockets.hid,
+            ChannelReader::new(None, false),
+            None,
+            &sink,
+            move |datagram, _, _| {
+                hid_output.on_hid_packet(datagram.data);
+                return Ok(());
+            },
+        );
+
+        let mut commands = CommandHandler::new(BasicGamePadState::new(config.profile));
+        session.spawn(
+            Channel::Command,
+            sockets.cmd,
+            ChannelReader::new(None, false),
+            None,
+            &sink,
+            move |datagram, socket, sink| {
+                for reply in commands.handle(datagram.data) {
+                    socket.send_to(&reply, datagram.source)?;
+                }
+                sink.stats.lock().unwrap().commands = commands.stats().clone();
+                if let Ok(packet) = CmdPacket::parse(datagram.data) {
+                    sink.send(SessionEventKind::Command(packet));
+                }
+                return Ok(());
+            },
+        );
+
+        let mut messages = MessageHandler::default();
+        session.spawn(
+            Channel::Message,
+            sockets.msg,
+            ChannelReader::new(None, false),
+            None,
+            &sink,
+            move |datagram, socket, sink| {
+                if let Some(reply) = messages.handle(datagram.data) {
+                    socket.send_to(&reply, datagram.source)?;
+                }
+                sink.stats.lock().unwrap().messages = messages.stats().clone();
+                sink.send(SessionEventKind::Message(datagram.data.to_vec()));
+                return Ok(());
+            },
+        );
+
+        info!("{}: session started", pad);
+        return Ok(session);
+    }
+
+    /// Runs on_packet for every packet that arrives on socket, on a
+    /// thread of its own, until the session stops or on_packet fails.
+    /// With a stall_timeout, the channel reports when it goes quiet.
+    fn spawn<F>(
+        &mut self,
+        channel: Channel,
+        socket: UdpSocket,
+        mut reader: ChannelReader,
+        stall_timeout: Option<Duration>,
+        sink: &EventSink,
+        mut on_packet: F,
+    ) where
+        F: FnMut(Datagram, &UdpSocket, &EventSink) -> io::Result<()> + Send + 'static,
+    {
+        let stop = self.stop.clone();
+        let sink = sink.clone();
+        let pad = self.pad;
+        let mut stalls = stall_timeout.map(StallDetector::new);
+        let thread = thread::spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                let result = reader.read(&socket, |datagram| {
+                    let resumed = stalls.as_mut().and_then(|s| s.on_packet(Instant::now()));
+                    if let Some(stalled_for) = resumed {
+                        info!("{}: {:?} resumed after {:?}", pad, channel, stalled_for);
+                        sink.send(SessionEventKind::Resumed {
+                            channel,
+                            stalled_for,
+                        });
+                    }
+                    return on_packet(datagram, &socket, &sink);
+                });
+                if let Err(err) = result {
+                    if !is_timeout(&err) {
+                        error!("{}: {:?} channel stopped: {}", pad, channel, err);
+                        sink.send(SessionEventKind::Error {
+                            channel,
+                            error: err.to_string(),
+                        });
+                        return Err(err);
+                    }
+                }
+                // Checked after every read, since a trickle of packets
+                // on another channel doesn't keep this one alive.
+                if let Some(silent_for) = stalls.as_mut().and_then(|s| s.check(Instant::now())) {
+                    warn!("{}: no {:?} packets for {:?}", pad, channel, silent_for);
+                    sink.stats.lock().unwrap().stalls += 1;
+                    sink.send(SessionEventKind::Stalled {
+                        channel,
+                        silent_for,
+                    });
+                }
+            }
+            debug!("{}: {:?} channel stopped", pad, channel);
+            return Ok(());
+        });
+        self.threads.push(thread);
+    }
+
+    /// Puts frames together from the video queue on a thread of its
+    /// own, until the session stops.
+    fn spawn_assembler(&mut self, sink: &EventSink, poll_interval: Duration) {
+        let stop = self.stop.clone();
+        let sink = sink.clone();
+        let queue = self.video_queue.clone();
+        let mut assembler = FrameAssembler::for_pad(self.pad);
+        let thread = thread::spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                let Some(packet) = queue.pop_timeout(poll_interval) else {
+                    continue;
+                };
+                let frame = assembler.add_packet(packet);
+                let mut stats = sink.stats.lock().unwrap();
+                stats.dropped_frames = assembler.dropped_frames();
+                stats.rejected_video_packets = assembler.rejected_packets();
+                if let Some(frame) = frame {
+                    stats.frames += 1;
+                    drop(stats);
+                    sink.send(SessionEventKind::Frame(frame));
+                }
+            }
+            // A receiving thread blocked on a full queue would never
+            // see the stop otherwise.
+            queue.close();
+            return Ok(());
+        });
+        self.threads.push(thread);
+    }
+
+    pub fn pad(&self) -> Pad {
+        return self.pad;
+    }
+
+    /// The time since the session started, on the same clock as the
+    /// events' timestamps.
+    pub fn elapsed(&self) -> Duration {
+        return self.start.elapsed();
+    }
+
+    /// The input that is sent to the console on every HID tick.
+    pub fn input(&self) -> &SharedInput {
+        return &self.input;
+    }
+
+    pub fn events(&self) -> &Receiver<SessionEvent> {
+        return &self.events;
+    }
+
+    pub fn stats(&self) -> SessionStats {
+        let mut stats = self.stats.lock().unwrap().clone();
+        if let Some(hid_sender) = &self.hid_sender {
+            stats.hid = hid_sender.stats();
+        }
+        stats.video_queue = self.video_queue.stats();
+        return stats;
+    }
+
+    /// Starts streaming samples to the console as mic audio, replacing
+    /// whatever was streaming before.
+    pub fn start_mic<I>(&mut self, samples: I, config: MicConfig) -> io::Result<()>
+    where
+        I: IntoIterator<Item = i16>,
+        I::IntoIter: Send + 'static,
+    {
+        let socket = self.audio_socket.try_clone()?;
+        self.mic_sender = Some(MicSender::start(
+            socket,
+            self.mic_destination,
+            samples,
+            config,
+        )?);
+        return Ok(());
+    }
+
+    /// Stops every channel and waits for their threads. Returns the
+    /// first error that stopped a channel early, if there was one.
+    pub fn shutdown(&mut self) -> io::Result<()> {
+        self.stop.store(true, Ordering::Relaxed);
+        let mut result = Ok(());
+        for sender_result in [
+            self.hid_sender.take().map(|mut sender| sender.stop()),
+            self.mic_sender.take().map(|mut sender| sender.stop()),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if result.is_ok() {
+                result = sender_result;
+            }
+        }
+        for thread in self.threads.drain(..) {
+            let thread_result = join_thread(thread);
+            if result.is_ok() {
+                result = thread_result;
+            }
+        }
+        info!("{}: session stopped", self.pad);
+        return result;
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        if !self.threads.is_empty() || self.hid_sender.is_some() {
+            let _ = self.shutdown();
+        }
+    }
+}
+
+/// How a channel's thread reads its socket.
+enum ChannelReader {
+    Single {
+        buf: Box<[u8; WUP_VID_PACKET_BUFFER_SIZE]>,
+        control: bool,
+    },
+    #[cfg(target_os = "linux")]
+    Batch(BatchReceiver),
+}
+
+impl ChannelReader {
+    /// With control, reads use recvmsg so each datagram carries the
+    /// receive timestamp and drop count, if they are enabled on the
+    /// socket. Batches always do.
+    fn new(batch_size: Option<usize>, control: bool) -> ChannelReader {
+        return match batch_size {
+            #[cfg(target_os = "linux")]
+            Some(batch_size) => ChannelReader::Batch(BatchReceiver::new(batch_size)),
+            _ => ChannelReader::Single {
+                buf: Box::new([0; WUP_VID_PACKET_BUFFER_SIZE]),
+                control,
+            },
+        };
+    }
+
+    /// Waits for packets and hands each one that arrives to on_packet.
+    fn read<F>(&mut self, socket: &UdpSocket, mut on_packet: F) -> io::Result<()>
+    where
+        F: FnMut(Datagram) -> io::Result<()>,
+    {
+        match self {
+            #[cfg(target_os = "linux")]
+            ChannelReader::Single { buf, control: true } => {
+                return on_packet(sockets::recv_datagram(socket, &mut buf[..])?);
+            }
+            ChannelReader::Single { buf, .. } => {
+                let (size, source) = socket.recv_from(&mut buf[..])?;
+                return on_packet(Datagram {
+                    data: &buf[..size],
+                    source,
+                    received_at: None,
+                    kernel_drops: None,
+                });
+            }
+            #[cfg(target_os = "linux")]
+            ChannelReader::Batch(batch) => {
+                batch.recv(socket)?;
+                for datagram in batch.packets() {
+                    on_packet(datagram)?;
+                }
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Notices when a stream that was flowing stops, and when it starts
+/// again. A stream that has never had a packet isn't stalled; it just
+/// hasn't started.
+#[derive(Debug, Clone)]
+pub struct StallDetector {
+    timeout: Duration,
+    last_packet: Option<Instant>,
+    stalled: bool,
+}
+
+impl StallDetector {
+    pub fn new(timeout: Duration) -> StallDetector {
+        return StallDetector {
+            timeout,
+            last_packet: None,
+            stalled: false,
+        };
+    }
+
+    pub fn is_stalled(&self) -> bool {
+        return self.stalled;
+    }
+
+    /// Records a packet arriving at now. Returns how long the stream
+    /// had been quiet if this packet ends a stall.
+    pub fn on_packet(&mut self, now: Instant) -> Option<Duration> {
+        let last_packet = self.last_packet.replace(now);
+        if !self.stalled {
+            return None;
+        }
+        self.stalled = false;
+        return last_packet.map(|last| now.saturating_duration_since(last));
+    }
+
+    /// Returns how long the stream has been quiet if it has stalled
+    /// since the last check.
+    pub fn check(&mut self, now: Instant) -> Option<Duration> {
+        let last_packet = self.last_packet?;
+        let silent_for = now.saturating_duration_since(last_packet);
+        if self.stalled || silent_for < self.timeout {
+            return None;
+        }
+        self.stalled = true;
+        return Some(silent_for);
+    }
+}
+
+fn is_timeout(err: &io::Error) -> bool {
+    return matches!(
+        err.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    );
+}
@@ -1,23 +1,669 @@
 // Copyright 2024 Dalton Durst and the drc-sim-rust contributors
 // SPDX-License-Identifier: MPL-2.0
 
-use std::net::UdpSocket;
+use core::fmt;
+#[cfg(unix)]
+use std::{ffi::CStr, net::Ipv6Addr};
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+    time::SystemTime,
+};
 
-// const PORT_WII_MSG: u16 = 50010;
+#[cfg(target_os = "linux")]
+use std::{
+    os::fd::AsRawFd,
+    time::{Duration, UNIX_EPOCH},
+};
+
+const PORT_WII_MSG: u16 = 50010;
 const PORT_WUP_VID: u16 = 50120;
 const PORT_WUP_AUD: u16 = 50121;
-// const PORT_WII_HID: u16 = 50122;
-// const PORT_WII_CMD: u16 = 50123;
+const PORT_WII_HID: u16 = 50122;
+const PORT_WII_CMD: u16 = 50123;
+
+/// How far the second GamePad's ports are from the first GamePad's.
+pub const PAD_PORT_OFFSET: u16 = 100;
+
+/// The console's ports are 100 lower than the GamePad's, so the
+/// GamePad sends input from 50122 to 50022.
+const CONSOLE_PORT_OFFSET: u16 = 100;
+
+/// The address the console usually gives itself on the GamePad network.
+pub const DEFAULT_CONSOLE_IP: &str = "192.168.1.10";
+
+/// The console can stream to two GamePads at once. Each one has its own
+/// set of ports, so every socket belongs to exactly one Pad.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Pad {
+    #[default]
+    First,
+    Second,
+}
+
+impl Pad {
+    pub const ALL: [Pad; 2] = [Pad::First, Pad::Second];
+
+    pub fn from_index(index: u8) -> Option<Pad> {
+        return Pad::ALL.get(index as usize).copied();
+    }
+
+    pub fn index(&self) -> u8 {
+        return match self {
+            Pad::First => 0,
+            Pad::Second => 1,
+        };
+    }
+
+    /// Returns this Pad's version of a port that belongs to the first
+    /// GamePad.
+    pub fn port(&self, base_port: u16) -> u16 {
+        return base_port + PAD_PORT_OFFSET * self.index() as u16;
+    }
+
+    /// Returns the console's port that matches one of this Pad's ports
+    /// from port().
+    pub fn console_port(&self, base_port: u16) -> u16 {
+        return self.port(base_port) - CONSOLE_PORT_OFFSET;
+    }
+}
+
+impl fmt::Display for Pad {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "pad {}", self.index())
+    }
+}
 
 fn get_socket(dest_ip: &str, port: u16) -> Result<UdpSocket, std::io::Error> {
-    let addr = format!("{}:{}", dest_ip, port);
-    return UdpSocket::bind(addr);
+    return UdpSocket::bind((dest_ip, port));
+}
+
+pub fn get_vid_socket(dest_ip: &str, pad: Pad) -> Result<UdpSocket, std::io::Error> {
+    return get_socket(dest_ip, pad.port(PORT_WUP_VID));
+}
+
+pub fn get_aud_socket(dest_ip: &str, pad: Pad) -> Result<UdpSocket, std::io::Error> {
+    return get_socket(dest_ip, pad.port(PORT_WUP_AUD));
+}
+
+/// Returns where pad's mic audio goes on the console at console_ip.
+pub fn console_aud_addr(console_ip: IpAddr, pad: Pad) -> SocketAddr {
+    return SocketAddr::new(console_ip, pad.console_port(PORT_WUP_AUD));
+}
+
+pub fn get_hid_socket(dest_ip: &str, pad: Pad) -> Result<UdpSocket, std::io::Error> {
+    return get_socket(dest_ip, pad.port(PORT_WII_HID));
+}
+
+/// Returns where pad's input reports go on the console at console_ip.
+pub fn console_hid_addr(console_ip: IpAddr, pad: Pad) -> SocketAddr {
+    return SocketAddr::new(console_ip, pad.console_port(PORT_WII_HID));
+}
+
+pub fn get_cmd_socket(dest_ip: &str, pad: Pad) -> Result<UdpSocket, std::io::Error> {
+    return get_socket(dest_ip, pad.port(PORT_WII_CMD));
+}
+
+/// Returns where pad's command packets go on the console at console_ip.
+pub fn console_cmd_addr(console_ip: IpAddr, pad: Pad) -> SocketAddr {
+    return SocketAddr::new(console_ip, pad.console_port(PORT_WII_CMD));
+}
+
+pub fn get_msg_socket(dest_ip: &str, pad: Pad) -> Result<UdpSocket, std::io::Error> {
+    return get_socket(dest_ip, pad.port(PORT_WII_MSG));
+}
+
+pub struct BindError {
+    pub kind: BindErrorKind,
+    pub text: String,
+}
+
+impl BindError {
+    fn new(kind: BindErrorKind, text: String) -> BindError {
+        return BindError { kind, text };
+    }
+    fn format_error(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}: {}", self.kind, self.text)
+    }
+}
+
+impl fmt::Display for BindError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return self.format_error(f);
+    }
+}
+
+impl fmt::Debug for BindError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return self.format_error(f);
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum BindErrorKind {
+    /// The interfaces couldn't be listed, or can't be on this
+    /// platform.
+    Io,
+    NoSuchInterface,
+    /// The interface exists but has no IPv4 address, which usually
+    /// means it isn't connected to the console yet.
+    NoIpv4Address,
+    /// No interface has the address.
+    AddressNotPresent,
+}
+
+/// Where to bind the GamePad's sockets: an address, or the name of the
+/// interface that is connected to the console.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BindAddress {
+    Ip(IpAddr),
+    Interface(String),
+}
+
+impl Default for BindAddress {
+    /// Every interface.
+    fn default() -> BindAddress {
+        return BindAddress::Ip(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+    }
+}
+
+impl fmt::Display for BindAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            BindAddress::Ip(ip) => write!(f, "{}", ip),
+            BindAddress::Interface(name) => write!(f, "{}", name),
+        };
+    }
+}
+
+impl BindAddress {
+    /// Reads an IP address, or anything else as an interface name.
+    pub fn parse(text: &str) -> BindAddress {
+        return match text.parse() {
+            Ok(ip) => BindAddress::Ip(ip),
+            Err(_) => BindAddress::Interface(text.to_string()),
+        };
+    }
+
+    /// Finds the IP address to bind to, checking that it belongs to
+    /// this machine.
+    pub fn resolve(&self) -> Result<IpAddr, BindError> {
+        if let BindAddress::Ip(ip) = self {
+            if ip.is_unspecified() {
+                return Ok(*ip);
+            }
+        }
+        return self.resolve_from(&interface_addresses().map_err(|err| {
+            BindError::new(
+                BindErrorKind::Io,
+                format!("couldn't list network interfaces: {}", err),
+            )
+        })?);
+    }
+
+    /// Like resolve, but against a given list of interfaces.
+    pub fn resolve_from(&self, interfaces: &[InterfaceAddress]) -> Result<IpAddr, BindError> {
+        match self {
+            BindAddress::Ip(ip) => {
+                if ip.is_unspecified() || interfaces.iter().any(|i| i.ip == Some(*ip)) {
+                    return Ok(*ip);
+                }
+                return Err(BindError::new(
+                    BindErrorKind::AddressNotPresent,
+                    format!(
+                        "no interface has the address {} (addresses here: {})",
+                        ip,
+                        list(interfaces.iter().filter_map(|i| i.ip))
+                    ),
+                ));
+            }
+            BindAddress::Interface(name) => {
+                let mut found = false;
+                for interface in interfaces.iter().filter(|i| &i.name == name) {
+                    found = true;
+                    if let Some(ip @ IpAddr::V4(_)) = interface.ip {
+                        return Ok(ip);
+                    }
+                }
+                if found {
+                    return Err(BindError::new(
+                        BindErrorKind::NoIpv4Address,
+                        format!(
+                            "{} has no IPv4 address, is it connected to the console?",
+                            name
+                        ),
+                    ));
+                }
+                let mut names: Vec<&str> = interfaces.iter().map(|i| i.name.as_str()).collect();
+                names.sort();
+                names.dedup();
+                return Err(BindError::new(
+                    BindErrorKind::NoSuchInterface,
+                    format!("no interface named {} (interfaces: {})", name, list(names)),
+                ));
+            }
+        }
+    }
+}
+
+fn list<T: fmt::Display, I: IntoIterator<Item = T>>(items: I) -> String {
+    let items: Vec<String> = items.into_iter().map(|item| item.to_string()).collect();
+    if items.is_empty() {
+        return String::from("none");
+    }
+    return items.join(", ");
+}
+
+/// One of this machine's interfaces and one of its addresses. An
+/// interface without any IP address is listed once with ip None.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceAddress {
+    pub name: String,
+    pub ip: Option<IpAddr>,
 }
 
-pub fn get_vid_socket(dest_ip: &str) -> Result<UdpSocket, std::io::Error> {
-    return get_socket(dest_ip, PORT_WUP_VID);
+/// Lists this machine's interfaces and their addresses.
+#[cfg(unix)]
+pub fn interface_addresses() -> io::Result<Vec<InterfaceAddress>> {
+    let mut first: *mut libc::ifaddrs = std::ptr::null_mut();
+    // SAFETY: getifaddrs fills in first with a list that we free below.
+    if unsafe { libc::getifaddrs(&mut first) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut addresses: Vec<InterfaceAddress> = Vec::new();
+    let mut current = first;
+    while !current.is_null() {
+        // SAFETY: current is a node of the list getifaddrs returned,
+        // and every node has a name. The address, when present, is the
+        // sockaddr type that its family says.
+        let (name, ip, next) = unsafe {
+            let ifa = &*current;
+            let name = CStr::from_ptr(ifa.ifa_name).to_string_lossy().into_owned();
+            let ip = if ifa.ifa_addr.is_null() {
+                None
+            } else {
+                match (*ifa.ifa_addr).sa_family as i32 {
+                    libc::AF_INET => {
+                        let addr = &*(ifa.ifa_addr as *const libc::sockaddr_in);
+                        Some(IpAddr::V4(Ipv4Addr::from(u32::from_be(
+                            addr.sin_addr.s_addr,
+                        ))))
+                    }
+                    libc::AF_INET6 => {
+                        let addr = &*(ifa.ifa_addr as *const libc::sockaddr_in6);
+                        Some(IpAddr::V6(Ipv6Addr::from(addr.sin6_addr.s6_addr)))
+                    }
+                    _ => None,
+                }
+            };
+            (name, ip, ifa.ifa_next)
+        };
+        let known = addresses.iter().position(|a| a.name == name);
+        match (known, ip) {
+            (None, ip) => addresses.push(InterfaceAddress { name, ip }),
+            (Some(index), Some(ip)) if addresses[index].ip.is_none() => {
+                addresses[index].ip = Some(ip)
+            }
+            (Some(_), Some(ip)) => addresses.push(InterfaceAddress { name, ip: Some(ip) }),
+            (Some(_), None) => {}
+        }
+        current = next;
+    }
+    // SAFETY: first came from getifaddrs and nothing refers to it now.
+    unsafe { libc::freeifaddrs(first) };
+    return Ok(addresses);
+}
+
+/// Lists this machine's interfaces and their addresses. Only done with
+/// getifaddrs, so it always fails elsewhere.
+#[cfg(not(unix))]
+pub fn interface_addresses() -> io::Result<Vec<InterfaceAddress>> {
+    return Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "listing interfaces is only supported on Unix",
+    ));
+}
+
+/// How many datagrams a BatchReceiver takes per syscall by default,
+/// a bit more than the packets in a large video frame.
+#[cfg(target_os = "linux")]
+pub const DEFAULT_BATCH_SIZE: usize = 64;
+
+/// One datagram read from a socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Datagram<'a> {
+    pub data: &'a [u8],
+    pub source: SocketAddr,
+    /// When the kernel received it, if receive timestamps are enabled
+    /// on the socket.
+    pub received_at: Option<SystemTime>,
+    /// How many datagrams the kernel had thrown away on this socket,
+    /// because its receive buffer was full, when this one was queued.
+    /// Only there if the drop counter is enabled, and only once
+    /// something has been dropped.
+    pub kernel_drops: Option<u32>,
+}
+
+/// Sets an int socket option at SOL_SOCKET.
+#[cfg(target_os = "linux")]
+fn set_socket_option(
+    socket: &impl AsRawFd,
+    option: libc::c_int,
+    value: libc::c_int,
+) -> io::Result<()> {
+    // SAFETY: the option value is a c_int that lives across the call.
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            option,
+            &value as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    return Ok(());
+}
+
+/// Asks the kernel to timestamp every datagram socket receives, with
+/// SO_TIMESTAMPNS. The timestamps are taken when the packet arrives, so
+/// they don't include the time it waited for us to read it. They come
+/// back from recv_datagram and BatchReceiver. Any socket will do,
+/// including the tokio ones from async_net.
+#[cfg(target_os = "linux")]
+pub fn enable_receive_timestamps(socket: &impl AsRawFd) -> io::Result<()> {
+    return set_socket_option(socket, libc::SO_TIMESTAMPNS, 1);
+}
+
+/// Asks the kernel to count the datagrams it drops on socket because
+/// the receive buffer is full, with SO_RXQ_OVFL. Those are lost on our
+/// side rather than over the air. The count comes back with every
+/// datagram from recv_datagram and BatchReceiver.
+#[cfg(target_os = "linux")]
+pub fn enable_drop_counter(socket: &impl AsRawFd) -> io::Result<()> {
+    return set_socket_option(socket, libc::SO_RXQ_OVFL, 1);
+}
+
+/// Asks for a receive buffer of bytes with SO_RCVBUF, and returns the
+/// size the kernel actually gave us. Linux doubles the request to
+/// leave room for its own bookkeeping, and caps it at
+/// net.core.rmem_max, so the result may be bigger or smaller.
+#[cfg(target_os = "linux")]
+pub fn set_receive_buffer_size(socket: &impl AsRawFd, bytes: usize) -> io::Result<usize> {
+    let bytes = bytes.min(libc::c_int::MAX as usize) as libc::c_int;
+    set_socket_option(socket, libc::SO_RCVBUF, bytes)?;
+    return receive_buffer_size(socket);
+}
+
+/// The size of socket's receive buffer, as SO_RCVBUF reports it.
+#[cfg(target_os = "linux")]
+pub fn receive_buffer_size(socket: &impl AsRawFd) -> io::Result<usize> {
+    let mut value: libc::c_int = 0;
+    let mut length = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    // SAFETY: value and length live across the call, and length is
+    // value's size.
+    let result = unsafe {
+        libc::getsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_RCVBUF,
+            &mut value as *mut libc::c_int as *mut libc::c_void,
+            &mut length,
+        )
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    return Ok(value as usize);
+}
+
+/// Like recv_from, but with the receive timestamp and drop count when
+/// they are enabled on socket.
+#[cfg(target_os = "linux")]
+pub fn recv_datagram<'a>(socket: &impl AsRawFd, buf: &'a mut [u8]) -> io::Result<Datagram<'a>> {
+    use std::mem;
+
+    // SAFETY: sockaddr_storage and msghdr are plain data, for which
+    // zero is a valid value.
+    let mut address: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let mut control: ControlBuffer = [0; CONTROL_WORDS];
+    let mut iovec = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+    let mut header: libc::msghdr = unsafe { mem::zeroed() };
+    header.msg_name = &mut address as *mut libc::sockaddr_storage as *mut libc::c_void;
+    header.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as _;
+    header.msg_iov = &mut iovec;
+    header.msg_iovlen = 1;
+    header.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    header.msg_controllen = mem::size_of::<ControlBuffer>() as _;
+
+    // SAFETY: the header points at an iovec covering buf, an address
+    // and a control buffer, all of which live until after the call.
+    let size = unsafe { libc::recvmsg(socket.as_raw_fd(), &mut header, 0) };
+    if size < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let source = match socket_addr(&address) {
+        Some(source) => source,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "datagram came from an address that isn't IP",
+            ))
+        }
+    };
+    // SAFETY: the kernel filled in the control messages it described.
+    let info = unsafe { ControlInfo::parse(&header) };
+    let size = (size as usize).min(buf.len());
+    return Ok(Datagram {
+        data: &buf[..size],
+        source,
+        received_at: info.received_at,
+        kernel_drops: info.kernel_drops,
+    });
+}
+
+/// Room for an SCM_TIMESTAMPNS and an SO_RXQ_OVFL control message, as
+/// u64s so that it is aligned for cmsghdr.
+#[cfg(target_os = "linux")]
+const CONTROL_WORDS: usize = 8;
+
+#[cfg(target_os = "linux")]
+type ControlBuffer = [u64; CONTROL_WORDS];
+
+/// What the kernel told us about a datagram in control messages.
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone, Copy, Default)]
+struct ControlInfo {
+    received_at: Option<SystemTime>,
+    kernel_drops: Option<u32>,
+}
+
+#[cfg(target_os = "linux")]
+impl ControlInfo {
+    /// Reads the control messages we know from header.
+    ///
+    /// # Safety
+    ///
+    /// header's control buffer must hold the control messages from a
+    /// successful recvmsg or recvmmsg.
+    unsafe fn parse(header: &libc::msghdr) -> ControlInfo {
+        let mut info = ControlInfo::default();
+        let mut message = libc::CMSG_FIRSTHDR(header);
+        while !message.is_null() {
+            let data = libc::CMSG_DATA(message);
+            match ((*message).cmsg_level, (*message).cmsg_type) {
+                (libc::SOL_SOCKET, libc::SCM_TIMESTAMPNS) => {
+                    let time = std::ptr::read_unaligned(data as *const libc::timespec);
+                    let since_epoch = Duration::new(time.tv_sec as u64, time.tv_nsec as u32);
+                    info.received_at = UNIX_EPOCH.checked_add(since_epoch);
+                }
+                (libc::SOL_SOCKET, libc::SO_RXQ_OVFL) => {
+                    info.kernel_drops = Some(std::ptr::read_unaligned(data as *const u32));
+                }
+                _ => {}
+            }
+            message = libc::CMSG_NXTHDR(header, message);
+        }
+        return info;
+    }
+}
+
+/// Receives many datagrams per syscall with recvmmsg, into buffers that
+/// are reused from one batch to the next. Datagrams larger than
+/// WUP_VID_PACKET_BUFFER_SIZE are cut short, as with recv_from into a
+/// buffer of that size.
+#[cfg(target_os = "linux")]
+pub struct BatchReceiver {
+    buffers: Vec<u8>,
+    lengths: Vec<usize>,
+    sources: Vec<Option<SocketAddr>>,
+    controls: Vec<ControlInfo>,
+    received: usize,
+    // What recvmmsg reads into and fills in, one of each per datagram.
+    // headers point into the others, and are pointed at them again
+    // before every call.
+    addresses: Vec<libc::sockaddr_storage>,
+    control_buffers: Vec<ControlBuffer>,
+    iovecs: Vec<libc::iovec>,
+    headers: Vec<libc::mmsghdr>,
+}
+
+// SAFETY: the raw pointers in iovecs and headers only point into the
+// receiver's own Vecs, and are only followed during recv, which
+// re-points them first.
+#[cfg(target_os = "linux")]
+unsafe impl Send for BatchReceiver {}
+
+#[cfg(target_os = "linux")]
+impl BatchReceiver {
+    pub fn new(batch_size: usize) -> BatchReceiver {
+        use std::mem;
+
+        let batch_size = batch_size.max(1);
+        return BatchReceiver {
+            buffers: vec![0; batch_size * crate::WUP_VID_PACKET_BUFFER_SIZE],
+            lengths: vec![0; batch_size],
+            sources: vec![None; batch_size],
+            controls: vec![ControlInfo::default(); batch_size],
+            received: 0,
+            // SAFETY: these are plain data, for which zero is a valid
+            // value.
+            addresses: vec![unsafe { mem::zeroed() }; batch_size],
+            control_buffers: vec![[0; CONTROL_WORDS]; batch_size],
+            iovecs: vec![unsafe { mem::zeroed() }; batch_size],
+            headers: vec![unsafe { mem::zeroed() }; batch_size],
+        };
+    }
+
+    pub fn batch_size(&self) -> usize {
+        return self.lengths.len();
+    }
+
+    /// Waits for at least one datagram, then takes as many as are
+    /// already waiting, up to the batch size. Returns how many arrived.
+    /// A read timeout on socket applies to the wait for the first one.
+    pub fn recv(&mut self, socket: &UdpSocket) -> io::Result<usize> {
+        use std::mem;
+
+        const SIZE: usize = crate::WUP_VID_PACKET_BUFFER_SIZE;
+        let batch_size = self.batch_size();
+        let slots = self
+            .headers
+            .iter_mut()
+            .zip(self.iovecs.iter_mut())
+            .zip(self.addresses.iter_mut())
+            .zip(self.control_buffers.iter_mut())
+            .zip(self.buffers.chunks_exact_mut(SIZE));
+        for ((((header, iovec), address), control), buffer) in slots {
+            iovec.iov_base = buffer.as_mut_ptr() as *mut libc::c_void;
+            iovec.iov_len = SIZE;
+            // recvmmsg shortens the lengths to what it wrote, so they
+            // are reset along with the pointers.
+            let header = &mut header.msg_hdr;
+            header.msg_name = address as *mut libc::sockaddr_storage as *mut libc::c_void;
+            header.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as _;
+            header.msg_iov = iovec as *mut libc::iovec;
+            header.msg_iovlen = 1;
+            header.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+            header.msg_controllen = mem::size_of::<ControlBuffer>() as _;
+            header.msg_flags = 0;
+        }
+
+        // SAFETY: every header points at an iovec, an address and a
+        // control buffer of self that live until after the call, and
+        // each iovec covers its own SIZE bytes of self.buffers.
+        let received = unsafe {
+            libc::recvmmsg(
+                socket.as_raw_fd(),
+                self.headers.as_mut_ptr(),
+                batch_size as _,
+                libc::MSG_WAITFORONE as _,
+                std::ptr::null_mut(),
+            )
+        };
+        if received < 0 {
+            self.received = 0;
+            return Err(io::Error::last_os_error());
+        }
+        self.received = received as usize;
+        for (index, header) in self.headers.iter().take(self.received).enumerate() {
+            self.lengths[index] = (header.msg_len as usize).min(SIZE);
+            self.sources[index] = socket_addr(&self.addresses[index]);
+            // SAFETY: recvmmsg filled in this header's control buffer.
+            self.controls[index] = unsafe { ControlInfo::parse(&header.msg_hdr) };
+        }
+        return Ok(self.received);
+    }
+
+    /// The datagrams from the last recv. Any from an address that
+    /// isn't IP, which a UDP socket never reports, are left out.
+    pub fn packets(&self) -> impl Iterator<Item = Datagram<'_>> {
+        return self
+            .buffers
+            .chunks_exact(crate::WUP_VID_PACKET_BUFFER_SIZE)
+            .zip(self.lengths.iter())
+            .zip(self.sources.iter().zip(self.controls.iter()))
+            .take(self.received)
+            .filter_map(|((buffer, length), (source, info))| {
+                return source.map(|source| Datagram {
+                    data: &buffer[..*length],
+                    source,
+                    received_at: info.received_at,
+                    kernel_drops: info.kernel_drops,
+                });
+            });
+    }
 }
 
-pub fn get_aud_socket(dest_ip: &str) -> Result<UdpSocket, std::io::Error> {
-    return get_socket(dest_ip, PORT_WUP_AUD);
+#[cfg(target_os = "linux")]
+fn socket_addr(address: &libc::sockaddr_storage) -> Option<SocketAddr> {
+    match address.ss_family as i32 {
+        libc::AF_INET => {
+            // SAFETY: the family says this is a sockaddr_in.
+            let address =
+                unsafe { &*(address as *const libc::sockaddr_storage as *const libc::sockaddr_in) };
+            let ip = Ipv4Addr::from(u32::from_be(address.sin_addr.s_addr));
+            return Some(SocketAddr::new(
+                IpAddr::V4(ip),
+                u16::from_be(address.sin_port),
+            ));
+        }
+        libc::AF_INET6 => {
+            // SAFETY: the family says this is a sockaddr_in6.
+            let address = unsafe {
+                &*(address as *const libc::sockaddr_storage as *const libc::sockaddr_in6)
+            };
+            let ip = Ipv6Addr::from(address.sin6_addr.s6_addr);
+            return Some(SocketAddr::new(
+                IpAddr::V6(ip),
+                u16::from_be(address.sin6_port),
+            ));
+        }
+        _ => return None,
+    }
 }
@@ -0,0 +1,162 @@
+// Copyright 2024 Dalton Durst and the drc-sim-rust contributors
+// SPDX-License-Identifier: MPL-2.0
+
+//! Converts positions on the decoded video into the raw values the
+//! GamePad's touch panel reports.
+//!
+//! The panel is a resistive overlay with its own 12-bit coordinates.
+//! It doesn't quite reach 0 or 4095 at the edges of the screen, and
+//! its Y axis counts up from the bottom. The default calibration is
+//! the one libdrc uses.
+
+use crate::{
+    hid::{GamePadInputReport, TouchPoint, TOUCH_MAX, TOUCH_PRESSURE_MAX, TOUCH_SAMPLES},
+    WUP_VIDEO_HEIGHT, WUP_VIDEO_WIDTH,
+};
+
+/// Raw panel values at the edges of the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TouchCalibration {
+    /// Raw X at the left edge.
+    pub left: u16,
+    /// Raw X at the right edge.
+    pub right: u16,
+    /// Raw Y at the top edge.
+    pub top: u16,
+    /// Raw Y at the bottom edge.
+    pub bottom: u16,
+}
+
+impl Default for TouchCalibration {
+    fn default() -> TouchCalibration {
+        return TouchCalibration {
+            left: 100,
+            right: 3900,
+            top: 3900,
+            bottom: 200,
+        };
+    }
+}
+
+/// A touch on the video frame, in pixels from the top left corner.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PixelTouch {
+    pub x: f32,
+    pub y: f32,
+    /// How hard the panel is pressed, from 0.0 to 1.0.
+    pub pressure: f32,
+}
+
+impl PixelTouch {
+    pub fn new(x: f32, y: f32, pressure: f32) -> PixelTouch {
+        return PixelTouch { x, y, pressure };
+    }
+}
+
+/// Maps pixels on a frame of a given size onto the touch panel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TouchScreen {
+    pub width: u16,
+    pub height: u16,
+    pub calibration: TouchCalibration,
+}
+
+impl Default for TouchScreen {
+    fn default() -> TouchScreen {
+        return TouchScreen::new(WUP_VIDEO_WIDTH as u16, WUP_VIDEO_HEIGHT as u16);
+    }
+}
+
+impl TouchScreen {
+    pub fn new(width: u16, height: u16) -> TouchScreen {
+        return TouchScreen {
+            width,
+            height,
+            calibration: TouchCalibration::default(),
+        };
+    }
+
+    /// Converts a pixel position to raw panel values. Returns None if
+    /// the position is off the frame.
+    pub fn to_raw(&self, x: f32, y: f32) -> Option<TouchPoint> {
+        let x = pixel_fraction(x, self.width)?;
+        let y = pixel_fraction(y, self.height)?;
+        let calibration = &self.calibration;
+        return Some(TouchPoint {
+            x: interpolate(calibration.left, calibration.right, x),
+            y: interpolate(calibration.top, calibration.bottom, y),
+        });
+    }
+
+    /// Converts a position in a window showing the frame scaled to fit,
+    /// with black bars to keep its aspect ratio, to a pixel position on
+    /// the frame. Returns None for positions on the bars.
+    pub fn window_to_frame(
+        &self,
+        x: f32,
+        y: f32,
+        window_width: f32,
+        window_height: f32,
+    ) -> Option<(f32, f32)> {
+        let scale = (window_width / self.width as f32).min(window_height / self.height as f32);
+        if scale.is_nan() || scale <= 0.0 {
+            return None;
+        }
+        let offset_x = (window_width - self.width as f32 * scale) / 2.0;
+        let offset_y = (window_height - self.height as f32 * scale) / 2.0;
+        let frame_x = (x - offset_x) / scale;
+        let frame_y = (y - offset_y) / scale;
+        pixel_fraction(frame_x, self.width)?;
+        pixel_fraction(frame_y, self.height)?;
+        return Some((frame_x, frame_y));
+    }
+
+    /// Puts samples into report's touch samples. The report holds
+    /// TOUCH_SAMPLES positions, so samples are spread across them in
+    /// order, and its one pressure value is the mean of the samples'.
+    /// Samples off the frame are dropped, and if none are left the
+    /// panel is released.
+    pub fn touch(&self, report: &mut GamePadInputReport, samples: &[PixelTouch]) {
+        let on_screen: Vec<(TouchPoint, f32)> = samples
+            .iter()
+            .filter_map(|sample| {
+                let point = self.to_raw(sample.x, sample.y)?;
+                return Some((point, sample.pressure.clamp(0.0, 1.0)));
+            })
+            .collect();
+        if on_screen.is_empty() {
+            report.set_touch(None);
+            report.touch_pressure = 0;
+            return;
+        }
+
+        for (i, slot) in report.touch.iter_mut().enumerate() {
+            let index = i * on_screen.len() / TOUCH_SAMPLES;
+            *slot = Some(on_screen[index].0);
+        }
+        let pressure: f32 =
+            on_screen.iter().map(|(_, pressure)| pressure).sum::<f32>() / on_screen.len() as f32;
+        report.touch_pressure = (pressure * TOUCH_PRESSURE_MAX as f32).round() as u16;
+    }
+
+    pub fn release(&self, report: &mut GamePadInputReport) {
+        self.touch(report, &[]);
+    }
+}
+
+/// Returns where position falls across size pixels, from 0.0 at the
+/// first pixel to 1.0 at the last.
+fn pixel_fraction(position: f32, size: u16) -> Option<f32> {
+    if !(position >= 0.0 && position < size as f32) {
+        return None;
+    }
+    if size <= 1 {
+        return Some(0.0);
+    }
+    return Some((position / (size - 1) as f32).min(1.0));
+}
+
+fn interpolate(start: u16, end: u16, fraction: f32) -> u16 {
+    let value = start as f32 + (end as f32 - start as f32) * fraction;
+    return (value.round() as u16).min(TOUCH_MAX);
+}
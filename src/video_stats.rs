@@ -0,0 +1,321 @@
+// Copyright 2024 Dalton Durst and the drc-sim-rust contributors
+// SPDX-License-Identifier: MPL-2.0
+
+//! Statistics about the frames coming out of a FrameAssembler.
+//!
+//! All timing is based on WUPVideoPacket.timestamp, which counts
+//! microseconds on the WUP's clock, so these numbers describe the
+//! stream as the WUP sent it rather than as we received it.
+
+use core::fmt;
+use std::{cmp::Ordering, collections::VecDeque};
+
+use crate::{incoming_packet_parser::u32_paws_compare, packet_organizer::AssembledFrame};
+
+/// How far back, in WUP microseconds, the rolling bitrate looks.
+pub const DEFAULT_ROLLING_WINDOW_US: u32 = 1_000_000;
+
+/// What we know about a single frame once it has been recorded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameStats {
+    pub timestamp: u32,
+    pub bytes: usize,
+    pub packets: usize,
+    pub is_idr: bool,
+    /// Microseconds since the previous frame, if there was one.
+    pub interval_us: Option<u32>,
+    /// This frame's size divided by interval_us, in bits per second.
+    pub instantaneous_bitrate: Option<f64>,
+    /// Bits per second over the rolling window ending at this frame.
+    pub rolling_bitrate: Option<f64>,
+}
+
+/// A snapshot of everything VideoStats has seen so far.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VideoStatsSummary {
+    pub frames: u64,
+    pub idr_frames: u64,
+    pub total_bytes: u64,
+    pub total_packets: u64,
+    pub min_frame_bytes: usize,
+    pub max_frame_bytes: usize,
+    pub mean_frame_bytes: f64,
+    pub mean_packets_per_frame: f64,
+    /// Mean number of frames from one IDR to the next.
+    pub mean_idr_interval_frames: Option<f64>,
+    /// Mean time from one IDR to the next in microseconds.
+    pub mean_idr_interval_us: Option<f64>,
+    pub mean_interval_us: Option<f64>,
+    pub min_interval_us: Option<u32>,
+    pub max_interval_us: Option<u32>,
+    /// Smoothed variation between consecutive frame intervals in
+    /// microseconds, computed like the RFC 3550 interarrival jitter.
+    pub jitter_us: f64,
+    /// Bits per second over the whole recording.
+    pub mean_bitrate: Option<f64>,
+    /// Bits per second over the most recent rolling window.
+    pub rolling_bitrate: Option<f64>,
+}
+
+impl fmt::Display for VideoStatsSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} frames ({} IDR), {} bytes in {} packets",
+            self.frames, self.idr_frames, self.total_bytes, self.total_packets
+        )?;
+        writeln!(
+            f,
+            "frame size: min {} max {} mean {:.0} bytes, {:.1} packets",
+            self.min_frame_bytes,
+            self.max_frame_bytes,
+            self.mean_frame_bytes,
+            self.mean_packets_per_frame
+        )?;
+        writeln!(
+            f,
+            "IDR interval: {} frames, {} us",
+            format_option(self.mean_idr_interval_frames, 1),
+            format_option(self.mean_idr_interval_us, 0)
+        )?;
+        writeln!(
+            f,
+            "frame interval: mean {} min {} max {} us, jitter {:.1} us",
+            format_option(self.mean_interval_us, 1),
+            format_option(self.min_interval_us.map(f64::from), 0),
+            format_option(self.max_interval_us.map(f64::from), 0),
+            self.jitter_us
+        )?;
+        write!(
+            f,
+            "bitrate: mean {} kbit/s, rolling {} kbit/s",
+            format_option(self.mean_bitrate.map(|b| b / 1000.0), 1),
+            format_option(self.rolling_bitrate.map(|b| b / 1000.0), 1)
+        )
+    }
+}
+
+fn format_option(value: Option<f64>, precision: usize) -> String {
+    return match value {
+        None => "n/a".to_string(),
+        Some(value) => format!("{:.*}", precision, value),
+    };
+}
+
+/// Collects statistics about assembled frames. Frames must be recorded
+/// in the order the FrameAssembler completes them.
+pub struct VideoStats {
+    rolling_window_us: u32,
+    /// (timestamp, bytes) of the frames within the rolling window.
+    window: VecDeque<(u32, usize)>,
+    window_bytes: usize,
+
+    frames: u64,
+    idr_frames: u64,
+    total_bytes: u64,
+    total_packets: u64,
+    min_frame_bytes: Option<usize>,
+    max_frame_bytes: usize,
+
+    first_frame_bytes: usize,
+    last_timestamp: Option<u32>,
+    /// Sum of every interval between frames, so that wrapping
+    /// timestamps don't throw off the mean.
+    elapsed_us: u64,
+    /// How many intervals went into elapsed_us. Late frames don't have
+    /// one.
+    intervals: u64,
+    min_interval_us: Option<u32>,
+    max_interval_us: Option<u32>,
+    last_interval_us: Option<u32>,
+    jitter_us: f64,
+
+    last_idr: Option<(u64, u32)>,
+    idr_intervals: u64,
+    idr_interval_frames_total: u64,
+    idr_interval_us_total: u64,
+}
+
+impl Default for VideoStats {
+    fn default() -> VideoStats {
+        return VideoStats::new(DEFAULT_ROLLING_WINDOW_US);
+    }
+}
+
+impl VideoStats {
+    pub fn new(rolling_window_us: u32) -> VideoStats {
+        return VideoStats {
+            rolling_window_us,
+            window: VecDeque::new(),
+            window_bytes: 0,
+            frames: 0,
+            idr_frames: 0,
+            total_bytes: 0,
+            total_packets: 0,
+            min_frame_bytes: None,
+            max_frame_bytes: 0,
+            first_frame_bytes: 0,
+            last_timestamp: None,
+            elapsed_us: 0,
+            intervals: 0,
+            min_interval_us: None,
+            max_interval_us: None,
+            last_interval_us: None,
+            jitter_us: 0.0,
+            last_idr: None,
+            idr_intervals: 0,
+            idr_interval_frames_total: 0,
+            idr_interval_us_total: 0,
+        };
+    }
+
+    pub fn record_frame(&mut self, frame: &AssembledFrame) -> FrameStats {
+        return self.record(
+            frame.timestamp,
+            frame.payload_len(),
+            frame.packets.len(),
+            frame.is_idr(),
+        );
+    }
+
+    /// Records a frame given its properties rather than the frame
+    /// itself.
+    pub fn record(
+        &mut self,
+        timestamp: u32,
+        bytes: usize,
+        packets: usize,
+        is_idr: bool,
+    ) -> FrameStats {
+        let frame_index = self.frames;
+        self.frames += 1;
+        self.total_bytes += bytes as u64;
+        self.total_packets += packets as u64;
+        self.min_frame_bytes = Some(self.min_frame_bytes.map_or(bytes, |min| min.min(bytes)));
+        self.max_frame_bytes = self.max_frame_bytes.max(bytes);
+
+        // A frame can complete after a newer one if its packets were
+        // delayed. It doesn't have a meaningful interval.
+        let out_of_order = self
+            .last_timestamp
+            .is_some_and(|last| u32_paws_compare(timestamp, last) != Some(Ordering::Greater));
+        let interval_us = self
+            .last_timestamp
+            .filter(|_| !out_of_order)
+            .map(|last| timestamp.wrapping_sub(last));
+        if let Some(interval) = interval_us {
+            self.elapsed_us += interval as u64;
+            self.intervals += 1;
+            self.min_interval_us = Some(self.min_interval_us.map_or(interval, |m| m.min(interval)));
+            self.max_interval_us = Some(self.max_interval_us.map_or(interval, |m| m.max(interval)));
+            if let Some(last_interval) = self.last_interval_us {
+                let difference = (interval as f64 - last_interval as f64).abs();
+                self.jitter_us += (difference - self.jitter_us) / 16.0;
+            }
+            self.last_interval_us = Some(interval);
+        }
+        if frame_index == 0 {
+            self.first_frame_bytes = bytes;
+        }
+        if !out_of_order {
+            self.last_timestamp = Some(timestamp);
+        }
+
+        if is_idr {
+            self.idr_frames += 1;
+            if let Some((last_index, last_timestamp)) = self.last_idr {
+                self.idr_intervals += 1;
+                self.idr_interval_frames_total += frame_index - last_index;
+                self.idr_interval_us_total += timestamp.wrapping_sub(last_timestamp) as u64;
+            }
+            self.last_idr = Some((frame_index, timestamp));
+        }
+
+        // A late frame would sit behind newer ones in the window, and
+        // trimming against its timestamp would empty the window.
+        if !out_of_order {
+            self.window.push_back((timestamp, bytes));
+            self.window_bytes += bytes;
+            while let Some((oldest, oldest_bytes)) = self.window.front().copied() {
+                if timestamp.wrapping_sub(oldest) <= self.rolling_window_us {
+                    break;
+                }
+                self.window.pop_front();
+                self.window_bytes -= oldest_bytes;
+            }
+        }
+
+        let instantaneous_bitrate = interval_us
+            .filter(|interval| *interval > 0)
+            .map(|interval| bits_per_second(bytes as u64, interval as u64));
+
+        return FrameStats {
+            timestamp,
+            bytes,
+            packets,
+            is_idr,
+            interval_us,
+            instantaneous_bitrate,
+            rolling_bitrate: self.rolling_bitrate(),
+        };
+    }
+
+    /// Bits per second over the rolling window. The oldest frame in the
+    /// window only marks where the window starts, so its bytes are not
+    /// counted.
+    pub fn rolling_bitrate(&self) -> Option<f64> {
+        let (oldest, oldest_bytes) = *self.window.front()?;
+        let (newest, _) = *self.window.back()?;
+        let span = newest.wrapping_sub(oldest);
+        if span == 0 {
+            return None;
+        }
+        return Some(bits_per_second(
+            (self.window_bytes - oldest_bytes) as u64,
+            span as u64,
+        ));
+    }
+
+    pub fn summary(&self) -> VideoStatsSummary {
+        let frames = self.frames as f64;
+        let mut summary = VideoStatsSummary {
+            frames: self.frames,
+            idr_frames: self.idr_frames,
+            total_bytes: self.total_bytes,
+            total_packets: self.total_packets,
+            min_frame_bytes: self.min_frame_bytes.unwrap_or(0),
+            max_frame_bytes: self.max_frame_bytes,
+            min_interval_us: self.min_interval_us,
+            max_interval_us: self.max_interval_us,
+            jitter_us: self.jitter_us,
+            rolling_bitrate: self.rolling_bitrate(),
+            ..Default::default()
+        };
+        if self.frames > 0 {
+            summary.mean_frame_bytes = self.total_bytes as f64 / frames;
+            summary.mean_packets_per_frame = self.total_packets as f64 / frames;
+        }
+        if self.idr_intervals > 0 {
+            let idr_intervals = self.idr_intervals as f64;
+            summary.mean_idr_interval_frames =
+                Some(self.idr_interval_frames_total as f64 / idr_intervals);
+            summary.mean_idr_interval_us = Some(self.idr_interval_us_total as f64 / idr_intervals);
+        }
+        if self.intervals > 0 {
+            summary.mean_interval_us = Some(self.elapsed_us as f64 / self.intervals as f64);
+        }
+        if self.elapsed_us > 0 {
+            // Like the rolling bitrate, the first frame only marks the
+            // start of the recording.
+            summary.mean_bitrate = Some(bits_per_second(
+                self.total_bytes - self.first_frame_bytes as u64,
+                self.elapsed_us,
+            ));
+        }
+        return summary;
+    }
+}
+
+fn bits_per_second(bytes: u64, microseconds: u64) -> f64 {
+    return (bytes * 8) as f64 * 1_000_000.0 / microseconds as f64;
+}
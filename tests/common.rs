@@ -15,5 +15,6 @@ pub fn data_ones() -> WUPVideoPacket {
         timestamp: 1,
         extended_header: 0u64.to_be_bytes(),
         payload: Vec::from([0x1]),
+        received_at: None,
     };
 }
@@ -0,0 +1,172 @@
+#![cfg(feature = "tokio")]
+
+use std::{net::UdpSocket, time::Duration};
+
+use arbitrary_int::{u1, u10, u3};
+use drc_sim_rust_lib::{
+    async_net::{run_commands, AsyncHidSender, AudioReceiver, VideoReceiver},
+    cmd::{BasicGamePadState, CommandHandler},
+    device_profile::DeviceProfile,
+    hid::{Buttons, GamePadInputReport},
+    hid_sender::SharedInput,
+    incoming_packet_parser::WUPAudioPacket,
+    sockets::Pad,
+};
+use tokio::time::timeout;
+
+mod video_packets;
+
+use video_packets::VideoPacketBuilder;
+
+fn local() -> UdpSocket {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    return socket;
+}
+
+#[tokio::test]
+async fn test_video_receiver() {
+    let console = local();
+    let mut receiver = VideoReceiver::from_std(local(), Pad::First).unwrap();
+    let address = receiver.local_addr().unwrap();
+
+    console.send_to(&[1, 2, 3], address).unwrap();
+    let mut packetizer = VideoPacketBuilder::new();
+    for timestamp in [1000, 2000] {
+        for packet in packetizer.packets(&[0x80; 3000], timestamp) {
+            console.send_to(&packet, address).unwrap();
+        }
+    }
+    let wait = Duration::from_secs(5);
+    let frame = timeout(wait, receiver.recv_frame()).await.unwrap().unwrap();
+    assert_eq!(frame.timestamp, 1000);
+    assert_eq!(frame.payload(), vec![0x80; 3000]);
+    let frame = timeout(wait, receiver.recv_frame()).await.unwrap().unwrap();
+    assert_eq!(frame.timestamp, 2000);
+    assert_eq!(receiver.bad_packets(), 1);
+    assert_eq!(receiver.assembler().completed_frames(), 2);
+}
+
+#[tokio::test]
+async fn test_audio_receiver_timestamps() {
+    let console = local();
+    let mut receiver = AudioReceiver::from_std(local()).unwrap();
+    #[cfg(target_os = "linux")]
+    receiver.enable_receive_timestamps().unwrap();
+
+    let packet = WUPAudioPacket {
+        format: u3::new(1),
+        channel: u1::new(0),
+        vibrate: false,
+        packet_type: u1::new(0),
+        seq_id: u10::new(7),
+        payload_size: 2,
+        timestamp: 99,
+        payload: vec![1, 2],
+        received_at: None,
+    };
+    console
+        .send_to(&packet.encode(), receiver.local_addr().unwrap())
+        .unwrap();
+    let received = timeout(Duration::from_secs(5), receiver.recv_packet())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(received.seq_id, u10::new(7));
+    assert_eq!(received.payload, vec![1, 2]);
+    #[cfg(target_os = "linux")]
+    assert!(received.received_at.is_some());
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_audio_receiver_drop_counter() {
+    use drc_sim_rust_lib::sockets::set_receive_buffer_size;
+
+    let console = local();
+    let socket = local();
+    set_receive_buffer_size(&socket, 1).unwrap();
+    let mut receiver = AudioReceiver::from_std(socket).unwrap();
+    receiver.enable_drop_counter().unwrap();
+    let address = receiver.local_addr().unwrap();
+
+    let mut packet = WUPAudioPacket {
+        format: u3::new(1),
+        channel: u1::new(0),
+        vibrate: false,
+        packet_type: u1::new(0),
+        seq_id: u10::new(0),
+        payload_size: 1024,
+        timestamp: 0,
+        payload: vec![0; 1024],
+        received_at: None,
+    };
+    // Far more than the smallest buffer holds.
+    for _ in 0..200 {
+        console.send_to(&packet.encode(), address).unwrap();
+    }
+    let wait = Duration::from_millis(100);
+    while let Ok(received) = timeout(wait, receiver.recv_packet()).await {
+        received.unwrap();
+    }
+
+    // Packets carry the count from when they were queued, so one sent
+    // after the flood has it.
+    packet.seq_id = u10::new(1);
+    console.send_to(&packet.encode(), address).unwrap();
+    let received = timeout(Duration::from_secs(5), receiver.recv_packet())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(received.seq_id, u10::new(1));
+    assert!(receiver.kernel_drops() > 0 && receiver.kernel_drops() < 200);
+}
+
+#[tokio::test]
+async fn test_async_hid_sender() {
+    let console = local();
+    let mut sender = AsyncHidSender::new(local(), console.local_addr().unwrap()).unwrap();
+    let mut report = GamePadInputReport::new();
+    report.buttons.set(Buttons::A, true);
+    report.sequence = 500;
+    sender.send(report.clone()).await.unwrap();
+    sender.send(report).await.unwrap();
+
+    let mut buf = [0u8; 256];
+    for sequence in [0u16, 1] {
+        let size = console.recv(&mut buf).unwrap();
+        assert!(size > 4);
+        assert_eq!(buf[0..2], sequence.to_le_bytes());
+        assert_eq!(buf[2..4], Buttons::A.0.to_be_bytes());
+    }
+
+    let input = SharedInput::new();
+    let run = sender.run(input, Duration::from_millis(5));
+    assert!(timeout(Duration::from_millis(50), run).await.is_err());
+    let size = console.recv(&mut buf).unwrap();
+    assert!(size > 4);
+    assert_eq!(buf[0..2], 2u16.to_le_bytes());
+}
+
+#[tokio::test]
+async fn test_run_commands() {
+    let console = local();
+    let socket = local();
+    let address = socket.local_addr().unwrap();
+    let mut handler = CommandHandler::new(BasicGamePadState::new(DeviceProfile::default()));
+
+    // Set the time, as in test_session.
+    let request = [0, 0, 2, 0, 8, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    console.send_to(&request, address).unwrap();
+    let run = run_commands(&mut handler, socket, console.local_addr().unwrap());
+    assert!(timeout(Duration::from_millis(200), run).await.is_err());
+
+    let mut buf = [0u8; 256];
+    let size = console.recv(&mut buf).unwrap();
+    assert_eq!(buf[..size], [1, 0, 2, 0, 0, 0, 1, 0]);
+    let size = console.recv(&mut buf).unwrap();
+    assert_eq!(buf[..size], [2, 0, 2, 0, 0, 0, 1, 0]);
+    assert_eq!(handler.stats().requests, 1);
+}
@@ -0,0 +1,159 @@
+use std::{net::UdpSocket, thread, time::Duration};
+
+use drc_sim_rust_lib::cmd::{
+    BasicGamePadState, CmdErrorKind, CmdHeader, CmdPacket, Command, CommandHandler, PacketType,
+    DEFAULT_UVC_UIC_RESPONSE,
+};
+
+fn packet(packet_type: u16, cmd_id: u16, seq_id: u16, payload: &[u8]) -> Vec<u8> {
+    let mut data = Vec::new();
+    for word in [packet_type, cmd_id, payload.len() as u16, seq_id] {
+        data.extend(word.to_le_bytes());
+    }
+    data.extend(payload);
+    return data;
+}
+
+fn generic_payload(id_primary: u8, id_secondary: u8, flags: u8) -> Vec<u8> {
+    return vec![
+        0x7e,
+        0x01,
+        0x02,
+        0x03,
+        0x04,
+        flags,
+        id_primary,
+        id_secondary,
+        0,
+        0,
+        0,
+        0,
+    ];
+}
+
+#[test]
+fn test_parse_packets() {
+    let time = packet(0, 2, 9, &[0x34, 0x12, 0, 0, 0x78, 0x56, 0x34, 0x12]);
+    let parsed = CmdPacket::parse(&time).unwrap();
+    assert_eq!(
+        parsed.header,
+        CmdHeader {
+            packet_type: PacketType::Request,
+            cmd_id: 2,
+            payload_size: 8,
+            seq_id: 9
+        }
+    );
+    assert_eq!(
+        parsed.command,
+        Command::SetTime {
+            jdn_base: 0x1234,
+            seconds: 0x12345678
+        }
+    );
+    assert_eq!(CmdHeader::parse(&time).unwrap().encode(), time[..8]);
+
+    let generic = CmdPacket::parse(&packet(0, 0, 1, &generic_payload(5, 6, 0x40))).unwrap();
+    match generic.command {
+        Command::Generic { header, payload } => {
+            assert_eq!((header.id_primary, header.id_secondary), (5, 6));
+            assert!(payload.is_empty());
+        }
+        other => panic!("parsed as {:?}", other),
+    }
+
+    let err = CmdPacket::parse(&[0; 4]).unwrap_err();
+    assert_eq!(err.kind, CmdErrorKind::TooShort);
+    let err = CmdPacket::parse(&packet(7, 0, 0, &[])).unwrap_err();
+    assert_eq!(err.kind, CmdErrorKind::BadPacketType);
+    let mut short = packet(0, 1, 0, &[0; 48]);
+    short.pop();
+    let err = CmdPacket::parse(&short).unwrap_err();
+    assert_eq!(err.kind, CmdErrorKind::SizeMismatch);
+}
+
+#[test]
+fn test_ack_and_respond() {
+    let mut handler = CommandHandler::new(BasicGamePadState::default());
+
+    let replies = handler.handle(&packet(0, 2, 3, &[1, 0, 0, 0, 2, 0, 0, 0]));
+    assert_eq!(replies, vec![packet(1, 2, 3, &[]), packet(2, 2, 3, &[])]);
+    assert_eq!(handler.state().time, Some((1, 2)));
+
+    let replies = handler.handle(&packet(0, 1, 4, &[0; 48]));
+    assert_eq!(replies[1], packet(2, 1, 4, &DEFAULT_UVC_UIC_RESPONSE));
+
+    // Responses from the console are only acked.
+    let replies = handler.handle(&packet(2, 1, 4, &[]));
+    assert_eq!(replies, vec![packet(3, 1, 4, &[])]);
+}
+
+#[test]
+fn test_generic_response() {
+    let mut state = BasicGamePadState::default();
+    let mut handler = CommandHandler::new(state.clone());
+    // Nobody has answered generic command 1/10 yet.
+    let replies = handler.handle(&packet(0, 0, 1, &generic_payload(1, 10, 0x40)));
+    assert_eq!(replies, vec![packet(1, 0, 1, &[])]);
+    assert_eq!(handler.stats().unanswered, 1);
+    // Without a UIC EEPROM dump, the read is answered from the
+    // profile's identity.
+    let replies = handler.handle(&packet(0, 0, 2, &generic_payload(5, 6, 0x40)));
+    let mut expected = vec![0x7e, 0x01, 0x02, 0x03, 0x04, 0x09, 5, 6, 0, 0, 0x03, 0x00];
+    expected.extend(state.profile.identity_eeprom());
+    assert_eq!(replies[1], packet(2, 0, 2, &expected));
+
+    state.profile.set_uic_eeprom(vec![0xAB, 0xCD]);
+    let mut handler = CommandHandler::new(state);
+    let replies = handler.handle(&packet(0, 0, 1, &generic_payload(5, 6, 0x40)));
+    let mut expected = vec![0x7e, 0x01, 0x02, 0x03, 0x04, 0x09, 5, 6, 0, 0, 0, 2];
+    expected.extend([0xAB, 0xCD]);
+    assert_eq!(replies[1], packet(2, 0, 1, &expected));
+}
+
+#[test]
+fn test_oversized_reply() {
+    let mut state = BasicGamePadState::default();
+    state.profile.set_uic_eeprom(vec![0; 70000]);
+    let mut handler = CommandHandler::new(state);
+    let replies = handler.handle(&packet(0, 0, 1, &generic_payload(5, 6, 0x40)));
+    // Only the ack goes out.
+    assert_eq!(replies, vec![packet(1, 0, 1, &[])]);
+    assert_eq!(handler.stats().unanswered, 1);
+    assert_eq!(handler.stats().responses_sent, 0);
+}
+
+#[test]
+fn test_retransmitted_request() {
+    let mut handler = CommandHandler::new(BasicGamePadState::default());
+    let request = packet(0, 1, 7, &[0; 48]);
+    let first = handler.handle(&request);
+    let second = handler.handle(&request);
+    assert_eq!(first, second);
+    assert_eq!(handler.stats().requests, 2);
+    assert_eq!(handler.stats().retransmissions, 1);
+    assert_eq!(handler.stats().responses_sent, 1);
+}
+
+#[test]
+fn test_run() {
+    let console = UdpSocket::bind("127.0.0.1:0").unwrap();
+    console
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let pad = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let pad_addr = pad.local_addr().unwrap();
+    let console_addr = console.local_addr().unwrap();
+    thread::spawn(move || {
+        CommandHandler::new(BasicGamePadState::default()).run(&pad, console_addr)
+    });
+
+    console
+        .send_to(&packet(0, 2, 1, &[0; 8]), pad_addr)
+        .unwrap();
+    let mut buf = [0u8; 64];
+    let size = console.recv(&mut buf).unwrap();
+    assert_eq!(&buf[..size], packet(1, 2, 1, &[]).as_slice());
+    let size = console.recv(&mut buf).unwrap();
+    assert_eq!(&buf[..size], packet(2, 2, 1, &[]).as_slice());
+}
@@ -0,0 +1,61 @@
+use std::{fs, path::PathBuf};
+
+use drc_sim_rust_lib::{
+    config::{Config, ConfigErrorKind},
+    packet_queue::OverflowPolicy,
+    sockets::BindAddress,
+};
+
+#[test]
+fn test_parse_config() {
+    let config = Config::parse(
+        "# Where the GamePad lives\n\
+         bind wlan1\n\
+         console 192.168.1.10  # the Wii U\n\
+         profile pad.profile\n\
+         receive_buffer 4194304\n\
+         video_overflow block\n",
+    )
+    .unwrap();
+    assert_eq!(
+        config.bind,
+        Some(BindAddress::Interface(String::from("wlan1")))
+    );
+    assert_eq!(config.console_ip, Some("192.168.1.10".parse().unwrap()));
+    assert_eq!(config.profile, Some(PathBuf::from("pad.profile")));
+    assert_eq!(config.receive_buffer_size, Some(4194304));
+    assert_eq!(config.video_overflow, Some(OverflowPolicy::Block));
+
+    assert_eq!(Config::parse("").unwrap(), Config::default());
+    let err = Config::parse("console wiiu").unwrap_err();
+    assert_eq!(err.kind, ConfigErrorKind::Syntax);
+    let err = Config::parse("receive_buffer lots").unwrap_err();
+    assert_eq!(err.kind, ConfigErrorKind::Syntax);
+    let err = Config::parse("video_overflow sometimes").unwrap_err();
+    assert_eq!(err.kind, ConfigErrorKind::Syntax);
+    let err = Config::parse("\nbind").unwrap_err();
+    assert_eq!(err.kind, ConfigErrorKind::Syntax);
+    assert!(err.text.contains("line 2"));
+}
+
+#[test]
+fn test_load_config() {
+    let dir = std::env::temp_dir().join(format!("drc-sim-config-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("drc-sim.conf"),
+        "bind 192.168.1.11\nprofile pad.profile\n",
+    )
+    .unwrap();
+    let config = Config::load(dir.join("drc-sim.conf")).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(
+        config.bind,
+        Some(BindAddress::Ip("192.168.1.11".parse().unwrap()))
+    );
+    // The profile is found next to the config file.
+    assert_eq!(config.profile, Some(dir.join("pad.profile")));
+
+    let err = Config::load("/nonexistent/drc-sim.conf").unwrap_err();
+    assert_eq!(err.kind, ConfigErrorKind::Io);
+}
@@ -0,0 +1,22 @@
+use std::{process::Command, sync::mpsc};
+
+use drc_sim_rust_lib::decode::ProcessDecoder;
+
+// cat stands in for a real decoder: whatever goes in comes back out as
+// "RGB".
+#[cfg(unix)]
+#[test]
+fn test_process_decoder_frames() {
+    let (sender, frames) = mpsc::channel();
+    let mut decoder = ProcessDecoder::spawn(Command::new("cat"), 2, 1, move |frame| {
+        sender.send(frame).unwrap();
+    })
+    .unwrap();
+    decoder.decode(&[1, 2, 3, 4]).unwrap();
+    decoder.decode(&[5, 6, 7, 8, 9, 10, 11, 12]).unwrap();
+    decoder.finish().unwrap();
+
+    let frames: Vec<Vec<u8>> = frames.iter().map(|frame| frame.rgb).collect();
+    assert_eq!(frames, [vec![1, 2, 3, 4, 5, 6], vec![7, 8, 9, 10, 11, 12]]);
+    assert!(decoder.decode(&[0]).is_err());
+}
@@ -0,0 +1,116 @@
+use std::fs;
+
+use drc_sim_rust_lib::{
+    cmd::{BasicGamePadState, CommandHandler, GenericReply, DEFAULT_UVC_UIC_RESPONSE},
+    device_profile::{DeviceProfile, ProfileErrorKind, Region, IDENTITY_EEPROM_SIZE},
+};
+
+#[test]
+fn test_default_profile() {
+    let profile = DeviceProfile::default();
+    assert_eq!(profile.region, Region::Usa);
+    assert_eq!(profile.uvc_uic_response, DEFAULT_UVC_UIC_RESPONSE);
+    // Without a dump, the EEPROM read is answered from the identity.
+    let eeprom = profile.uic_eeprom();
+    assert_eq!(eeprom.len(), IDENTITY_EEPROM_SIZE);
+    assert_eq!(eeprom, profile.identity_eeprom());
+    assert_eq!(
+        profile.generic_reply(5, 6),
+        Some(GenericReply {
+            error_code: 0,
+            data: eeprom
+        })
+    );
+    assert_eq!(profile.generic_reply(1, 10), None);
+}
+
+#[test]
+fn test_identity_eeprom() {
+    let profile = DeviceProfile::parse(
+        "serial JW123\n\
+         region europe\n\
+         firmware 0x0001002a\n\
+         uic_firmware 10\n",
+    )
+    .unwrap();
+    assert_eq!(profile.serial, "JW123");
+    assert_eq!(profile.region, Region::Europe);
+    assert_eq!(profile.firmware_version, 0x0001002a);
+    assert_eq!(profile.uic_firmware_version, 0x10);
+    let eeprom = profile.identity_eeprom();
+    assert_eq!(eeprom[0..4], [0x00, 0x01, 0x00, 0x2a]);
+    assert_eq!(eeprom[4..8], [0x00, 0x00, 0x00, 0x10]);
+    assert_eq!(eeprom[8], 4);
+    assert_eq!(eeprom[0x10..0x16], *b"JW123\0");
+}
+
+#[test]
+fn test_parse_profile() {
+    let profile = DeviceProfile::parse(
+        "# A made up GamePad\n\
+         name Test Pad  # with a comment\n\
+         uic_eeprom 0102 03\n\
+         uvc_uic ff ee\n\
+         generic 1 10 0a0B\n",
+    )
+    .unwrap();
+    assert_eq!(profile.name, "Test Pad");
+    assert_eq!(profile.uic_eeprom(), [1u8, 2, 3]);
+    assert_eq!(profile.uvc_uic_response, vec![0xff, 0xee]);
+    assert_eq!(
+        profile.generic_reply(1, 10),
+        Some(GenericReply {
+            error_code: 0,
+            data: vec![0x0a, 0x0b]
+        })
+    );
+}
+
+#[test]
+fn test_bad_profiles() {
+    let err = DeviceProfile::parse("uic_eeprom 012").unwrap_err();
+    assert_eq!(err.kind, ProfileErrorKind::BadHex);
+    let err = DeviceProfile::parse("uvc_uic zz").unwrap_err();
+    assert_eq!(err.kind, ProfileErrorKind::BadHex);
+    let err = DeviceProfile::parse("\ngeneric 300 1 00").unwrap_err();
+    assert_eq!(err.kind, ProfileErrorKind::Syntax);
+    assert!(err.text.contains("line 2"));
+    let err = DeviceProfile::parse("colour white").unwrap_err();
+    assert_eq!(err.kind, ProfileErrorKind::Syntax);
+    let err = DeviceProfile::parse("region mars").unwrap_err();
+    assert_eq!(err.kind, ProfileErrorKind::BadValue);
+    let err = DeviceProfile::parse("firmware 1.2").unwrap_err();
+    assert_eq!(err.kind, ProfileErrorKind::BadHex);
+    let err = DeviceProfile::load("/nonexistent/profile.txt").unwrap_err();
+    assert_eq!(err.kind, ProfileErrorKind::Io);
+}
+
+#[test]
+fn test_load_eeprom_file() {
+    let dir = std::env::temp_dir().join(format!("drc-sim-profile-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("uic.bin"), [0xAB, 0xCD]).unwrap();
+    fs::write(dir.join("profile.txt"), "uic_eeprom_file uic.bin\n").unwrap();
+    let profile = DeviceProfile::load(dir.join("profile.txt")).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(profile.uic_eeprom(), [0xABu8, 0xCD]);
+}
+
+#[test]
+fn test_profile_answers_commands() {
+    let profile = DeviceProfile::parse("uvc_uic 0102\ngeneric 1 10 aa").unwrap();
+    let mut handler = CommandHandler::new(BasicGamePadState::new(profile));
+
+    let mut request = vec![0, 0, 1, 0, 48, 0, 5, 0];
+    request.extend([0; 48]);
+    let replies = handler.handle(&request);
+    assert_eq!(replies[1], vec![2, 0, 1, 0, 2, 0, 5, 0, 1, 2]);
+
+    let mut request = vec![0, 0, 0, 0, 12, 0, 6, 0];
+    request.extend([0x7e, 0, 0, 0, 0, 0x40, 1, 10, 0, 0, 0, 0]);
+    let replies = handler.handle(&request);
+    assert_eq!(
+        replies[1],
+        vec![2, 0, 0, 0, 13, 0, 6, 0, 0x7e, 0, 0, 0, 0, 0x09, 1, 10, 0, 0, 0, 1, 0xaa]
+    );
+}
@@ -0,0 +1,93 @@
+use std::{net::UdpSocket, thread, time::Duration};
+
+use assert_matches::assert_matches;
+use drc_sim_rust_lib::feedback::{parse_hid_output, FeedbackEvent, FeedbackMonitor};
+use drc_sim_rust_lib::incoming_packet_parser::process_audio_packet;
+
+fn audio_packet(vibrate: bool, timestamp: u32) -> Vec<u8> {
+    let mut packet = vec![(vibrate as u8) << 3, 0x01, 0x00, 0x04];
+    packet.extend(timestamp.to_le_bytes());
+    packet.extend([0u8; 4]);
+    return packet;
+}
+
+#[test]
+fn test_rumble_changes() {
+    let (mut monitor, events) = FeedbackMonitor::with_channel();
+    for (vibrate, timestamp) in [(false, 1), (true, 2), (true, 3), (false, 4)] {
+        let packet = process_audio_packet(&audio_packet(vibrate, timestamp)).unwrap();
+        monitor.on_audio_packet(&packet);
+    }
+    assert!(!monitor.is_rumbling());
+    let events: Vec<FeedbackEvent> = events.try_iter().collect();
+    assert_eq!(
+        events,
+        vec![
+            FeedbackEvent::Rumble {
+                on: true,
+                timestamp: 2
+            },
+            FeedbackEvent::Rumble {
+                on: false,
+                timestamp: 4
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_hid_output() {
+    let pad = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let console = UdpSocket::bind("127.0.0.1:0").unwrap();
+    console
+        .send_to(&[0x07, 0x02, 0xff], pad.local_addr().unwrap())
+        .unwrap();
+
+    let (mut monitor, events) = FeedbackMonitor::with_channel();
+    thread::spawn(move || monitor.run_hid(&pad));
+    let event = events.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(
+        event,
+        FeedbackEvent::HidOutput {
+            data: vec![0x07, 0x02, 0xff]
+        }
+    );
+}
+
+#[test]
+fn test_parse_rumble_pattern() {
+    let on = true;
+    let off = false;
+    assert_eq!(
+        parse_hid_output(&[0x01, 10, 0b1100_1010, 0b0100_0000]),
+        Some(FeedbackEvent::RumblePattern {
+            pattern: vec![on, on, off, off, on, off, on, off, off, on]
+        })
+    );
+    let mut longest = vec![0x01, 120];
+    longest.extend([0xff; 15]);
+    assert_matches!(
+        parse_hid_output(&longest),
+        Some(FeedbackEvent::RumblePattern { pattern }) if pattern.len() == 120
+    );
+    // Too long, too short for its length, empty, and an unknown type
+    assert_eq!(parse_hid_output(&[0x01, 121, 0xff]), None);
+    assert_eq!(parse_hid_output(&[0x01, 9, 0xff]), None);
+    assert_eq!(parse_hid_output(&[0x01, 0]), None);
+    assert_eq!(parse_hid_output(&[0x05, 5]), None);
+
+    let (mut monitor, events) = FeedbackMonitor::with_channel();
+    monitor.on_hid_packet(&[0x01, 2, 0x80]);
+    monitor.on_hid_packet(&[0x05, 5]);
+    let events: Vec<FeedbackEvent> = events.try_iter().collect();
+    assert_eq!(
+        events,
+        vec![
+            FeedbackEvent::RumblePattern {
+                pattern: vec![on, off]
+            },
+            FeedbackEvent::HidOutput { data: vec![5, 5] },
+        ]
+    );
+    assert_eq!(monitor.hid_outputs(), 2);
+}
@@ -0,0 +1,222 @@
+use drc_sim_rust_lib::{
+    h264::{
+        add_emulation_prevention, remove_emulation_prevention, split_annex_b, H264Encapsulator,
+        PPS, SPS,
+    },
+    h264_validator::{BitstreamProblemKind, BitstreamValidator, SliceType},
+};
+use proptest::prelude::*;
+
+const SLICE_DATA: [u8; 6] = [0x88, 0x84, 0x00, 0x00, 0x01, 0x80];
+
+#[test]
+fn test_emulation_prevention() {
+    let mut nal = Vec::new();
+    add_emulation_prevention(&mut nal, &[0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x04]);
+    assert_eq!(
+        nal,
+        [0x00, 0x00, 0x03, 0x01, 0x00, 0x00, 0x03, 0x00, 0x00, 0x04]
+    );
+}
+
+proptest! {
+    #[test]
+    fn test_emulation_prevention_round_trip(data in proptest::collection::vec(0u8..5, 0..64)) {
+        let mut nal = Vec::new();
+        add_emulation_prevention(&mut nal, &data);
+        prop_assert_eq!(remove_emulation_prevention(&nal), data);
+    }
+}
+
+#[test]
+fn test_encapsulate_idr() {
+    let mut encapsulator = H264Encapsulator::new();
+    let access_unit = encapsulator.encapsulate_payload(true, &SLICE_DATA);
+    let nals = split_annex_b(&access_unit);
+    assert_eq!(nals.len(), 3);
+    assert_eq!(nals[0], SPS);
+    assert_eq!(nals[1], PPS);
+    assert_eq!(nals[2][..5], [0x25, 0xb8, 0x04, 0xff, 0x88]);
+}
+
+#[test]
+fn test_validate_good_stream() {
+    let mut encapsulator = H264Encapsulator::new();
+    let mut validator = BitstreamValidator::new();
+    for i in 0..300 {
+        let is_idr = i % 100 == 0;
+        let access_unit = encapsulator.encapsulate_payload(is_idr, &SLICE_DATA);
+        let validation = validator.validate(i, is_idr, &access_unit);
+        assert!(validation.is_ok(), "frame {}: {:?}", i, validation.problems);
+        assert_eq!(validation.slices.len(), 1);
+        let slice = &validation.slices[0];
+        assert_eq!(
+            slice.slice_type,
+            if is_idr { SliceType::I } else { SliceType::P }
+        );
+        assert_eq!(slice.frame_num, i % 100 % 256);
+        assert_eq!(slice.data_len, SLICE_DATA.len());
+        assert_eq!(validation.claimed_macroblocks, 54 * 30);
+        assert_eq!(validation.expected_macroblocks, Some(54 * 30));
+    }
+    assert_eq!(validator.frames_checked(), 300);
+    assert_eq!(validator.bad_frames(), 0);
+}
+
+#[test]
+fn test_validate_frame_num_gap() {
+    let mut encapsulator = H264Encapsulator::new();
+    let mut validator = BitstreamValidator::new();
+    let access_unit = encapsulator.encapsulate_payload(true, &SLICE_DATA);
+    assert!(validator.validate(0, true, &access_unit).is_ok());
+    // A frame that never makes it to the validator
+    encapsulator.encapsulate_payload(false, &SLICE_DATA);
+    let access_unit = encapsulator.encapsulate_payload(false, &SLICE_DATA);
+    let validation = validator.validate(2, false, &access_unit);
+    assert_eq!(validation.problems.len(), 1);
+    assert_eq!(
+        validation.problems[0].kind,
+        BitstreamProblemKind::FrameNumGap
+    );
+}
+
+#[test]
+fn test_validate_missing_parameter_sets() {
+    let mut encapsulator = H264Encapsulator::new();
+    let mut validator = BitstreamValidator::new();
+    let access_unit = encapsulator.encapsulate_payload(false, &SLICE_DATA);
+    let validation = validator.validate(0, false, &access_unit);
+    let kinds: Vec<BitstreamProblemKind> = validation.problems.iter().map(|p| p.kind).collect();
+    assert_eq!(
+        kinds,
+        [
+            BitstreamProblemKind::MissingParameterSets,
+            BitstreamProblemKind::NoSlices
+        ]
+    );
+}
+
+#[test]
+fn test_validate_idr_mismatch_and_bad_escape() {
+    let mut encapsulator = H264Encapsulator::new();
+    let mut validator = BitstreamValidator::new();
+    let mut access_unit = encapsulator.encapsulate_payload(true, &SLICE_DATA);
+    // Un-escape the slice data as if we forgot to add emulation
+    // prevention.
+    let len = access_unit.len();
+    access_unit.remove(len - 3);
+    let validation = validator.validate(0, false, &access_unit);
+    let kinds: Vec<BitstreamProblemKind> = validation.problems.iter().map(|p| p.kind).collect();
+    assert!(kinds.contains(&BitstreamProblemKind::IdrMismatch));
+    assert!(!validation.is_ok());
+}
+
+/// Writes the fields of a baseline-profile SPS, for building broken
+/// ones.
+struct SpsWriter {
+    bits: Vec<bool>,
+}
+
+impl SpsWriter {
+    fn u(&mut self, count: u32, value: u64) -> &mut SpsWriter {
+        for i in (0..count).rev() {
+            self.bits.push(value >> i & 1 == 1);
+        }
+        return self;
+    }
+
+    fn ue(&mut self, value: u32) -> &mut SpsWriter {
+        let value = value as u64 + 1;
+        let len = 64 - value.leading_zeros();
+        return self.u(len - 1, 0).u(len, value);
+    }
+
+    fn access_unit(&mut self) -> Vec<u8> {
+        // rbsp_stop_one_bit and alignment
+        self.bits.push(true);
+        while !self.bits.len().is_multiple_of(8) {
+            self.bits.push(false);
+        }
+        let rbsp: Vec<u8> = self
+            .bits
+            .chunks(8)
+            .map(|byte| byte.iter().fold(0, |acc, &bit| acc << 1 | bit as u8))
+            .collect();
+        let mut access_unit = vec![0, 0, 0, 1, 0x67];
+        add_emulation_prevention(&mut access_unit, &rbsp);
+        return access_unit;
+    }
+}
+
+/// A baseline SPS up to pic_order_cnt_type, with log2_max_frame_num as
+/// given.
+fn sps_start(log2_max_frame_num_minus4: u32) -> SpsWriter {
+    let mut sps = SpsWriter { bits: Vec::new() };
+    // profile_idc, constraint flags, level_idc, seq_parameter_set_id
+    sps.u(8, 66).u(8, 0).u(8, 40).ue(0);
+    sps.ue(log2_max_frame_num_minus4);
+    return sps;
+}
+
+/// Everything after pic_order_cnt_type 2, with the given size and crop.
+fn sps_finish(sps: &mut SpsWriter, width_mbs_minus1: u32, crop_right: u32) -> Vec<u8> {
+    // max_num_ref_frames, gaps_in_frame_num_value_allowed_flag
+    sps.ue(1).u(1, 0);
+    // pic_height_in_map_units_minus1, frame_mbs_only_flag,
+    // direct_8x8_inference_flag, frame_cropping_flag
+    sps.ue(width_mbs_minus1).ue(29).u(1, 1).u(1, 1).u(1, 1);
+    sps.ue(0).ue(crop_right).ue(0).ue(0);
+    // vui_parameters_present_flag
+    sps.u(1, 0);
+    return sps.access_unit();
+}
+
+#[test]
+fn test_validate_hostile_sps() {
+    let max_ue = u32::MAX - 1;
+    let cases = [
+        // A well-formed SPS, to show the writer works.
+        (sps_finish(sps_start(4).ue(2), 53, 5), None),
+        (
+            sps_finish(sps_start(max_ue).ue(2), 53, 5),
+            Some("outside 4..=16"),
+        ),
+        (
+            sps_finish(sps_start(13).ue(2), 53, 5),
+            Some("outside 4..=16"),
+        ),
+        (
+            sps_finish(sps_start(40).ue(2), 53, 5),
+            Some("outside 4..=16"),
+        ),
+        (
+            sps_finish(sps_start(4).ue(0).ue(max_ue), 53, 5),
+            Some("outside 4..=16"),
+        ),
+        (
+            sps_finish(sps_start(4).ue(2), max_ue, 5),
+            Some("larger than any level"),
+        ),
+        (
+            sps_finish(sps_start(4).ue(2), 53, max_ue),
+            Some("cropped to nothing"),
+        ),
+    ];
+    for (access_unit, error) in cases {
+        let mut validator = BitstreamValidator::new();
+        let validation = validator.validate(0, true, &access_unit);
+        let problem = validation
+            .problems
+            .iter()
+            .find(|p| p.kind == BitstreamProblemKind::BadParameterSet);
+        match error {
+            None => assert!(problem.is_none(), "{:?}", validation.problems),
+            Some(error) => assert!(
+                problem.is_some_and(|p| p.text.contains(error)),
+                "want {:?}, got {:?}",
+                error,
+                validation.problems
+            ),
+        }
+    }
+}
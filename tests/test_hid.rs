@@ -0,0 +1,104 @@
+use drc_sim_rust_lib::hid::{
+    Buttons, ExtraButtons, GamePadInputReport, PowerStatus, Stick, TouchPoint, Vector3,
+    INPUT_REPORT_SIZE, TOUCH_SAMPLES,
+};
+use proptest::prelude::*;
+
+#[test]
+fn test_default_report() {
+    let report = GamePadInputReport::new().encode();
+    assert_eq!(report.len(), INPUT_REPORT_SIZE);
+    assert_eq!(&report[0..6], &[0, 0, 0, 0, 0x01, 0xFF]);
+    assert_eq!(
+        &report[6..14],
+        &[0x00, 0x08, 0x00, 0x08, 0x00, 0x08, 0x00, 0x08]
+    );
+    assert_eq!(report[14], 0x80);
+    assert!(report[15..127].iter().all(|b| *b == 0));
+    assert_eq!(report[127], 0xE0);
+}
+
+#[test]
+fn test_encode_fields() {
+    let mut input = GamePadInputReport::new();
+    input.sequence = 0x1234;
+    input.buttons = Buttons::A | Buttons::HOME;
+    input.extra_buttons = ExtraButtons::L3 | ExtraButtons::TV;
+    input.power_status.set(PowerStatus::HEADPHONES, true);
+    input.battery = 0x42;
+    input.left_stick = Stick { x: 0x123, y: 0xABC };
+    input.right_stick = Stick::from_normalized(1.0, -1.0);
+    input.volume = 0x10;
+    input.accelerometer = Vector3 {
+        x: -1,
+        y: 0x0102,
+        z: 0x7FFF,
+    };
+    input.gyroscope = Vector3 {
+        x: -2,
+        y: 0x010203,
+        z: 0x7FFFFF,
+    };
+    input.magnetometer = Vector3 {
+        x: 1,
+        y: -256,
+        z: 3,
+    };
+    input.timestamp = 0xDEADBEEF;
+    let report = input.encode();
+
+    assert_eq!(&report[0..2], &[0x34, 0x12]);
+    assert_eq!(&report[2..4], &[0x80, 0x02]);
+    assert_eq!(report[4], 0x21);
+    assert_eq!(report[5], 0x42);
+    assert_eq!(&report[6..10], &[0x23, 0x01, 0xBC, 0x0A]);
+    assert_eq!(&report[10..14], &[0x00, 0x0F, 0x00, 0x01]);
+    assert_eq!(report[14], 0x10);
+    assert_eq!(&report[15..21], &[0xFF, 0xFF, 0x02, 0x01, 0xFF, 0x7F]);
+    assert_eq!(
+        &report[21..30],
+        &[0xFE, 0xFF, 0xFF, 0x03, 0x02, 0x01, 0xFF, 0xFF, 0x7F]
+    );
+    assert_eq!(&report[30..36], &[0x01, 0x00, 0x00, 0xFF, 0x03, 0x00]);
+    assert_eq!(&report[76..80], &[0xEF, 0xBE, 0xAD, 0xDE]);
+    assert_eq!(report[80], 0xA0);
+    assert_eq!(report[127], 0xE0);
+}
+
+#[test]
+fn test_encode_touch() {
+    let mut input = GamePadInputReport::new();
+    input.set_touch(Some(TouchPoint { x: 0x123, y: 0x456 }));
+    input.touch_pressure = 0b101_100_011_010;
+    let report = input.encode();
+
+    assert_eq!(&report[36..40], &[0x23, 0x81 | 0x20, 0x56, 0x84 | 0x30]);
+    assert_eq!(&report[40..44], &[0x23, 0x81 | 0x40, 0x56, 0x84 | 0x50]);
+    for i in 2..TOUCH_SAMPLES {
+        let offset = 36 + i * 4;
+        assert_eq!(&report[offset..offset + 4], &[0x23, 0x81, 0x56, 0x84]);
+    }
+
+    input.set_touch(None);
+    let report = input.encode();
+    assert!(report[36..76].iter().all(|b| *b == 0));
+}
+
+proptest! {
+    #[test]
+    fn test_stick_from_normalized_in_range(x in proptest::num::f32::ANY, y in proptest::num::f32::ANY) {
+        let stick = Stick::from_normalized(x, y);
+        let min = Stick::CENTER - Stick::RANGE;
+        let max = Stick::CENTER + Stick::RANGE;
+        prop_assert!(stick.x >= min && stick.x <= max);
+        prop_assert!(stick.y >= min && stick.y <= max);
+    }
+
+    #[test]
+    fn test_buttons_round_trip(bits: u16) {
+        let mut input = GamePadInputReport::new();
+        input.buttons = Buttons(bits);
+        let report = input.encode();
+        prop_assert_eq!(u16::from_be_bytes([report[2], report[3]]), bits);
+    }
+}
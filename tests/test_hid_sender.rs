@@ -0,0 +1,71 @@
+use std::{net::UdpSocket, time::Duration};
+
+use drc_sim_rust_lib::hid::{Buttons, INPUT_REPORT_SIZE};
+use drc_sim_rust_lib::hid_sender::{HidSender, ReportHook, SharedInput};
+use drc_sim_rust_lib::motion::{ImuNoise, MotionSource};
+
+#[test]
+fn test_sender_sends_latest_input() {
+    let console = UdpSocket::bind("127.0.0.1:0").unwrap();
+    console
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let input = SharedInput::new();
+    let mut sender = HidSender::start(
+        socket,
+        console.local_addr().unwrap(),
+        input.clone(),
+        Duration::from_millis(2),
+    );
+
+    let mut buf = [0u8; 256];
+    let mut last_sequence = None;
+    let mut last_timestamp = 0;
+    let mut saw_a = false;
+    for i in 0..40 {
+        if i == 10 {
+            input.update(|report| report.buttons = Buttons::A);
+        }
+        let size = console.recv(&mut buf).unwrap();
+        assert_eq!(size, INPUT_REPORT_SIZE);
+        let sequence = u16::from_le_bytes([buf[0], buf[1]]);
+        let timestamp = u32::from_le_bytes([buf[76], buf[77], buf[78], buf[79]]);
+        if let Some(last) = last_sequence {
+            assert_eq!(sequence, last + 1);
+            assert!(timestamp > last_timestamp);
+        }
+        last_sequence = Some(sequence);
+        last_timestamp = timestamp;
+        saw_a |= buf[2] == 0x80;
+    }
+    assert!(saw_a);
+
+    sender.stop().unwrap();
+    let stats = sender.stats();
+    assert!(stats.reports_sent >= 40);
+}
+
+#[test]
+fn test_sender_runs_hook() {
+    let console = UdpSocket::bind("127.0.0.1:0").unwrap();
+    console
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let mut motion = MotionSource::at_rest();
+    motion.noise = ImuNoise::NONE;
+    let hook: ReportHook = Box::new(move |report, elapsed| motion.advance(report, elapsed));
+    let _sender = HidSender::start_with_hook(
+        socket,
+        console.local_addr().unwrap(),
+        SharedInput::new(),
+        Duration::from_millis(2),
+        Some(hook),
+    );
+
+    let mut buf = [0u8; 256];
+    console.recv(&mut buf).unwrap();
+    // 1g on z, as a little-endian i16
+    assert_eq!(&buf[19..21], &4096i16.to_le_bytes());
+}
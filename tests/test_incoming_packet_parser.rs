@@ -2,7 +2,7 @@ use std::{cmp::Ordering, panic::catch_unwind};
 
 use arbitrary_int::{u10, u11, u2, u4};
 use drc_sim_rust_lib::incoming_packet_parser::{
-    process_video_packet, u10_paws_compare, u32_paws_compare, WUPVideoPacket,
+    process_audio_packet, process_video_packet, u10_paws_compare, u32_paws_compare, WUPVideoPacket,
 };
 use proptest::prelude::*;
 
@@ -19,7 +19,7 @@ fn data_from_wupvideopacket(input: WUPVideoPacket) -> Result<Vec<u8>, &'static s
 
     let first_byte: u8 = (u8::from(input.magic) << 4)
         | (u8::from(input.packet_type) << 2)
-        | (u8::from(seq_id[0]) >> 6)
+        | (seq_id[0] >> 6)
         | seq_id[0];
     data.push(first_byte);
 
@@ -77,6 +77,7 @@ fn data_christmas_tree() -> WUPVideoPacket {
         timestamp: 0xFFFFFFFF,
         extended_header: 0xFFFFFFFFFFFFFFFFu64.to_be_bytes(),
         payload: Vec::from([0xFF]),
+        received_at: None,
     };
 }
 
@@ -121,7 +122,7 @@ fn fail_with_invalid_magic() {
 
 #[test]
 fn fail_with_invalid_type() {
-    let mut packet = CHRISTMAS_TREE_SLICE.clone();
+    let mut packet = CHRISTMAS_TREE_SLICE;
     packet[0] = 0xF8;
     assert_eq!(process_video_packet(&packet), None);
 }
@@ -265,3 +266,26 @@ fn do_u10_timestamp_compare_prop(s: u16, difference: u16) {
     assert_eq!(u10_paws_compare(s, t), expected);
     assert_eq!(u10_paws_compare(t, s), inverse_expected);
 }
+
+#[test]
+fn test_process_audio_packet() {
+    // format 1, channel 1, vibrate, packet type 0, seq_id 0x3ff,
+    // payload_size 2, timestamp 0x12345678
+    let data = [
+        0x3B, 0xFF, 0x00, 0x02, 0x78, 0x56, 0x34, 0x12, 0xAA, 0xBB, 0xCC,
+    ];
+    let packet = process_audio_packet(&data).unwrap();
+    assert_eq!(u8::from(packet.format), 1);
+    assert_eq!(u8::from(packet.channel), 1);
+    assert!(packet.vibrate);
+    assert_eq!(u8::from(packet.packet_type), 0);
+    assert_eq!(packet.seq_id, u10::new(0x3ff));
+    assert_eq!(packet.payload_size, 2);
+    assert_eq!(packet.timestamp, 0x12345678);
+    assert_eq!(packet.payload, vec![0xAA, 0xBB]);
+
+    assert!(process_audio_packet(&data[..7]).is_none());
+    let mut too_short = data;
+    too_short[3] = 0x10;
+    assert!(process_audio_packet(&too_short).is_none());
+}
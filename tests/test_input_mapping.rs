@@ -0,0 +1,111 @@
+use drc_sim_rust_lib::hid::{Buttons, ExtraButtons, Stick};
+use drc_sim_rust_lib::hid_sender::SharedInput;
+use drc_sim_rust_lib::input_mapping::{
+    parse_recorded_events, InputMapper, InputMapping, MappingErrorKind, RawInputEvent, EV_KEY,
+};
+
+const RECORDING: &str = "
+# Press south and the left trigger
+EV_KEY BTN_SOUTH 1
+EV_ABS ABS_Z 1023
+EV_SYN SYN_REPORT 0
+# Push the left stick all the way up and right, press the d-pad left
+EV_ABS ABS_X 32767
+EV_ABS ABS_Y -32768
+EV_ABS ABS_HAT0X -1
+EV_KEY BTN_THUMBL 1
+EV_SYN SYN_REPORT 0
+";
+
+#[test]
+fn test_default_mapping_with_recording() {
+    let events = parse_recorded_events(RECORDING).unwrap();
+    assert_eq!(events.len(), 8);
+    assert_eq!(events[0], RawInputEvent::new(EV_KEY, 0x130, 1));
+
+    let mut mapper = InputMapper::default();
+    mapper.set_device_range(0x02, 0, 1023);
+    let mut reports = Vec::new();
+    for event in events {
+        if mapper.handle(event) {
+            reports.push(mapper.state().clone());
+        }
+    }
+    assert_eq!(reports.len(), 2);
+    assert_eq!(reports[0].buttons, Buttons::B | Buttons::ZL);
+    assert_eq!(reports[0].left_stick, Stick::default());
+    assert_eq!(reports[1].buttons, Buttons::B | Buttons::ZL | Buttons::LEFT);
+    assert_eq!(reports[1].extra_buttons, ExtraButtons::L3);
+    assert_eq!(
+        reports[1].left_stick,
+        Stick {
+            x: Stick::CENTER + Stick::RANGE,
+            y: Stick::CENTER + Stick::RANGE
+        }
+    );
+}
+
+#[test]
+fn test_run_updates_shared_input() {
+    let mapping = InputMapping::parse(
+        "key 304 A  # BTN_SOUTH by number\n\
+         axis ABS_RX right_x\n\
+         range ABS_RX 0 255\n",
+    )
+    .unwrap();
+    let mut mapper = InputMapper::new(mapping);
+    let input = SharedInput::new();
+    input.update(|report| report.battery = 7);
+    let events = parse_recorded_events(
+        "EV_KEY BTN_SOUTH 1\nEV_ABS ABS_RX 0\nEV_SYN SYN_REPORT 0\nEV_KEY BTN_SOUTH 0\n",
+    )
+    .unwrap();
+    mapper.run(events, &input);
+
+    let report = input.get();
+    // The release hasn't been followed by a SYN_REPORT yet.
+    assert_eq!(report.buttons, Buttons::A);
+    assert_eq!(report.right_stick.x, Stick::CENTER - Stick::RANGE);
+    assert_eq!(report.battery, 7);
+}
+
+#[test]
+fn test_mapping_errors() {
+    let err = InputMapping::parse("key BTN_SOUTH Q").unwrap_err();
+    assert_eq!(err.kind, MappingErrorKind::UnknownTarget);
+    let err = InputMapping::parse("\nkey BTN_NOPE A").unwrap_err();
+    assert_eq!(err.kind, MappingErrorKind::UnknownCode);
+    assert!(err.text.starts_with("line 2"));
+    let err = InputMapping::parse("axis ABS_X left_x sideways").unwrap_err();
+    assert_eq!(err.kind, MappingErrorKind::Syntax);
+    let err = InputMapping::parse("range ABS_X 10 0").unwrap_err();
+    assert_eq!(err.kind, MappingErrorKind::Syntax);
+    let err = InputMapping::load("/nonexistent/mapping").unwrap_err();
+    assert_eq!(err.kind, MappingErrorKind::Io);
+}
+
+#[test]
+fn test_button_with_two_sources() {
+    // The default mapping puts both BTN_TL2 and ABS_Z on ZL.
+    let events = parse_recorded_events(
+        "
+        EV_KEY BTN_TL2 1
+        EV_ABS ABS_Z 1023
+        EV_SYN SYN_REPORT 0
+        EV_KEY BTN_TL2 0
+        EV_SYN SYN_REPORT 0
+        EV_ABS ABS_Z 0
+        EV_SYN SYN_REPORT 0
+        ",
+    )
+    .unwrap();
+    let mut mapper = InputMapper::default();
+    mapper.set_device_range(0x02, 0, 1023);
+    let mut reports = Vec::new();
+    for event in events {
+        if mapper.handle(event) {
+            reports.push(mapper.state().buttons);
+        }
+    }
+    assert_eq!(reports, [Buttons::ZL, Buttons::ZL, Buttons::default()]);
+}
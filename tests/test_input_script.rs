@@ -0,0 +1,150 @@
+use drc_sim_rust_lib::hid::{Buttons, GamePadInputReport, Stick};
+use drc_sim_rust_lib::hid_sender::SharedInput;
+use drc_sim_rust_lib::input_mapping::ButtonTarget;
+use drc_sim_rust_lib::input_script::{
+    InputScript, ScriptAction, ScriptErrorKind, ScriptEvent, ScriptPlayer, ScriptRecorder,
+    StickSide, TiltDirection, DEFAULT_TILT_DEGREES,
+};
+use drc_sim_rust_lib::touch::TouchScreen;
+
+const SCRIPT: &str = "
+press A 100ms     # hold A
+wait 50ms
+touch 300 200 20ms
+stick left 0 1
+tilt left
+";
+
+#[test]
+fn test_parse_timeline() {
+    let script = InputScript::parse(SCRIPT).unwrap();
+    let a = ButtonTarget::Main(Buttons::A);
+    assert_eq!(
+        &script.events[..4],
+        &[
+            ScriptEvent {
+                at_us: 0,
+                action: ScriptAction::Press(a)
+            },
+            ScriptEvent {
+                at_us: 100_000,
+                action: ScriptAction::Release(a)
+            },
+            ScriptEvent {
+                at_us: 150_000,
+                action: ScriptAction::Touch { x: 300.0, y: 200.0 }
+            },
+            ScriptEvent {
+                at_us: 170_000,
+                action: ScriptAction::Untouch
+            },
+        ]
+    );
+    assert_eq!(
+        script.events[4].action,
+        ScriptAction::Stick {
+            side: StickSide::Left,
+            x: 0.0,
+            y: 1.0
+        }
+    );
+    assert_eq!(script.events.len(), 6);
+    assert_eq!(script.length_us, 170_000);
+}
+
+#[test]
+fn test_parse_errors() {
+    let err = InputScript::parse("press Q").unwrap_err();
+    assert_eq!(err.kind, ScriptErrorKind::UnknownButton);
+    let err = InputScript::parse("wait 100").unwrap_err();
+    assert_eq!(err.kind, ScriptErrorKind::BadNumber);
+    let err = InputScript::parse("\n\njump").unwrap_err();
+    assert_eq!(err.kind, ScriptErrorKind::Syntax);
+    assert!(err.text.starts_with("line 3"));
+}
+
+#[test]
+fn test_parse_tilt_duration() {
+    let script = InputScript::parse("tilt left 10 100ms\ntilt back 5ms\ntilt right 20").unwrap();
+    let tilt = |at_us, direction, degrees| ScriptEvent {
+        at_us,
+        action: ScriptAction::Tilt { direction, degrees },
+    };
+    assert_eq!(
+        script.events,
+        vec![
+            tilt(0, TiltDirection::Left, 10.0),
+            tilt(100_000, TiltDirection::Flat, 0.0),
+            tilt(100_000, TiltDirection::Back, DEFAULT_TILT_DEGREES),
+            tilt(105_000, TiltDirection::Flat, 0.0),
+            tilt(105_000, TiltDirection::Right, 20.0),
+        ]
+    );
+    assert_eq!(script.length_us, 105_000);
+    let err = InputScript::parse("tilt left 10 100").unwrap_err();
+    assert_eq!(err.kind, ScriptErrorKind::BadNumber);
+}
+
+#[test]
+fn test_player_follows_frame_timestamps() {
+    let script = InputScript::parse(SCRIPT).unwrap();
+    let mut player = ScriptPlayer::new(script);
+    let input = SharedInput::new();
+    // Start just before the timestamps wrap.
+    let start = u32::MAX - 20_000;
+
+    assert_eq!(player.on_frame(start, &input), 1);
+    assert_eq!(input.get().buttons, Buttons::A);
+    // An older frame arriving late doesn't move time.
+    assert_eq!(player.on_frame(start - 500_000, &input), 0);
+    assert_eq!(player.on_frame(start.wrapping_add(99_999), &input), 0);
+    assert_eq!(player.on_frame(start.wrapping_add(100_000), &input), 1);
+    assert_eq!(input.get().buttons, Buttons::NONE);
+
+    player.on_frame(start.wrapping_add(160_000), &input);
+    let report = input.get();
+    assert_eq!(report.touch[0], TouchScreen::default().to_raw(300.0, 200.0));
+    assert!(!player.is_finished());
+
+    player.on_frame(start.wrapping_add(170_000), &input);
+    let report = input.get();
+    assert!(!report.is_touched());
+    assert_eq!(report.left_stick.y, Stick::CENTER + Stick::RANGE);
+    // The right edge is now higher, so "up" has some +x in it.
+    assert!(report.accelerometer.x > 1000);
+    assert!(player.is_finished());
+}
+
+#[test]
+fn test_record_and_replay() {
+    let mut recorder = ScriptRecorder::new();
+    let mut report = GamePadInputReport::new();
+    recorder.on_frame(1000, &report);
+    report.buttons = Buttons::B | Buttons::UP;
+    recorder.on_frame(17_683, &report);
+    report.buttons = Buttons::UP;
+    report.left_stick = Stick::from_normalized(-0.5, 0.0);
+    report.set_touch(TouchScreen::default().to_raw(100.0, 50.0));
+    recorder.on_frame(34_366, &report);
+    report.set_touch(None);
+    recorder.on_frame(51_049, &report);
+    assert_eq!(
+        recorder.script(),
+        "wait 16683us\npress UP\npress B\nwait 16683us\nrelease B\nstick left -0.5 0\n\
+         touch 100 50\nwait 16683us\nuntouch\n"
+    );
+
+    let script = InputScript::parse(recorder.script()).unwrap();
+    let mut player = ScriptPlayer::new(script);
+    let input = SharedInput::new();
+    for timestamp in [5000, 21_683, 38_366] {
+        player.on_frame(timestamp, &input);
+    }
+    let replayed = input.get();
+    assert_eq!(replayed.buttons, Buttons::UP);
+    assert_eq!(replayed.left_stick, report.left_stick);
+    assert_eq!(
+        replayed.touch[0],
+        TouchScreen::default().to_raw(100.0, 50.0)
+    );
+}
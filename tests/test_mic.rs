@@ -0,0 +1,131 @@
+use std::{net::UdpSocket, time::Duration};
+
+use arbitrary_int::{u10, u3};
+use drc_sim_rust_lib::{
+    incoming_packet_parser::process_audio_packet,
+    mic::{resample, MicConfig, MicPacketizer, MicSender, WavAudio, WavErrorKind},
+};
+
+fn wav(channels: u16, sample_rate: u32, bits: u16, samples: &[i16]) -> Vec<u8> {
+    let mut data = Vec::new();
+    let body: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+    data.extend(b"RIFF");
+    data.extend((4 + 8 + 16 + 8 + body.len() as u32 + 8).to_le_bytes());
+    data.extend(b"WAVE");
+    // An odd-sized chunk we don't know, to check padding is skipped.
+    data.extend(b"LIST");
+    data.extend(3u32.to_le_bytes());
+    data.extend([1, 2, 3, 0]);
+    data.extend(b"fmt ");
+    data.extend(16u32.to_le_bytes());
+    data.extend(1u16.to_le_bytes());
+    data.extend(channels.to_le_bytes());
+    data.extend(sample_rate.to_le_bytes());
+    data.extend((sample_rate * channels as u32 * 2).to_le_bytes());
+    data.extend((channels * 2).to_le_bytes());
+    data.extend(bits.to_le_bytes());
+    data.extend(b"data");
+    data.extend((body.len() as u32).to_le_bytes());
+    data.extend(body);
+    return data;
+}
+
+#[test]
+fn test_parse_wav() {
+    let audio = WavAudio::parse(&wav(2, 32000, 16, &[100, 300, -2, -4])).unwrap();
+    assert_eq!(audio.sample_rate, 32000);
+    assert_eq!(audio.channels, 2);
+    assert_eq!(audio.samples, vec![100, 300, -2, -4]);
+    assert_eq!(audio.to_mono(32000), vec![200, -3]);
+    assert_eq!(audio.to_mono(16000), vec![200]);
+
+    let err = WavAudio::parse(&wav(1, 16000, 8, &[0])).unwrap_err();
+    assert_eq!(err.kind, WavErrorKind::Unsupported);
+    let err = WavAudio::parse(b"RIFF\0\0\0\0AVI ").unwrap_err();
+    assert_eq!(err.kind, WavErrorKind::NotWav);
+    let mut cut = wav(1, 16000, 16, &[1, 2, 3]);
+    cut.truncate(cut.len() - 1);
+    let err = WavAudio::parse(&cut).unwrap_err();
+    assert_eq!(err.kind, WavErrorKind::NotWav);
+    let err = WavAudio::load("/nonexistent.wav").unwrap_err();
+    assert_eq!(err.kind, WavErrorKind::Io);
+}
+
+#[test]
+fn test_resample() {
+    assert_eq!(resample(&[0, 100, 200, 300], 2, 1), vec![0, 200]);
+    assert_eq!(resample(&[0, 100], 1, 2), vec![0, 50, 100, 100]);
+    assert!(resample(&[], 1, 2).is_empty());
+}
+
+#[test]
+fn test_packetizer() {
+    let mut packetizer = MicPacketizer::new(MicConfig::default());
+    let first = process_audio_packet(&packetizer.packet(&[1, -1], 1234)).unwrap();
+    assert_eq!(first.format, u3::new(6));
+    assert_eq!(first.seq_id, u10::new(0));
+    assert_eq!(first.timestamp, 1234);
+    assert_eq!(first.payload, vec![1, 0, 0xff, 0xff]);
+    assert!(!first.vibrate);
+    let second = process_audio_packet(&packetizer.packet(&[], 0)).unwrap();
+    assert_eq!(second.seq_id, u10::new(1));
+    assert_eq!(second.payload_size, 0);
+}
+
+#[test]
+fn test_sender_streams_at_rate() {
+    let console = UdpSocket::bind("127.0.0.1:0").unwrap();
+    console
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let config = MicConfig {
+        samples_per_packet: 100,
+        sample_rate: 10000,
+        ..MicConfig::default()
+    };
+    assert_eq!(config.packet_duration(), Duration::from_millis(10));
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let mut sender =
+        MicSender::start(socket, console.local_addr().unwrap(), 0..250, config).unwrap();
+
+    let mut buf = [0u8; 1024];
+    let mut timestamps = Vec::new();
+    let mut sizes = Vec::new();
+    for _ in 0..3 {
+        let size = console.recv(&mut buf).unwrap();
+        let packet = process_audio_packet(&buf[..size]).unwrap();
+        timestamps.push(packet.timestamp);
+        sizes.push(packet.payload.len());
+    }
+    assert_eq!(sizes, vec![200, 200, 100]);
+    // The last packet is due two intervals after the first.
+    assert!(timestamps[2] - timestamps[0] >= 19_000);
+    sender.stop().unwrap();
+    assert!(sender.is_finished());
+    assert_eq!(sender.stats().samples_sent, 250);
+}
+
+#[test]
+fn test_sender_rejects_bad_config() {
+    let console = UdpSocket::bind("127.0.0.1:0").unwrap();
+    for config in [
+        MicConfig {
+            sample_rate: 0,
+            ..MicConfig::default()
+        },
+        MicConfig {
+            samples_per_packet: 0,
+            ..MicConfig::default()
+        },
+        MicConfig {
+            samples_per_packet: 40000,
+            ..MicConfig::default()
+        },
+    ] {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let err = MicSender::start(socket, console.local_addr().unwrap(), 0..250, config)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,101 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+};
+
+use drc_sim_rust_lib::{decode::DecodedFrame, mjpeg_server::MjpegServer};
+
+fn get(server: &MjpegServer, path: &str) -> BufReader<TcpStream> {
+    let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+    write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+    return BufReader::new(stream);
+}
+
+/// Reads the status line and headers, returning the status line and
+/// Content-Length if there was one.
+fn read_head(reader: &mut BufReader<TcpStream>) -> (String, Option<usize>) {
+    let mut status = String::new();
+    reader.read_line(&mut status).unwrap();
+    let mut content_length = None;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).unwrap();
+        if header == "\r\n" {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length: ") {
+            content_length = Some(value.trim().parse().unwrap());
+        }
+    }
+    return (status.trim().to_string(), content_length);
+}
+
+fn grey_frame() -> DecodedFrame {
+    return DecodedFrame {
+        width: 16,
+        height: 8,
+        rgb: vec![0x80; 16 * 8 * 3],
+    };
+}
+
+#[test]
+fn test_latest_frame() {
+    let server = MjpegServer::bind("127.0.0.1:0", 80).unwrap();
+
+    let mut response = get(&server, "/frame.jpg");
+    let (status, _) = read_head(&mut response);
+    assert_eq!(status, "HTTP/1.1 503 Service Unavailable");
+
+    server.publish(&grey_frame());
+    let mut response = get(&server, "/frame.jpg");
+    let (status, content_length) = read_head(&mut response);
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    let mut body = vec![0u8; content_length.unwrap()];
+    response.read_exact(&mut body).unwrap();
+    assert_eq!(body[..2], [0xFF, 0xD8]);
+    assert_eq!(body[body.len() - 2..], [0xFF, 0xD9]);
+}
+
+#[test]
+fn test_stream() {
+    let server = MjpegServer::bind("127.0.0.1:0", 80).unwrap();
+    server.publish_jpeg(b"first".to_vec());
+
+    let mut response = get(&server, "/stream.mjpg");
+    let (status, _) = read_head(&mut response);
+    assert_eq!(status, "HTTP/1.1 200 OK");
+
+    for expected in [&b"first"[..], &b"second"[..]] {
+        let mut boundary = String::new();
+        response.read_line(&mut boundary).unwrap();
+        assert_eq!(boundary, "--drcsimframe\r\n");
+        let (_, content_length) = read_head(&mut response);
+        let mut body = vec![0u8; content_length.unwrap() + 2];
+        response.read_exact(&mut body).unwrap();
+        assert_eq!(&body[..body.len() - 2], expected);
+        server.publish_jpeg(b"second".to_vec());
+    }
+}
+
+#[test]
+fn test_not_found() {
+    let server = MjpegServer::bind("127.0.0.1:0", 80).unwrap();
+    let mut response = get(&server, "/nothing");
+    let (status, _) = read_head(&mut response);
+    assert_eq!(status, "HTTP/1.1 404 Not Found");
+}
+
+#[test]
+fn test_drop_frees_port() {
+    let server = MjpegServer::bind("127.0.0.1:0", 80).unwrap();
+    let address = server.local_addr();
+    server.publish_jpeg(b"first".to_vec());
+    let mut response = get(&server, "/stream.mjpg");
+    read_head(&mut response);
+
+    drop(server);
+    // The streaming client is ended rather than left waiting.
+    let mut rest = Vec::new();
+    response.read_to_end(&mut rest).unwrap();
+    MjpegServer::bind(address, 80).unwrap();
+}
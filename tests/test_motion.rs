@@ -0,0 +1,96 @@
+use std::time::Duration;
+
+use drc_sim_rust_lib::hid::{GamePadInputReport, Vector3};
+use drc_sim_rust_lib::motion::{ImuNoise, MotionSource, Quaternion};
+
+fn noiseless() -> MotionSource {
+    let mut motion = MotionSource::at_rest();
+    motion.noise = ImuNoise::NONE;
+    return motion;
+}
+
+#[test]
+fn test_flat_at_rest() {
+    let sample = noiseless().sample();
+    assert_eq!(
+        sample.accelerometer,
+        Vector3 {
+            x: 0,
+            y: 0,
+            z: 4096
+        }
+    );
+    assert_eq!(sample.gyroscope, Vector3::default());
+    assert_eq!(
+        sample.magnetometer,
+        Vector3 {
+            x: 0,
+            y: 200,
+            z: -440
+        }
+    );
+}
+
+#[test]
+fn test_noise_is_small_and_repeatable() {
+    let mut first = MotionSource::with_seed(1234);
+    let mut second = MotionSource::with_seed(1234);
+    let mut sum_z = 0i64;
+    for _ in 0..1000 {
+        let sample = first.sample();
+        assert_eq!(sample, second.sample());
+        assert!(sample.accelerometer.x.abs() < 100);
+        assert!(sample.gyroscope.y.abs() < 1000);
+        sum_z += sample.accelerometer.z as i64;
+    }
+    assert!((sum_z / 1000 - 4096).abs() < 5);
+    assert_ne!(
+        MotionSource::with_seed(1).sample(),
+        MotionSource::with_seed(2).sample()
+    );
+}
+
+#[test]
+fn test_rotation() {
+    let mut motion = noiseless();
+    // Tip the top of the GamePad up at 90 degrees per second.
+    motion.angular_velocity = Vector3 {
+        x: 90.0,
+        y: 0.0,
+        z: 0.0,
+    };
+    for _ in 0..100 {
+        motion.step(Duration::from_millis(10));
+    }
+    let sample = motion.sample();
+    assert!(sample.accelerometer.x.abs() <= 1);
+    assert!((sample.accelerometer.y - 4096).abs() <= 1);
+    assert!(sample.accelerometer.z.abs() <= 1);
+    // Pitch is the last of the three gyro values in the report.
+    assert_eq!(
+        sample.gyroscope,
+        Vector3 {
+            x: 0,
+            y: 0,
+            z: 90_000
+        }
+    );
+}
+
+#[test]
+fn test_orientation_and_report() {
+    let mut motion = noiseless();
+    // Screen down.
+    motion.orientation = Quaternion::from_axis_angle(
+        Vector3 {
+            x: 0.0,
+            y: 1.0,
+            z: 0.0,
+        },
+        180.0,
+    );
+    let mut report = GamePadInputReport::new();
+    motion.advance(&mut report, Duration::from_millis(5));
+    assert_eq!(report.accelerometer.z, -4096);
+    assert_eq!(report.magnetometer.z, 440);
+}
@@ -0,0 +1,53 @@
+use std::{net::UdpSocket, thread, time::Duration};
+
+use drc_sim_rust_lib::msg::{MessageHandler, MessageStats};
+
+#[test]
+fn test_unknown_messages_are_counted() {
+    let mut handler = MessageHandler::default();
+    assert_eq!(handler.handle(&[1, 2, 3]), None);
+    assert_eq!(handler.handle(&[]), None);
+    assert_eq!(
+        *handler.stats(),
+        MessageStats {
+            received: 2,
+            unknown: 2,
+            responses_sent: 0
+        }
+    );
+}
+
+#[test]
+fn test_responder() {
+    let mut handler = MessageHandler::new(Some(Box::new(|message: &[u8]| {
+        return match message.first() {
+            Some(1) => Some(vec![0x81]),
+            _ => None,
+        };
+    })));
+    assert_eq!(handler.handle(&[1, 0]), Some(vec![0x81]));
+    assert_eq!(handler.handle(&[2, 0]), None);
+    assert_eq!(handler.stats().responses_sent, 1);
+    assert_eq!(handler.stats().unknown, 1);
+}
+
+#[test]
+fn test_run_replies_to_sender() {
+    let pad = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let pad_addr = pad.local_addr().unwrap();
+    thread::spawn(move || {
+        let mut handler = MessageHandler::new(Some(Box::new(|message: &[u8]| {
+            return Some(message.iter().rev().copied().collect());
+        })));
+        return handler.run(&pad);
+    });
+
+    let console = UdpSocket::bind("127.0.0.1:0").unwrap();
+    console
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    console.send_to(&[1, 2, 3], pad_addr).unwrap();
+    let mut buf = [0u8; 16];
+    let size = console.recv(&mut buf).unwrap();
+    assert_eq!(&buf[..size], &[3, 2, 1]);
+}
@@ -1,8 +1,10 @@
 use arbitrary_int::u10;
 use drc_sim_rust_lib::{
-    incoming_packet_parser::WUPVideoPacket, packet_organizer::FrameAccumulator,
+    incoming_packet_parser::WUPVideoPacket,
+    packet_organizer::{FrameAccumulator, FrameAssembler},
+    sockets::Pad,
+    STALE_FRAME_THRESHOLD,
 };
-use proptest::proptest;
 
 mod common;
 
@@ -103,3 +105,98 @@ fn test_three_dgram_wrap_accumulator() {
     assert_eq!(completed[0..], [&packet1, &packet2, &packet3]);
 }
 
+fn frame_packets(timestamp: u32, first_seq_id: u16, count: u16) -> Vec<WUPVideoPacket> {
+    let mut packets = Vec::new();
+    for i in 0..count {
+        let mut packet = common::data_ones();
+        packet.seq_id = u10::new((first_seq_id + i) % 1024);
+        packet.frame_begin = i == 0;
+        packet.frame_end = i == count - 1;
+        packet.timestamp = timestamp;
+        packet.payload = Vec::from([i as u8]);
+        packets.push(packet);
+    }
+    return packets;
+}
+
+#[test]
+fn test_assembler_out_of_order() {
+    let mut packets = frame_packets(127384127, 1022, 4);
+    packets.swap(0, 3);
+    packets.swap(1, 2);
+
+    let mut assembler = FrameAssembler::new();
+    for packet in packets.drain(..3) {
+        assert!(assembler.add_packet(packet).is_none());
+    }
+    let frame = assembler.add_packet(packets.remove(0));
+    assert!(frame.is_some());
+    let frame = frame.unwrap();
+    assert_eq!(frame.timestamp, 127384127);
+    assert_eq!(frame.payload(), [0, 1, 2, 3]);
+    assert!(!frame.is_idr());
+    assert_eq!(assembler.completed_frames(), 1);
+    assert_eq!(assembler.incomplete_frames(), 0);
+}
+
+#[test]
+fn test_assembler_drops_stale_frames() {
+    let mut assembler = FrameAssembler::new();
+    let mut incomplete = frame_packets(1000, 0, 2);
+    assert!(assembler.add_packet(incomplete.remove(0)).is_none());
+
+    let mut newer = frame_packets(1000 + STALE_FRAME_THRESHOLD + 1, 2, 2);
+    assert!(assembler.add_packet(newer.remove(0)).is_none());
+    let mut newest = frame_packets(1000 + STALE_FRAME_THRESHOLD * 2, 4, 1);
+    assert!(assembler.add_packet(newest.remove(0)).is_some());
+
+    assert_eq!(assembler.dropped_frames(), 1);
+    assert_eq!(assembler.completed_frames(), 1);
+    assert_eq!(assembler.incomplete_frames(), 1);
+}
+
+#[test]
+fn test_assembler_rejects_packets() {
+    let mut assembler = FrameAssembler::new();
+    let mut packets = frame_packets(5000, 0, 2);
+    assert!(assembler.add_packet(packets[0].clone()).is_none());
+    // A duplicate begin packet
+    assert!(assembler.add_packet(packets[0].clone()).is_none());
+    assert_eq!(assembler.rejected_packets(), 1);
+
+    // A packet without a timestamp doesn't start a frame or move the
+    // high water mark, so the frame at 5000 isn't dropped as stale.
+    let mut untimed = frame_packets(5000 + STALE_FRAME_THRESHOLD * 2, 8, 1).remove(0);
+    untimed.has_timestamp = false;
+    assert!(assembler.add_packet(untimed).is_none());
+    assert_eq!(assembler.rejected_packets(), 2);
+    assert_eq!(assembler.incomplete_frames(), 1);
+
+    assert!(assembler.add_packet(packets.remove(1)).is_some());
+    assert_eq!(assembler.dropped_frames(), 0);
+}
+
+#[test]
+fn test_assembled_frame_idr() {
+    let mut packets = frame_packets(5, 0, 1);
+    packets[0].extended_header[3] = 0x80;
+    let mut assembler = FrameAssembler::new();
+    let frame = assembler.add_packet(packets.remove(0)).unwrap();
+    assert!(frame.is_idr());
+}
+
+#[test]
+fn test_assemblers_per_pad() {
+    let mut first = FrameAssembler::for_pad(Pad::First);
+    let mut second = FrameAssembler::for_pad(Pad::Second);
+    // Both streams can use the same timestamps and seq_ids without
+    // interfering with each other.
+    let mut first_packets = frame_packets(1000, 0, 2);
+    let mut second_packets = frame_packets(1000, 0, 2);
+    assert!(first.add_packet(first_packets.remove(0)).is_none());
+    assert!(second.add_packet(second_packets.remove(0)).is_none());
+    let first_frame = first.add_packet(first_packets.remove(0)).unwrap();
+    let second_frame = second.add_packet(second_packets.remove(0)).unwrap();
+    assert_eq!(first_frame.pad, Pad::First);
+    assert_eq!(second_frame.pad, Pad::Second);
+}
@@ -0,0 +1,99 @@
+use std::{thread, time::Duration};
+
+use drc_sim_rust_lib::packet_queue::{OverflowPolicy, PacketQueue, QueueStats};
+
+const WAIT: Duration = Duration::from_millis(10);
+
+fn drain(queue: &PacketQueue<u32>) -> Vec<u32> {
+    let mut items = Vec::new();
+    while let Some(item) = queue.pop_timeout(WAIT) {
+        items.push(item);
+    }
+    return items;
+}
+
+#[test]
+fn test_drop_oldest() {
+    let queue = PacketQueue::new(3, OverflowPolicy::DropOldest);
+    for n in 0..5 {
+        assert!(queue.push(n));
+    }
+    assert_eq!(queue.len(), 3);
+    assert_eq!(drain(&queue), vec![2, 3, 4]);
+    assert_eq!(
+        queue.stats(),
+        QueueStats {
+            pushed: 5,
+            popped: 3,
+            dropped_oldest: 2,
+            max_len: 3,
+            ..QueueStats::default()
+        }
+    );
+}
+
+#[test]
+fn test_drop_newest() {
+    let queue = PacketQueue::new(3, OverflowPolicy::DropNewest);
+    let pushed: Vec<bool> = (0..5).map(|n| queue.push(n)).collect();
+    assert_eq!(pushed, vec![true, true, true, false, false]);
+    assert_eq!(drain(&queue), vec![0, 1, 2]);
+    let stats = queue.stats();
+    assert_eq!((stats.pushed, stats.dropped_newest), (3, 2));
+}
+
+#[test]
+fn test_block() {
+    let queue = PacketQueue::new(2, OverflowPolicy::Block);
+    let producer = queue.clone();
+    let thread = thread::spawn(move || {
+        for n in 0..10 {
+            assert!(producer.push(n));
+        }
+    });
+    let mut items = Vec::new();
+    while items.len() < 10 {
+        if let Some(item) = queue.pop_timeout(Duration::from_secs(5)) {
+            items.push(item);
+            // Give the producer time to fill the queue again.
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+    thread.join().unwrap();
+    assert_eq!(items, (0..10).collect::<Vec<u32>>());
+    let stats = queue.stats();
+    assert_eq!(stats.pushed, 10);
+    assert!(stats.blocked > 0);
+    assert!(stats.max_len <= 2);
+}
+
+#[test]
+fn test_close() {
+    let queue = PacketQueue::new(1, OverflowPolicy::Block);
+    assert!(queue.push(1));
+    let producer = queue.clone();
+    let thread = thread::spawn(move || producer.push(2));
+    thread::sleep(WAIT);
+    queue.close();
+    // The blocked push gives up, and the queue takes nothing new.
+    assert!(!thread.join().unwrap());
+    assert!(!queue.push(3));
+    assert_eq!(queue.pop_timeout(WAIT), Some(1));
+    assert_eq!(queue.pop_timeout(Duration::from_secs(5)), None);
+    let stats = queue.stats();
+    assert_eq!((stats.dropped_after_close, stats.dropped_newest), (2, 0));
+}
+
+#[test]
+fn test_parse_policy() {
+    assert_eq!(
+        OverflowPolicy::parse("drop-oldest"),
+        Some(OverflowPolicy::DropOldest)
+    );
+    assert_eq!(
+        OverflowPolicy::parse("drop-newest"),
+        Some(OverflowPolicy::DropNewest)
+    );
+    assert_eq!(OverflowPolicy::parse("block"), Some(OverflowPolicy::Block));
+    assert_eq!(OverflowPolicy::parse("drop"), None);
+}
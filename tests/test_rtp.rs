@@ -0,0 +1,103 @@
+use std::net::{SocketAddr, UdpSocket};
+
+use drc_sim_rust_lib::{
+    h264::H264Encapsulator,
+    rtp::{sdp, RtpClock, RtpH264Packetizer, RtpSink, H264_PAYLOAD_TYPE},
+};
+
+#[test]
+fn test_clock_conversion() {
+    let mut clock = RtpClock::new(1000);
+    assert_eq!(clock.convert(0xFFFF_FFF0), 1000);
+    // 1/60th of a second later, across the WUP timestamp wrapping
+    assert_eq!(
+        clock.convert(0xFFFF_FFF0u32.wrapping_add(16_667)),
+        1000 + 1500
+    );
+    assert_eq!(
+        clock.convert(0xFFFF_FFF0u32.wrapping_add(1_000_000)),
+        1000 + 90_000
+    );
+    // A late frame goes back in time.
+    assert_eq!(
+        clock.convert(0xFFFF_FFF0u32.wrapping_add(500_000)),
+        1000 + 45_000
+    );
+}
+
+#[test]
+fn test_single_nal_packets() {
+    let mut packetizer = RtpH264Packetizer::new(0x1234_5678, 0xFFFF, 1400);
+    let access_unit = H264Encapsulator::new().encapsulate_payload(true, &[0x88, 0x80]);
+    let packets = packetizer.packetize(&access_unit, 0xAABB_CCDD);
+    // SPS, PPS and the slice
+    assert_eq!(packets.len(), 3);
+    assert_eq!(
+        packets[0][..12],
+        [
+            0x80,
+            H264_PAYLOAD_TYPE,
+            0xFF,
+            0xFF,
+            0xAA,
+            0xBB,
+            0xCC,
+            0xDD,
+            0x12,
+            0x34,
+            0x56,
+            0x78
+        ]
+    );
+    assert_eq!(packets[0][12], 0x67);
+    assert_eq!(packets[1][2..4], [0x00, 0x00]);
+    assert_eq!(packets[1][12], 0x68);
+    assert_eq!(packets[2][1], 0x80 | H264_PAYLOAD_TYPE);
+    assert_eq!(packets[2][12..], [0x25, 0xb8, 0x04, 0xff, 0x88, 0x80]);
+}
+
+#[test]
+fn test_fu_a_packets() {
+    let mut packetizer = RtpH264Packetizer::new(1, 0, 12 + 10);
+    let slice_data: Vec<u8> = (1..=20).collect();
+    let access_unit = H264Encapsulator::new().encapsulate_payload(false, &slice_data);
+    let packets = packetizer.packetize(&access_unit, 0);
+
+    // 23 bytes of NAL payload after the NAL header, 8 per fragment
+    assert_eq!(packets.len(), 3);
+    let mut reassembled = vec![0x21];
+    for (i, packet) in packets.iter().enumerate() {
+        assert_eq!(packet[12], 0x20 | 28);
+        let start = i == 0;
+        let end = i == packets.len() - 1;
+        assert_eq!(packet[13], ((start as u8) << 7) | ((end as u8) << 6) | 1);
+        assert_eq!(packet[1] & 0x80 != 0, end);
+        reassembled.extend_from_slice(&packet[14..]);
+    }
+    assert_eq!(reassembled[..], access_unit[4..]);
+}
+
+#[test]
+fn test_sdp() {
+    let sdp = sdp("127.0.0.1:5004".parse().unwrap());
+    assert!(sdp.contains("m=video 5004 RTP/AVP 96\r\n"));
+    assert!(sdp.contains("c=IN IP4 127.0.0.1\r\n"));
+    assert!(sdp.contains("a=rtpmap:96 H264/90000\r\n"));
+    assert!(
+        sdp.contains("profile-level-id=640020;sprop-parameter-sets=Z2QAIKwrQGwe82g=,aO4GDOg=\r\n")
+    );
+}
+
+#[test]
+fn test_sink_sends() {
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let destination: SocketAddr = receiver.local_addr().unwrap();
+    let mut sink = RtpSink::new(destination).unwrap();
+    let access_unit = H264Encapsulator::new().encapsulate_payload(false, &[0x88]);
+    sink.send(&access_unit, 1234).unwrap();
+
+    let mut buf = [0u8; 1500];
+    let len = receiver.recv(&mut buf).unwrap();
+    assert_eq!(buf[12..len], [0x21, 0xe0, 0x03, 0xff, 0x88]);
+    assert_eq!(buf[1], 0x80 | H264_PAYLOAD_TYPE);
+}
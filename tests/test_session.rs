@@ -0,0 +1,275 @@
+use std::{
+    net::{SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use arbitrary_int::{u1, u10, u3};
+use assert_matches::assert_matches;
+use drc_sim_rust_lib::{
+    feedback::FeedbackEvent,
+    hid::Buttons,
+    incoming_packet_parser::WUPAudioPacket,
+    packet_queue::OverflowPolicy,
+    session::{
+        Channel, Session, SessionConfig, SessionEvent, SessionEventKind, SessionSockets,
+        StallDetector,
+    },
+};
+
+mod video_packets;
+
+use video_packets::VideoPacketBuilder;
+
+fn local() -> UdpSocket {
+    return UdpSocket::bind("127.0.0.1:0").unwrap();
+}
+
+/// Starts a session on loopback sockets, with everything it sends going
+/// to the returned console socket.
+fn start_session() -> (Session, UdpSocket, [SocketAddr; 5]) {
+    return start_session_with(SessionConfig {
+        hid_interval: Duration::from_millis(50),
+        // Tests that look at every event don't want stalls among them.
+        stall_timeout: None,
+        ..SessionConfig::default()
+    });
+}
+
+fn start_session_with(config: SessionConfig) -> (Session, UdpSocket, [SocketAddr; 5]) {
+    let console = local();
+    console
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let console_addr = console.local_addr().unwrap();
+    let sockets = SessionSockets {
+        video: local(),
+        audio: local(),
+        hid: local(),
+        cmd: local(),
+        msg: local(),
+        hid_destination: console_addr,
+        mic_destination: console_addr,
+    };
+    let addrs = [
+        sockets.video.local_addr().unwrap(),
+        sockets.audio.local_addr().unwrap(),
+        sockets.hid.local_addr().unwrap(),
+        sockets.cmd.local_addr().unwrap(),
+        sockets.msg.local_addr().unwrap(),
+    ];
+    let session = Session::start_with_sockets(config, sockets).unwrap();
+    return (session, console, addrs);
+}
+
+fn next_event(session: &Session) -> SessionEvent {
+    return session
+        .events()
+        .recv_timeout(Duration::from_secs(5))
+        .unwrap();
+}
+
+#[test]
+fn test_session_channels() {
+    let (mut session, console, [video, audio, hid, cmd, msg]) = start_session();
+
+    let audio_packet = WUPAudioPacket {
+        format: u3::new(1),
+        channel: u1::new(0),
+        vibrate: true,
+        packet_type: u1::new(0),
+        seq_id: u10::new(3),
+        payload_size: 2,
+        timestamp: 99,
+        payload: vec![1, 2],
+        received_at: None,
+    };
+    console.send_to(&audio_packet.encode(), audio).unwrap();
+    assert_matches!(
+        next_event(&session).kind,
+        SessionEventKind::Feedback(FeedbackEvent::Rumble {
+            on: true,
+            timestamp: 99
+        })
+    );
+    assert_matches!(next_event(&session).kind, SessionEventKind::Audio(packet) if packet.timestamp == 99);
+
+    console.send_to(&[5, 5], hid).unwrap();
+    assert_matches!(
+        next_event(&session).kind,
+        SessionEventKind::Feedback(FeedbackEvent::HidOutput { data }) if data == vec![5, 5]
+    );
+
+    console.send_to(&[9], msg).unwrap();
+    assert_matches!(next_event(&session).kind, SessionEventKind::Message(data) if data == vec![9]);
+
+    let mut packetizer = VideoPacketBuilder::new();
+    for packet in packetizer.packets(&[0x80; 100], 1000) {
+        console.send_to(&packet, video).unwrap();
+    }
+    let event = next_event(&session);
+    assert_matches!(event.kind, SessionEventKind::Frame(frame) if frame.timestamp == 1000);
+
+    // Set the time; the session acks and responds to the sender.
+    let request = [0, 0, 2, 0, 8, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    console.send_to(&request, cmd).unwrap();
+    assert_matches!(next_event(&session).kind, SessionEventKind::Command(_));
+    let mut replies = Vec::new();
+    let mut buf = [0u8; 256];
+    while replies.len() < 2 {
+        let (size, from) = console.recv_from(&mut buf).unwrap();
+        // Input reports arrive here too.
+        if from == cmd {
+            replies.push(buf[..size].to_vec());
+        }
+    }
+    assert_eq!(replies[0], vec![1, 0, 2, 0, 0, 0, 1, 0]);
+    assert_eq!(replies[1], vec![2, 0, 2, 0, 0, 0, 1, 0]);
+
+    let stats = session.stats();
+    assert_eq!(stats.audio_packets, 1);
+    assert_eq!(stats.frames, 1);
+    assert_eq!(stats.commands.requests, 1);
+    assert_eq!(stats.messages.unknown, 1);
+    session.shutdown().unwrap();
+}
+
+#[test]
+fn test_session_sends_input() {
+    let (mut session, console, [_, _, hid, _, _]) = start_session();
+    session.input().update(|report| report.buttons = Buttons::A);
+    let mut buf = [0u8; 256];
+    loop {
+        let (size, from) = console.recv_from(&mut buf).unwrap();
+        assert_eq!(from, hid);
+        assert_eq!(size, 128);
+        if u16::from_be_bytes([buf[2], buf[3]]) == Buttons::A.0 {
+            break;
+        }
+    }
+    assert!(session.stats().hid.reports_sent >= 1);
+    session.shutdown().unwrap();
+}
+
+#[test]
+fn test_stall_detector() {
+    let start = Instant::now();
+    let at = |ms: u64| start + Duration::from_millis(ms);
+    let mut detector = StallDetector::new(Duration::from_millis(500));
+    // Nothing has arrived yet, so nothing has stalled.
+    assert_eq!(detector.check(at(1000)), None);
+
+    assert_eq!(detector.on_packet(at(1000)), None);
+    assert_eq!(detector.check(at(1400)), None);
+    assert_eq!(detector.check(at(1600)), Some(Duration::from_millis(600)));
+    assert!(detector.is_stalled());
+    // A stall is only reported once.
+    assert_eq!(detector.check(at(2000)), None);
+
+    assert_eq!(
+        detector.on_packet(at(2500)),
+        Some(Duration::from_millis(1500))
+    );
+    assert!(!detector.is_stalled());
+    assert_eq!(detector.on_packet(at(2600)), None);
+}
+
+#[test]
+fn test_session_reports_stall_and_resume() {
+    let (mut session, console, [_, audio, _, _, _]) = start_session_with(SessionConfig {
+        read_timeout: Duration::from_millis(10),
+        stall_timeout: Some(Duration::from_millis(100)),
+        ..SessionConfig::default()
+    });
+    let packet = WUPAudioPacket {
+        format: u3::new(1),
+        channel: u1::new(0),
+        vibrate: false,
+        packet_type: u1::new(0),
+        seq_id: u10::new(0),
+        payload_size: 0,
+        timestamp: 0,
+        payload: Vec::new(),
+        received_at: None,
+    };
+    console.send_to(&packet.encode(), audio).unwrap();
+    assert_matches!(next_event(&session).kind, SessionEventKind::Audio(_));
+    assert_matches!(
+        next_event(&session).kind,
+        SessionEventKind::Stalled { channel: Channel::Audio, silent_for } if silent_for >= Duration::from_millis(100)
+    );
+
+    console.send_to(&packet.encode(), audio).unwrap();
+    assert_matches!(
+        next_event(&session).kind,
+        SessionEventKind::Resumed {
+            channel: Channel::Audio,
+            ..
+        }
+    );
+    assert_matches!(next_event(&session).kind, SessionEventKind::Audio(_));
+    assert_eq!(session.stats().stalls, 1);
+    session.shutdown().unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_session_batch_receive() {
+    let (mut session, console, [video, _, _, _, _]) = start_session_with(SessionConfig {
+        batch_size: Some(8),
+        ..SessionConfig::default()
+    });
+    let mut packetizer = VideoPacketBuilder::new();
+    for timestamp in [1000, 2000] {
+        for packet in packetizer.packets(&[0x80; 3000], timestamp) {
+            console.send_to(&packet, video).unwrap();
+        }
+    }
+    assert_matches!(next_event(&session).kind, SessionEventKind::Frame(frame) if frame.timestamp == 1000);
+    assert_matches!(next_event(&session).kind, SessionEventKind::Frame(frame) if frame.timestamp == 2000);
+    let stats = session.stats();
+    assert_eq!(stats.video_packets, 6);
+    assert_eq!((stats.video_queue.pushed, stats.video_queue.popped), (6, 6));
+    session.shutdown().unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_session_receive_timestamps() {
+    let (mut session, console, [video, _, _, _, _]) = start_session_with(SessionConfig {
+        receive_timestamps: true,
+        ..SessionConfig::default()
+    });
+    let mut packetizer = VideoPacketBuilder::new();
+    for packet in packetizer.packets(&[0x80; 3000], 1000) {
+        console.send_to(&packet, video).unwrap();
+    }
+    let frame = match next_event(&session).kind {
+        SessionEventKind::Frame(frame) => frame,
+        other => panic!("expected a frame, got {:?}", other),
+    };
+    assert!(frame.packets.iter().all(|p| p.received_at.is_some()));
+    assert!(frame.first_received_at().unwrap() <= frame.last_received_at().unwrap());
+    session.shutdown().unwrap();
+}
+
+#[test]
+fn test_session_blocking_video_queue() {
+    let (mut session, console, [video, _, _, _, _]) = start_session_with(SessionConfig {
+        video_queue_capacity: 1,
+        video_overflow: OverflowPolicy::Block,
+        ..SessionConfig::default()
+    });
+    let mut packetizer = VideoPacketBuilder::new();
+    for timestamp in [1000, 2000, 3000] {
+        for packet in packetizer.packets(&[0x80; 3000], timestamp) {
+            console.send_to(&packet, video).unwrap();
+        }
+    }
+    for timestamp in [1000, 2000, 3000] {
+        assert_matches!(next_event(&session).kind, SessionEventKind::Frame(frame) if frame.timestamp == timestamp);
+    }
+    let stats = session.stats();
+    assert_eq!(stats.video_queue.pushed, 9);
+    assert_eq!(stats.video_queue.max_len, 1);
+    session.shutdown().unwrap();
+}
@@ -0,0 +1,224 @@
+use std::net::IpAddr;
+
+use drc_sim_rust_lib::sockets::{
+    console_hid_addr, interface_addresses, BindAddress, BindErrorKind, InterfaceAddress, Pad,
+    DEFAULT_CONSOLE_IP, PAD_PORT_OFFSET,
+};
+
+#[test]
+fn test_pad_ports() {
+    assert_eq!(Pad::First.port(50120), 50120);
+    assert_eq!(Pad::Second.port(50120), 50120 + PAD_PORT_OFFSET);
+    assert_eq!(Pad::from_index(1), Some(Pad::Second));
+    assert_eq!(Pad::from_index(2), None);
+    assert_eq!(Pad::Second.to_string(), "pad 1");
+}
+
+#[test]
+fn test_console_hid_addr() {
+    let console = DEFAULT_CONSOLE_IP.parse().unwrap();
+    assert_eq!(
+        console_hid_addr(console, Pad::First),
+        "192.168.1.10:50022".parse().unwrap()
+    );
+    assert_eq!(Pad::Second.console_port(50122), 50122);
+}
+
+#[test]
+fn test_bind_address_parse() {
+    assert_eq!(
+        BindAddress::parse("192.168.1.11"),
+        BindAddress::Ip("192.168.1.11".parse().unwrap())
+    );
+    assert_eq!(
+        BindAddress::parse("wlan1"),
+        BindAddress::Interface(String::from("wlan1"))
+    );
+    assert_eq!(
+        BindAddress::default(),
+        BindAddress::Ip("0.0.0.0".parse().unwrap())
+    );
+}
+
+#[test]
+fn test_bind_address_resolve() {
+    let interface = |name: &str, ip: Option<&str>| InterfaceAddress {
+        name: name.to_string(),
+        ip: ip.map(|ip| ip.parse().unwrap()),
+    };
+    let interfaces = [
+        interface("lo", Some("127.0.0.1")),
+        interface("wlan1", Some("fe80::1")),
+        interface("wlan1", Some("192.168.1.11")),
+        interface("eth0", None),
+    ];
+    let resolve = |text: &str| BindAddress::parse(text).resolve_from(&interfaces);
+
+    assert_eq!(
+        resolve("wlan1").unwrap(),
+        "192.168.1.11".parse::<IpAddr>().unwrap()
+    );
+    assert_eq!(
+        resolve("192.168.1.11").unwrap(),
+        "192.168.1.11".parse::<IpAddr>().unwrap()
+    );
+    assert_eq!(
+        resolve("0.0.0.0").unwrap(),
+        "0.0.0.0".parse::<IpAddr>().unwrap()
+    );
+
+    let err = resolve("eth0").unwrap_err();
+    assert_eq!(err.kind, BindErrorKind::NoIpv4Address);
+    let err = resolve("wlan9").unwrap_err();
+    assert_eq!(err.kind, BindErrorKind::NoSuchInterface);
+    assert!(err.text.contains("eth0, lo, wlan1"), "{}", err.text);
+    let err = resolve("10.0.0.5").unwrap_err();
+    assert_eq!(err.kind, BindErrorKind::AddressNotPresent);
+    assert!(err.text.contains("192.168.1.11"), "{}", err.text);
+}
+
+#[test]
+fn test_interface_addresses() {
+    let interfaces = interface_addresses().unwrap();
+    let loopback: IpAddr = "127.0.0.1".parse().unwrap();
+    assert!(interfaces.iter().any(|i| i.ip == Some(loopback)));
+    assert_eq!(BindAddress::Ip(loopback).resolve().unwrap(), loopback);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_batch_receiver() {
+    use std::{net::UdpSocket, time::Duration};
+
+    use drc_sim_rust_lib::{sockets::BatchReceiver, WUP_VID_PACKET_BUFFER_SIZE};
+
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket
+        .set_read_timeout(Some(Duration::from_millis(100)))
+        .unwrap();
+    let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+    for n in 0..5u8 {
+        sender
+            .send_to(&[n; 3], socket.local_addr().unwrap())
+            .unwrap();
+    }
+    sender
+        .send_to(
+            &[9; WUP_VID_PACKET_BUFFER_SIZE + 10],
+            socket.local_addr().unwrap(),
+        )
+        .unwrap();
+
+    let mut receiver = BatchReceiver::new(4);
+    assert_eq!(receiver.recv(&socket).unwrap(), 4);
+    let packets: Vec<_> = receiver.packets().collect();
+    assert_eq!(packets.len(), 4);
+    assert_eq!(packets[3].data, &[3u8; 3][..]);
+    assert_eq!(packets[3].source, sender.local_addr().unwrap());
+    assert_eq!(packets[3].received_at, None);
+
+    assert_eq!(receiver.recv(&socket).unwrap(), 2);
+    let lengths: Vec<usize> = receiver
+        .packets()
+        .map(|datagram| datagram.data.len())
+        .collect();
+    assert_eq!(lengths, vec![3, WUP_VID_PACKET_BUFFER_SIZE]);
+
+    let err = receiver.recv(&socket).unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+    ));
+    assert_eq!(receiver.packets().count(), 0);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_receive_timestamps() {
+    use std::{
+        net::UdpSocket,
+        time::{Duration, SystemTime},
+    };
+
+    use drc_sim_rust_lib::sockets::{enable_receive_timestamps, recv_datagram, BatchReceiver};
+
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let mut buf = [0u8; 16];
+
+    sender
+        .send_to(&[1, 2], socket.local_addr().unwrap())
+        .unwrap();
+    let datagram = recv_datagram(&socket, &mut buf).unwrap();
+    assert_eq!(datagram.data, &[1u8, 2][..]);
+    assert_eq!(datagram.source, sender.local_addr().unwrap());
+    assert_eq!(datagram.received_at, None);
+    assert_eq!(datagram.kernel_drops, None);
+
+    enable_receive_timestamps(&socket).unwrap();
+    let before = SystemTime::now();
+    sender.send_to(&[3], socket.local_addr().unwrap()).unwrap();
+    let received_at = recv_datagram(&socket, &mut buf)
+        .unwrap()
+        .received_at
+        .unwrap();
+    // The kernel's clock and ours can disagree by a little.
+    let slack = Duration::from_millis(100);
+    assert!(received_at + slack >= before);
+    assert!(received_at <= SystemTime::now() + slack);
+
+    for n in 0..3u8 {
+        sender.send_to(&[n], socket.local_addr().unwrap()).unwrap();
+    }
+    let mut receiver = BatchReceiver::new(4);
+    assert_eq!(receiver.recv(&socket).unwrap(), 3);
+    assert!(receiver
+        .packets()
+        .all(|datagram| datagram.received_at.is_some()));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_receive_buffer_and_drop_counter() {
+    use std::net::UdpSocket;
+
+    use drc_sim_rust_lib::sockets::{
+        enable_drop_counter, receive_buffer_size, recv_datagram, set_receive_buffer_size,
+        BatchReceiver,
+    };
+
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let address = socket.local_addr().unwrap();
+
+    // Linux doubles what we ask for, and has a floor well above this.
+    let size = set_receive_buffer_size(&socket, 1).unwrap();
+    assert!(size > 1);
+    assert_eq!(receive_buffer_size(&socket).unwrap(), size);
+    let bigger = set_receive_buffer_size(&socket, size * 2).unwrap();
+    assert!(bigger > size);
+    set_receive_buffer_size(&socket, 1).unwrap();
+
+    enable_drop_counter(&socket).unwrap();
+    let mut buf = [0u8; 2048];
+    sender.send_to(&[1], address).unwrap();
+    assert_eq!(recv_datagram(&socket, &mut buf).unwrap().kernel_drops, None);
+
+    // Far more than the smallest buffer holds.
+    for _ in 0..200 {
+        sender.send_to(&[0; 1400], address).unwrap();
+    }
+    socket.set_nonblocking(true).unwrap();
+    let mut receiver = BatchReceiver::new(256);
+    while receiver.recv(&socket).is_ok() {}
+
+    // Each datagram carries the count from when it was queued, so one
+    // sent after the flood has them all.
+    socket.set_nonblocking(false).unwrap();
+    sender.send_to(&[2], address).unwrap();
+    receiver.recv(&socket).unwrap();
+    let datagram = receiver.packets().next().unwrap();
+    assert_eq!(datagram.data, [2]);
+    let drops = datagram.kernel_drops.unwrap();
+    assert!(drops > 0 && drops < 200);
+}
@@ -0,0 +1,69 @@
+use drc_sim_rust_lib::hid::{GamePadInputReport, TouchPoint, TOUCH_PRESSURE_MAX};
+use drc_sim_rust_lib::touch::{PixelTouch, TouchScreen};
+use proptest::prelude::*;
+
+#[test]
+fn test_corners() {
+    let screen = TouchScreen::default();
+    assert_eq!(
+        screen.to_raw(0.0, 0.0),
+        Some(TouchPoint { x: 100, y: 3900 })
+    );
+    assert_eq!(
+        screen.to_raw(853.0, 479.0),
+        Some(TouchPoint { x: 3900, y: 200 })
+    );
+    assert_eq!(screen.to_raw(854.0, 0.0), None);
+    assert_eq!(screen.to_raw(-1.0, 0.0), None);
+    assert_eq!(screen.to_raw(f32::NAN, 0.0), None);
+}
+
+#[test]
+fn test_window_to_frame() {
+    let screen = TouchScreen::default();
+    // Twice the frame's size: no bars.
+    assert_eq!(
+        screen.window_to_frame(854.0, 480.0, 1708.0, 960.0),
+        Some((427.0, 240.0))
+    );
+    // Much taller than the frame: bars above and below.
+    let (x, y) = screen.window_to_frame(427.0, 300.0, 854.0, 880.0).unwrap();
+    assert_eq!((x, y), (427.0, 100.0));
+    assert_eq!(screen.window_to_frame(427.0, 100.0, 854.0, 880.0), None);
+}
+
+#[test]
+fn test_touch_spreads_samples() {
+    let screen = TouchScreen::default();
+    let mut report = GamePadInputReport::new();
+    screen.touch(
+        &mut report,
+        &[
+            PixelTouch::new(0.0, 0.0, 0.5),
+            PixelTouch::new(853.0, 479.0, 1.0),
+            PixelTouch::new(1000.0, 0.0, 0.0),
+        ],
+    );
+    let first = screen.to_raw(0.0, 0.0);
+    let second = screen.to_raw(853.0, 479.0);
+    assert!(report.touch[..5].iter().all(|sample| *sample == first));
+    assert!(report.touch[5..].iter().all(|sample| *sample == second));
+    assert_eq!(
+        report.touch_pressure,
+        (0.75 * TOUCH_PRESSURE_MAX as f32).round() as u16
+    );
+
+    screen.release(&mut report);
+    assert!(!report.is_touched());
+    assert_eq!(report.touch_pressure, 0);
+}
+
+proptest! {
+    #[test]
+    fn test_raw_in_calibrated_range(x in 0.0f32..854.0, y in 0.0f32..480.0) {
+        let screen = TouchScreen::default();
+        let point = screen.to_raw(x, y).unwrap();
+        prop_assert!((100..=3900).contains(&point.x));
+        prop_assert!((200..=3900).contains(&point.y));
+    }
+}
@@ -0,0 +1,75 @@
+use drc_sim_rust_lib::video_stats::VideoStats;
+
+#[test]
+fn test_steady_stream() {
+    let mut stats = VideoStats::new(1_000_000);
+    let mut timestamp = 0xFFFF_0000u32;
+    for i in 0..120 {
+        let frame = stats.record(timestamp, 1000, 2, i % 30 == 0);
+        if i == 0 {
+            assert_eq!(frame.interval_us, None);
+            assert_eq!(frame.instantaneous_bitrate, None);
+        } else {
+            assert_eq!(frame.interval_us, Some(16_000));
+            assert_eq!(frame.instantaneous_bitrate, Some(500_000.0));
+        }
+        timestamp = timestamp.wrapping_add(16_000);
+    }
+    let summary = stats.summary();
+    assert_eq!(summary.frames, 120);
+    assert_eq!(summary.idr_frames, 4);
+    assert_eq!(summary.total_bytes, 120_000);
+    assert_eq!(summary.total_packets, 240);
+    assert_eq!(summary.mean_frame_bytes, 1000.0);
+    assert_eq!(summary.mean_packets_per_frame, 2.0);
+    assert_eq!(summary.mean_idr_interval_frames, Some(30.0));
+    assert_eq!(summary.mean_idr_interval_us, Some(480_000.0));
+    assert_eq!(summary.mean_interval_us, Some(16_000.0));
+    assert_eq!(summary.jitter_us, 0.0);
+    assert_eq!(summary.mean_bitrate, Some(500_000.0));
+    assert_eq!(summary.rolling_bitrate, Some(500_000.0));
+}
+
+#[test]
+fn test_jitter_and_rolling_window() {
+    let mut stats = VideoStats::new(100_000);
+    let mut timestamp = 0u32;
+    for i in 0..100 {
+        let bytes = if i < 50 { 1000 } else { 2000 };
+        stats.record(timestamp, bytes, 1, false);
+        timestamp += if i % 2 == 0 { 15_000 } else { 17_000 };
+    }
+    let summary = stats.summary();
+    assert_eq!(summary.min_interval_us, Some(15_000));
+    assert_eq!(summary.max_interval_us, Some(17_000));
+    assert!(summary.jitter_us > 1900.0 && summary.jitter_us <= 2000.0);
+    // Only the larger frames are within the window by the end.
+    assert_eq!(summary.rolling_bitrate, Some(1_000_000.0));
+    assert_eq!(summary.mean_idr_interval_frames, None);
+}
+
+#[test]
+fn test_late_frame_has_no_interval() {
+    let mut stats = VideoStats::default();
+    stats.record(50_000, 100, 1, false);
+    let late = stats.record(30_000, 100, 1, false);
+    assert_eq!(late.interval_us, None);
+    let next = stats.record(66_000, 100, 1, false);
+    assert_eq!(next.interval_us, Some(16_000));
+    assert_eq!(stats.summary().mean_interval_us, Some(16_000.0));
+}
+
+#[test]
+fn test_late_frame_keeps_rolling_window() {
+    let mut stats = VideoStats::new(100_000);
+    for i in 0..10 {
+        stats.record(50_000 + i * 10_000, 1000, 1, false);
+    }
+    let before = stats.rolling_bitrate();
+    assert_eq!(before, Some(800_000.0));
+    // Older than everything in the window.
+    let late = stats.record(30_000, 1000, 1, false);
+    assert_eq!(late.rolling_bitrate, before);
+    let next = stats.record(150_000, 1000, 1, false);
+    assert_eq!(next.rolling_bitrate, Some(800_000.0));
+}
@@ -0,0 +1,42 @@
+/// The most payload one built packet carries.
+pub const VIDEO_PACKET_PAYLOAD: usize = 1400;
+
+/// Splits frames into encoded WUP video packets, in the layout
+/// process_video_packet reads, for feeding receivers in tests and
+/// benches. The sequence ID carries on from one frame to the next.
+pub struct VideoPacketBuilder {
+    seq_id: u16,
+}
+
+impl VideoPacketBuilder {
+    pub fn new() -> VideoPacketBuilder {
+        return VideoPacketBuilder { seq_id: 0 };
+    }
+
+    pub fn packets(&mut self, frame: &[u8], timestamp: u32) -> Vec<Vec<u8>> {
+        let chunks: Vec<&[u8]> = frame.chunks(VIDEO_PACKET_PAYLOAD).collect();
+        let last = chunks.len().saturating_sub(1);
+        let mut packets = Vec::with_capacity(chunks.len());
+        for (index, chunk) in chunks.iter().enumerate() {
+            // magic 15, packet_type 0
+            let first: u16 = (0xF << 12) | self.seq_id;
+            let mut second: u16 = 1 << 11; // has_timestamp
+            if index == 0 {
+                second |= 1 << 14; // frame_begin
+            }
+            if index == last {
+                second |= (1 << 13) | (1 << 12); // chunk_end and frame_end
+            }
+            second |= chunk.len() as u16;
+            let mut packet = Vec::with_capacity(16 + chunk.len());
+            packet.extend(first.to_be_bytes());
+            packet.extend(second.to_be_bytes());
+            packet.extend(timestamp.to_be_bytes());
+            packet.extend([0; 8]); // extended_header
+            packet.extend(*chunk);
+            packets.push(packet);
+            self.seq_id = (self.seq_id + 1) % 1024;
+        }
+        return packets;
+    }
+}